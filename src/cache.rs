@@ -26,6 +26,175 @@ impl MimeMap {
     }
 }
 
+/// Keeps track of the titles of fetched podcasts, keyed by their hashed feed url.
+pub struct TitleMap;
+
+impl TitleMap {
+    pub fn get_title(url: &str) -> Option<String> {
+        let hashed = hashed_url(url);
+        let file = fs::read_to_string(Self::path()).ok()?;
+
+        // A title is appended whenever it changes, so the last entry is the current one.
+        file.lines()
+            .rev()
+            .filter_map(utils::parse_quoted_words)
+            .find(|(key, _)| key == &hashed)
+            .map(|(_, title)| title)
+    }
+
+    pub fn update(url: &str, title: &str) -> Option<()> {
+        let title = title.replace('\n', " ");
+        let title = title.trim();
+
+        if title.is_empty() || Self::get_title(url).as_deref() == Some(title) {
+            return Some(());
+        }
+
+        let hashed = hashed_url(url);
+        utils::append_to_config(&Self::path(), &hashed, title).ok()?;
+        Some(())
+    }
+
+    fn path() -> PathBuf {
+        utils::cache_dir().join("podcast_titles")
+    }
+}
+
+/// Remembers which podcasts the funding links have been shown for, so they're only shown once.
+pub struct FundingNotice;
+
+impl FundingNotice {
+    pub fn is_shown(url: &str) -> bool {
+        utils::get_file_map_val(&Self::path(), &hashed_url(url)).is_some()
+    }
+
+    pub fn mark_shown(url: &str) -> Option<()> {
+        utils::append_to_config(&Self::path(), &hashed_url(url), "shown").ok()
+    }
+
+    fn path() -> PathBuf {
+        utils::cache_dir().join("funding_shown")
+    }
+}
+
+/// Remembers when each podcast was first synced, keyed by the hashed feed url, so that standard
+/// mode doesn't download the episodes published before.
+pub struct Subscriptions;
+
+impl Subscriptions {
+    pub fn get(url: &str) -> Option<utils::Unix> {
+        let secs = utils::get_file_map_val(&Self::path(), &hashed_url(url))?;
+        secs.parse().ok().map(utils::Unix::from_secs)
+    }
+
+    pub fn save(url: &str, subscribed_at: utils::Unix) -> Option<()> {
+        let secs = subscribed_at.as_secs().to_string();
+        utils::append_to_config(&Self::path(), &hashed_url(url), &secs).ok()
+    }
+
+    fn path() -> PathBuf {
+        utils::cache_dir().join("subscriptions")
+    }
+}
+
+/// Remembers when each podcast was last synced without errors, keyed by the hashed feed url.
+pub struct LastSync;
+
+impl LastSync {
+    pub fn get(url: &str) -> Option<utils::Unix> {
+        let secs = utils::get_file_map_val(&Self::path(), &hashed_url(url))?;
+        secs.parse().ok().map(utils::Unix::from_secs)
+    }
+
+    /// Replaces the previous time, as the podcasts are synced far too often to keep appending.
+    pub fn save(url: &str, synced_at: utils::Unix) -> Option<()> {
+        // Podcasts are synced concurrently, and each of them rewrites the whole file.
+        static WRITE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let hashed = hashed_url(url);
+        let existing = fs::read_to_string(Self::path()).unwrap_or_default();
+
+        let mut s: String = existing
+            .lines()
+            .filter(|line| line.split_whitespace().next() != Some(hashed.as_str()))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        s.push_str(&format!("{} {}\n", hashed, synced_at.as_secs()));
+
+        fs::write(Self::path(), s).ok()
+    }
+
+    fn path() -> PathBuf {
+        utils::cache_dir().join("last_sync")
+    }
+}
+
+/// Caches feeds along with their `ETag` and `Last-Modified` headers, so that unchanged feeds
+/// don't have to be downloaded again.
+pub struct FeedCache;
+
+impl FeedCache {
+    const ETAG: &'static str = "etag";
+    const LAST_MODIFIED: &'static str = "last-modified";
+
+    /// The cached feed and the headers it was served with.
+    pub fn get(url: &str) -> Option<CachedFeed> {
+        let (xml_path, headers_path) = Self::paths(url);
+        let xml = fs::read_to_string(&xml_path).ok()?;
+
+        Some(CachedFeed {
+            xml,
+            etag: utils::get_file_map_val(&headers_path, Self::ETAG),
+            last_modified: utils::get_file_map_val(&headers_path, Self::LAST_MODIFIED),
+            fetched_at: fs::metadata(&xml_path).and_then(|m| m.modified()).ok(),
+        })
+    }
+
+    /// The cached feed if it was fetched less than `ttl` ago.
+    pub fn fresh(url: &str, ttl: std::time::Duration) -> Option<CachedFeed> {
+        let feed = Self::get(url)?;
+        let age = feed.fetched_at?.elapsed().ok()?;
+        (age < ttl).then_some(feed)
+    }
+
+    /// Saves the feed, which also marks it as fetched now.
+    pub fn save(url: &str, feed: &CachedFeed) -> Option<()> {
+        let (xml_path, headers_path) = Self::paths(url);
+        let _ = fs::remove_file(&headers_path);
+
+        fs::write(&xml_path, &feed.xml).ok()?;
+
+        if let Some(etag) = &feed.etag {
+            utils::append_to_config(&headers_path, Self::ETAG, etag).ok()?;
+        }
+
+        if let Some(last_modified) = &feed.last_modified {
+            utils::append_to_config(&headers_path, Self::LAST_MODIFIED, last_modified).ok()?;
+        }
+
+        Some(())
+    }
+
+    fn paths(url: &str) -> (PathBuf, PathBuf) {
+        let dir = utils::cache_dir().join("feeds");
+        utils::create_dir(&dir);
+        let hashed = hashed_url(url);
+        (
+            dir.join(format!("{}.xml", hashed)),
+            dir.join(format!("{}.headers", hashed)),
+        )
+    }
+}
+
+pub struct CachedFeed {
+    pub xml: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When the feed was last downloaded or confirmed to be unchanged.
+    pub fetched_at: Option<std::time::SystemTime>,
+}
+
 fn read_file_to_vec(path: &Path) -> io::Result<Vec<u8>> {
     let mut file = fs::File::open(path)?;
     let mut data = Vec::new();
@@ -55,11 +224,11 @@ fn cached_image(url: &str, ui: &DownloadBar) -> Option<Vec<u8>> {
     image
 }
 
-async fn write_image(url: &str, ui: &DownloadBar) -> Option<()> {
+async fn write_image(client: &reqwest::Client, url: &str, ui: &DownloadBar) -> Option<()> {
     use std::io::Write;
 
     let hashed = hashed_url(url);
-    let response = match reqwest::get(url).await {
+    let response = match client.get(url).send().await {
         Ok(res) => {
             ui.log_info("connected to image url");
             res
@@ -89,15 +258,16 @@ async fn write_image(url: &str, ui: &DownloadBar) -> Option<()> {
     Some(())
 }
 
-pub async fn get_image(
+/// Loads the image and its mime type, downloading it if it's not cached yet.
+pub async fn get_image_data(
+    client: &reqwest::Client,
     url: &str,
-    picture_type: id3::frame::PictureType,
     ui: &DownloadBar,
-) -> Option<id3::frame::Frame> {
+) -> Option<(Vec<u8>, String)> {
     let data = match cached_image(url, ui) {
         Some(data) => data,
         None => {
-            write_image(url, ui).await?;
+            write_image(client, url, ui).await?;
             cached_image(url, ui)?
         }
     };
@@ -110,6 +280,17 @@ pub async fn get_image(
         }
     };
 
+    Some((data, mime_type))
+}
+
+pub async fn get_image(
+    client: &reqwest::Client,
+    url: &str,
+    picture_type: id3::frame::PictureType,
+    ui: &DownloadBar,
+) -> Option<id3::frame::Frame> {
+    let (data, mime_type) = get_image_data(client, url, ui).await?;
+
     let pic = id3::frame::Picture {
         data,
         mime_type,
@@ -1,9 +1,16 @@
 use crate::display::DownloadBar;
+use crate::display::PlainProgress;
 use crate::episode;
+use crate::error::CringeError;
+use crate::observer::SyncObserver;
 use crate::patterns::Evaluate;
 use crate::patterns::FullPattern;
+use crate::podcast::LibraryStats;
+use crate::podcast::ListOrder;
 use crate::podcast::Podcast;
 use crate::podcast::RawPodcast;
+use crate::podcast::SyncReport;
+use crate::tui;
 use crate::utils;
 use crate::utils::Unix;
 use futures::future;
@@ -17,10 +24,10 @@ use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time;
+use tokio::sync::oneshot;
+use tokio::sync::Semaphore;
 
 /// Represents a [`PodcastConfig`] value that is either enabled, disabled,
 /// or deferring to the global config. Only valid for optional values.
@@ -120,6 +127,63 @@ impl<'a> EvalData<'a> {
     }
 }
 
+/// Audio format that downloaded episodes are converted to with ffmpeg.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscodeFormat {
+    Mp3,
+    Opus,
+    M4a,
+}
+
+impl TranscodeFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Opus => "opus",
+            Self::M4a => "m4a",
+        }
+    }
+
+    /// The ffmpeg arguments for encoding the audio stream.
+    pub fn codec_args(&self) -> &'static [&'static str] {
+        match self {
+            Self::Mp3 => &["-c:a", "libmp3lame", "-q:a", "2"],
+            Self::Opus => &["-c:a", "libopus", "-b:a", "64k"],
+            Self::M4a => &["-c:a", "aac", "-b:a", "128k"],
+        }
+    }
+}
+
+/// How downloaded files are hashed to find the ones that are already on disk.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupeMode {
+    /// The size and the start and end of the file.
+    Quick,
+    /// All of the file.
+    Full,
+}
+
+/// What happens to the rest of the episodes when one of them fails to download.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnError {
+    /// No further episodes are downloaded.
+    #[default]
+    Stop,
+    /// The episode is skipped until the next sync.
+    Skip,
+    /// The episode is tried once more before it's skipped.
+    RetryThenSkip,
+}
+
+impl OnError {
+    pub fn skips(self) -> bool {
+        self != Self::Stop
+    }
+}
+
 /// Full configuration for a specific podcast-episode.
 ///
 /// Combines settings from [`GlobalConfig`] and [`PodcastConfig`].
@@ -127,7 +191,14 @@ impl<'a> EvalData<'a> {
 #[derive(Debug, Clone, Default)]
 pub struct Config {
     pub url: String,
+    pub podcast_name: String,
     pub name_pattern: String,
+    /// Path of the episode relative to the download path, without the extension.
+    pub filename_template: Option<PathBuf>,
+    /// In bytes, including the extension.
+    pub max_filename_length: usize,
+    /// Transliterate file names to ASCII.
+    pub ascii_filenames: bool,
     pub id_pattern: String,
     pub download_path: PathBuf,
     pub partial_path: Option<PathBuf>,
@@ -135,6 +206,34 @@ pub struct Config {
     pub symlink: Option<PathBuf>,
     pub id3_tags: HashMap<String, String>,
     pub download_hook: Option<PathBuf>,
+    pub download_transcripts: bool,
+    pub embed_artwork: bool,
+    pub transcode_to: Option<TranscodeFormat>,
+    pub keep_original: bool,
+    /// Target loudness in LUFS, if loudness normalization is enabled.
+    pub normalize_loudness: Option<f64>,
+    pub write_metadata_sidecar: bool,
+    pub save_cover: bool,
+    pub set_mtime_from_pubdate: bool,
+    pub verify_length: bool,
+    /// Learn the size of enclosures with a HEAD request before downloading them.
+    pub probe_size: bool,
+    /// Download enclosures in ranges of this many bytes, if `chunked_download` is enabled.
+    pub chunk_size: Option<u64>,
+    pub skip_if_file_exists: bool,
+    pub preferred_format: Option<String>,
+    /// In kbps.
+    pub preferred_bitrate: Option<u64>,
+    pub embed_chapters: bool,
+    pub extract_soundbites: bool,
+    /// Path to the SQLite index of downloaded episodes, if it's enabled.
+    pub episode_index: Option<PathBuf>,
+    /// Only set along with the episode index, which the hashes are kept in.
+    pub dedupe: Option<DedupeMode>,
+    pub retry: RetryPolicy,
+    pub request: RequestSettings,
+    /// Shared between all downloads, `None` when downloads aren't throttled.
+    pub rate_limiter: Option<Arc<utils::RateLimiter>>,
 }
 
 impl Config {
@@ -144,6 +243,7 @@ impl Config {
         data: EvalData<'_>,
     ) -> Self {
         let podcast_config = podcast_config.to_owned();
+        let request = podcast_config.request_settings();
         let id3_tags = {
             let mut map = HashMap::with_capacity(
                 global_config.id3_tags.len() + podcast_config.id3_tags.len(),
@@ -167,7 +267,7 @@ impl Config {
             .download_path
             .unwrap_or_else(|| global_config.download_path.clone());
 
-        let download_path = FullPattern::direct_eval_dir(&download_path_str, data);
+        let download_path = FullPattern::direct_eval_path(&download_path_str, data);
 
         let tracker_path = match podcast_config
             .tracker_path
@@ -183,7 +283,7 @@ impl Config {
             }
         };
 
-        let tracker_path = FullPattern::direct_eval_file(&tracker_path, data);
+        let tracker_path = FullPattern::direct_eval_path(&tracker_path, data);
 
         let name_pattern = FullPattern::from_str(
             &podcast_config
@@ -192,6 +292,29 @@ impl Config {
         )
         .evaluate(data);
 
+        // The extension is always added after downloading, so an explicit one is redundant.
+        let filename_template = podcast_config
+            .filename_template
+            .or(global_config.filename_template.clone())
+            .map(|template| {
+                let template = template.strip_suffix(".{ext}").unwrap_or(&template);
+                FullPattern::from_str(template).evaluate_path(data)
+            });
+
+        let max_filename_length = podcast_config
+            .max_filename_length
+            .or(global_config.max_filename_length)
+            .unwrap_or(255);
+        if max_filename_length < 16 {
+            eprintln!("max_filename_length must be at least 16 bytes");
+            process::exit(1);
+        }
+
+        let ascii_filenames = podcast_config
+            .ascii_filenames
+            .or(global_config.ascii_filenames)
+            .unwrap_or(false);
+
         let id_pattern = podcast_config
             .id_pattern
             .unwrap_or_else(|| global_config.id_pattern.clone());
@@ -201,16 +324,124 @@ impl Config {
         let symlink = podcast_config
             .symlink
             .or(global_config.symlink.clone())
-            .map(|str| FullPattern::direct_eval_dir(str.as_ref(), data));
+            .map(|str| FullPattern::direct_eval_path(str.as_ref(), data));
+
+        let download_transcripts = podcast_config
+            .download_transcripts
+            .or(global_config.download_transcripts)
+            .unwrap_or(false);
+
+        let embed_artwork = podcast_config
+            .embed_artwork
+            .or(global_config.embed_artwork)
+            .unwrap_or(true);
+
+        let transcode_to = podcast_config
+            .transcode_to
+            .into_val(global_config.transcode_to.as_ref());
+
+        let keep_original = podcast_config
+            .keep_original
+            .or(global_config.keep_original)
+            .unwrap_or(false);
+
+        let normalize_loudness = podcast_config
+            .normalize_loudness
+            .or(global_config.normalize_loudness)
+            .unwrap_or(false)
+            .then(|| {
+                podcast_config
+                    .target_lufs
+                    .or(global_config.target_lufs)
+                    .unwrap_or(-16.)
+            });
+
+        let skip_if_file_exists = podcast_config
+            .skip_if_file_exists
+            .or(global_config.skip_if_file_exists)
+            .unwrap_or(false);
+
+        let preferred_format = podcast_config
+            .preferred_format
+            .or(global_config.preferred_format.clone());
+
+        let preferred_bitrate = podcast_config
+            .preferred_bitrate
+            .or(global_config.preferred_bitrate);
+
+        let write_metadata_sidecar = podcast_config
+            .write_metadata_sidecar
+            .or(global_config.write_metadata_sidecar)
+            .unwrap_or(false);
+
+        let save_cover = podcast_config
+            .save_cover
+            .or(global_config.save_cover)
+            .unwrap_or(false);
+
+        let set_mtime_from_pubdate = podcast_config
+            .set_mtime_from_pubdate
+            .or(global_config.set_mtime_from_pubdate)
+            .unwrap_or(false);
+
+        let extract_soundbites = podcast_config
+            .extract_soundbites
+            .or(global_config.extract_soundbites)
+            .unwrap_or(false);
+
+        let embed_chapters = podcast_config
+            .embed_chapters
+            .or(global_config.embed_chapters)
+            .unwrap_or(false);
+
+        let verify_length = podcast_config
+            .verify_length
+            .or(global_config.verify_length)
+            .unwrap_or(false);
+
+        let probe_size = podcast_config
+            .probe_size
+            .or(global_config.probe_size)
+            .unwrap_or(true);
+
+        let chunked_download = podcast_config
+            .chunked_download
+            .or(global_config.chunked_download)
+            .unwrap_or(false);
+
+        let chunk_size = chunked_download.then(|| {
+            let size = podcast_config
+                .chunk_size
+                .as_deref()
+                .or(global_config.chunk_size.as_deref())
+                .unwrap_or("8MB");
+            // Tiny chunks would take a request for every few bytes.
+            const MIN_CHUNK_SIZE: u64 = 64 << 10;
+            match utils::str_to_bytes(size) {
+                Ok(bytes) if bytes >= MIN_CHUNK_SIZE => bytes,
+                Ok(_) => {
+                    eprintln!("invalid chunk_size: {} is less than 64KB", size);
+                    process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("invalid chunk_size: {}", e);
+                    process::exit(1);
+                }
+            }
+        });
 
         let partial_path = podcast_config
             .partial_path
             .or(global_config.partial_path.clone())
-            .map(|str| FullPattern::direct_eval_dir(str.as_ref(), data));
+            .map(|str| FullPattern::direct_eval_path(str.as_ref(), data));
 
         Config {
             url: podcast_config.url.clone(),
+            podcast_name: data.pod_name.to_string(),
             name_pattern,
+            filename_template,
+            max_filename_length,
+            ascii_filenames,
             id_pattern,
             download_path,
             partial_path,
@@ -218,12 +449,39 @@ impl Config {
             symlink,
             id3_tags: id3_tags.clone(),
             download_hook: download_hook.clone(),
+            download_transcripts,
+            embed_artwork,
+            transcode_to,
+            keep_original,
+            normalize_loudness,
+            write_metadata_sidecar,
+            save_cover,
+            set_mtime_from_pubdate,
+            verify_length,
+            probe_size,
+            chunk_size,
+            skip_if_file_exists,
+            preferred_format,
+            preferred_bitrate,
+            embed_chapters,
+            extract_soundbites,
+            episode_index: global_config.episode_index(),
+            dedupe: global_config.dedupe(),
+            retry: RetryPolicy::new(global_config),
+            request,
+            rate_limiter: None,
         }
     }
 }
 
+/// Identifies the program, since some hosts block generic or empty user agents.
 fn default_user_agent() -> String {
-    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.36".to_string()
+    format!(
+        "{}/{} (+{})",
+        crate::APPNAME,
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_REPOSITORY")
+    )
 }
 
 #[derive(Serialize, Default, Deserialize, Debug, PartialEq, Clone)]
@@ -257,6 +515,7 @@ pub struct IndicatifSettings {
     pub hooks: Option<String>,
     pub spinner_speed: Option<u64>,
     pub title_length: Option<usize>,
+    pub plain: Option<bool>,
 }
 
 impl IndicatifSettings {
@@ -288,6 +547,14 @@ impl IndicatifSettings {
         Self::default_podcast_fetch_template()
     }
 
+    pub fn waiting_template() -> String {
+        "{spinner:.green}  {msg}waiting...".to_string()
+    }
+
+    pub fn concurrent_status_template() -> String {
+        "{spinner:.green}  {msg}".to_string()
+    }
+
     pub fn download_template(&self) -> String {
         self.download_bar
             .clone()
@@ -322,6 +589,32 @@ impl IndicatifSettings {
     pub fn enabled(&self) -> bool {
         self.enabled.unwrap_or(true)
     }
+
+    /// Checks that the configured templates can be used by indicatif.
+    pub fn check_templates(&self) -> Result<(), String> {
+        for (key, template) in [
+            ("download_bar", &self.download_bar),
+            ("completed", &self.completed),
+            ("hooks", &self.hooks),
+        ] {
+            if let Some(template) = template {
+                indicatif::ProgressStyle::default_bar()
+                    .template(template)
+                    .map_err(|e| format!("invalid {} template: {}", key, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the progress is printed as lines of status rather than redrawn bars, which by
+    /// default is the case when stderr isn't a terminal, e.g. under systemd or in CI.
+    pub fn plain(&self) -> bool {
+        use std::io::IsTerminal;
+        self.enabled()
+            && self
+                .plain
+                .unwrap_or_else(|| !std::io::stderr().is_terminal())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -332,21 +625,76 @@ pub struct GlobalConfig {
     partial_path: Option<String>,
     #[serde(default = "default_name_pattern")]
     name_pattern: String,
+    filename_template: Option<String>,
+    max_filename_length: Option<usize>,
+    ascii_filenames: Option<bool>,
     #[serde(default = "default_id_pattern")]
     id_pattern: String,
     max_days: Option<i64>,
     max_episodes: Option<i64>,
     earliest_date: Option<String>,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    id3_tags: HashMap<String, String>,
     download_hook: Option<PathBuf>,
+    post_sync_hook: Option<PathBuf>,
+    metrics_path: Option<PathBuf>,
     tracker_path: Option<String>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    symlink: Option<String>,
+    download_transcripts: Option<bool>,
+    embed_artwork: Option<bool>,
+    transcode_to: Option<TranscodeFormat>,
+    keep_original: Option<bool>,
+    normalize_loudness: Option<bool>,
+    target_lufs: Option<f64>,
+    write_metadata_sidecar: Option<bool>,
+    save_cover: Option<bool>,
+    set_mtime_from_pubdate: Option<bool>,
+    verify_length: Option<bool>,
+    probe_size: Option<bool>,
+    chunked_download: Option<bool>,
+    chunk_size: Option<String>,
+    skip_if_file_exists: Option<bool>,
+    preferred_format: Option<String>,
+    preferred_bitrate: Option<u64>,
+    all_enclosures: Option<bool>,
+    embed_chapters: Option<bool>,
+    extract_soundbites: Option<bool>,
+    generate_playlist: Option<bool>,
+    latest_symlink: Option<bool>,
+    sort_by_episode_number: Option<bool>,
+    on_error: Option<OnError>,
+    max_feed_pages: Option<usize>,
+    max_retries: Option<u32>,
+    retry_base_delay_ms: Option<u64>,
+    max_retry_after_secs: Option<u64>,
+    concurrent_downloads: Option<usize>,
+    processing_concurrency: Option<usize>,
+    max_concurrent_podcasts: Option<usize>,
+    max_downloads_per_run: Option<usize>,
+    max_download_rate: Option<String>,
+    feed_cache_ttl: Option<String>,
+    notify: Option<bool>,
+    episode_index: Option<bool>,
+    episode_index_path: Option<PathBuf>,
+    dedupe: Option<DedupeMode>,
+    connect_timeout_secs: Option<u64>,
+    request_timeout_secs: Option<u64>,
+    max_redirects: Option<usize>,
+    max_age: Option<String>,
+    keep_recent: Option<usize>,
+    ignore_block: Option<bool>,
+    enabled: Option<bool>,
+    auto_update_feed_url: Option<bool>,
+    follow_new_feed_url: Option<bool>,
+    tag_title_template: Option<String>,
+    tag_album_template: Option<String>,
+    // Tables have to come after the plain values, otherwise toml fails to serialize it.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    id3_tags: HashMap<String, String>,
     #[serde(default, skip_serializing_if = "IndicatifSettings::is_default")]
     style: Arc<IndicatifSettings>,
-    user_agent: Option<String>,
     #[serde(default, skip_serializing_if = "SearchSettings::is_default")]
     search: SearchSettings,
-    symlink: Option<String>,
     #[serde(default, skip_serializing_if = "LogConfig::is_default")]
     log: Arc<LogConfig>,
 }
@@ -417,6 +765,11 @@ impl GlobalConfig {
         Arc::clone(&self.style)
     }
 
+    /// Prints the progress as lines of status, as with `--plain`.
+    pub fn set_plain_progress(&mut self) {
+        Arc::make_mut(&mut self.style).plain = Some(true);
+    }
+
     pub fn log(&self) -> Arc<LogConfig> {
         Arc::clone(&self.log)
     }
@@ -433,6 +786,20 @@ impl GlobalConfig {
         self.user_agent.clone().unwrap_or_else(default_user_agent)
     }
 
+    pub fn connect_timeout(&self) -> time::Duration {
+        time::Duration::from_secs(self.connect_timeout_secs.unwrap_or(30))
+    }
+
+    /// No timeout by default, since large episodes can take a long time to download.
+    pub fn request_timeout(&self) -> Option<time::Duration> {
+        self.request_timeout_secs.map(time::Duration::from_secs)
+    }
+
+    /// Enclosures are often behind tracking redirectors, which can chain a few hops.
+    pub fn max_redirects(&self) -> usize {
+        self.max_redirects.unwrap_or(10)
+    }
+
     pub fn search_settings(&self) -> &SearchSettings {
         &self.search
     }
@@ -445,6 +812,63 @@ impl GlobalConfig {
         self.search.max_results.unwrap_or(9)
     }
 
+    /// How many episodes of a podcast are downloaded at the same time.
+    pub fn concurrent_downloads(&self) -> usize {
+        self.concurrent_downloads.unwrap_or(1).max(1)
+    }
+
+    /// How many downloaded episodes are processed in the background at the same time, 0 means
+    /// each episode is processed before the next download starts.
+    pub fn processing_concurrency(&self) -> usize {
+        self.processing_concurrency.unwrap_or(0)
+    }
+
+    /// The combined download speed of all episodes in bytes per second.
+    pub fn max_download_rate(&self) -> Option<u64> {
+        let rate = self.max_download_rate.as_ref()?;
+        match utils::str_to_bytes(rate) {
+            Ok(rate) => Some(rate),
+            Err(e) => {
+                eprintln!("invalid max_download_rate: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    /// How long a downloaded feed is reused before it's fetched again.
+    pub fn feed_cache_ttl(&self) -> Option<time::Duration> {
+        let ttl = self.feed_cache_ttl.as_ref()?;
+        match utils::str_to_duration(ttl) {
+            Ok(ttl) => Some(ttl),
+            Err(e) => {
+                eprintln!("invalid feed_cache_ttl: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    pub fn episode_index(&self) -> Option<PathBuf> {
+        if !self.episode_index.unwrap_or(false) {
+            return None;
+        }
+
+        Some(
+            self.episode_index_path
+                .clone()
+                .unwrap_or_else(crate::episode_index::default_path),
+        )
+    }
+
+    /// How duplicate downloads are detected, which needs the episode index.
+    pub fn dedupe(&self) -> Option<DedupeMode> {
+        self.episode_index().and(self.dedupe)
+    }
+
+    /// How many podcasts are synced at the same time.
+    pub fn max_concurrent_podcasts(&self) -> usize {
+        self.max_concurrent_podcasts.unwrap_or(5).max(1)
+    }
+
     pub fn max_line_width(&self) -> usize {
         self.search.line_width.unwrap_or(79)
     }
@@ -454,6 +878,9 @@ impl Default for GlobalConfig {
     fn default() -> Self {
         Self {
             name_pattern: default_name_pattern(),
+            filename_template: None,
+            max_filename_length: None,
+            ascii_filenames: None,
             download_path: default_download_path(),
             id_pattern: default_id_pattern(),
             max_days: None,
@@ -461,14 +888,188 @@ impl Default for GlobalConfig {
             earliest_date: None,
             id3_tags: Default::default(),
             download_hook: None,
+            post_sync_hook: None,
+            metrics_path: None,
             tracker_path: None,
             style: Default::default(),
             search: Default::default(),
             log: Default::default(),
             symlink: None,
             user_agent: None,
+            proxy: None,
             partial_path: None,
+            download_transcripts: None,
+            embed_artwork: None,
+            transcode_to: None,
+            keep_original: None,
+            normalize_loudness: None,
+            target_lufs: None,
+            write_metadata_sidecar: None,
+            save_cover: None,
+            set_mtime_from_pubdate: None,
+            verify_length: None,
+            probe_size: None,
+            chunked_download: None,
+            chunk_size: None,
+            skip_if_file_exists: None,
+            preferred_format: None,
+            preferred_bitrate: None,
+            all_enclosures: None,
+            embed_chapters: None,
+            extract_soundbites: None,
+            generate_playlist: None,
+            latest_symlink: None,
+            sort_by_episode_number: None,
+            on_error: None,
+            max_feed_pages: None,
+            max_retries: None,
+            retry_base_delay_ms: None,
+            max_retry_after_secs: None,
+            concurrent_downloads: None,
+            processing_concurrency: None,
+            max_concurrent_podcasts: None,
+            max_downloads_per_run: None,
+            max_download_rate: None,
+            feed_cache_ttl: None,
+            notify: None,
+            episode_index: None,
+            episode_index_path: None,
+            dedupe: None,
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_redirects: None,
+            max_age: None,
+            keep_recent: None,
+            ignore_block: None,
+            enabled: None,
+            auto_update_feed_url: None,
+            follow_new_feed_url: None,
+            tag_title_template: None,
+            tag_album_template: None,
+        }
+    }
+}
+
+/// How many times failed requests are retried, and for how long to wait between them.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: time::Duration,
+    /// The longest a `Retry-After` header of a rate-limited response is waited for.
+    pub max_retry_after: time::Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(global_config: &GlobalConfig) -> Self {
+        Self {
+            max_retries: global_config.max_retries.unwrap_or(3),
+            base_delay: time::Duration::from_millis(
+                global_config.retry_base_delay_ms.unwrap_or(1000),
+            ),
+            max_retry_after: time::Duration::from_secs(
+                global_config.max_retry_after_secs.unwrap_or(300),
+            ),
+        }
+    }
+
+    /// Exponential backoff, the delay doubles on each attempt.
+    pub fn delay(&self, attempt: u32) -> time::Duration {
+        self.base_delay * 2u32.saturating_pow(attempt.saturating_sub(1))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: time::Duration::from_millis(1000),
+            max_retry_after: time::Duration::from_secs(300),
+        }
+    }
+}
+
+/// Authentication and custom headers that are attached to every request of a podcast.
+///
+/// The client is shared between all podcasts so these can't be configured on the client itself.
+#[derive(Clone, Default)]
+pub struct RequestSettings {
+    username: Option<String>,
+    password: Option<String>,
+    /// Overrides the user agent of the client.
+    user_agent: Option<String>,
+    headers: HashMap<String, String>,
+}
+
+impl RequestSettings {
+    pub fn apply(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(user_agent) = &self.user_agent {
+            request = request.header(reqwest::header::USER_AGENT, user_agent);
+        }
+
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        for (key, val) in &self.headers {
+            request = request.header(key, val);
+        }
+
+        request
+    }
+}
+
+// Manual impl so that credentials don't end up in the logs.
+impl std::fmt::Debug for RequestSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestSettings")
+            .field("username", &self.username.as_ref().map(|_| "<redacted>"))
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("user_agent", &self.user_agent)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Filters out episodes of a podcast before the download mode is considered.
+#[derive(Debug, Clone, Default)]
+pub struct EpisodeFilter {
+    title_include: Option<Regex>,
+    title_exclude: Option<Regex>,
+    min_duration: Option<time::Duration>,
+    max_duration: Option<time::Duration>,
+    require_duration: bool,
+}
+
+impl EpisodeFilter {
+    pub fn matches(&self, attrs: &episode::Attributes) -> bool {
+        let title = attrs.title();
+
+        if let Some(include) = &self.title_include {
+            if !include.is_match(title) {
+                return false;
+            }
+        }
+
+        if let Some(exclude) = &self.title_exclude {
+            if exclude.is_match(title) {
+                return false;
+            }
+        }
+
+        // Episodes without a known duration are only filtered out if explicitly required.
+        let Some(duration) = attrs.duration() else {
+            return !self.require_duration;
+        };
+
+        if self.min_duration.is_some_and(|min| duration < min) {
+            return false;
+        }
+
+        if self.max_duration.is_some_and(|max| duration > max) {
+            return false;
         }
+
+        true
     }
 }
 
@@ -478,15 +1079,44 @@ pub enum DownloadMode {
         max_time: Option<Unix>,
         earliest_date: Option<Unix>,
         max_episodes: Option<i64>,
+        /// Episodes published before the podcast was subscribed to are skipped.
+        subscribed_at: Option<Unix>,
     },
     Backlog {
         start: Unix,
         interval: Unix,
+        /// Episodes published before this are skipped, per `backlog_max_age`.
+        earliest: Option<Unix>,
+        /// How many of the episodes are older than `earliest`, which depends on the feed so it's
+        /// filled in when the feed is loaded.
+        skipped: usize,
+    },
+    /// Only episodes published within the range are downloaded.
+    DateRange {
+        after: Option<Unix>,
+        before: Option<Unix>,
     },
+    /// Only the most recent episodes are downloaded, older ones can be pruned from disk.
+    LatestN { count: usize },
 }
 
 impl DownloadMode {
     pub fn new(global_config: &GlobalConfig, podcast_config: &PodcastConfig) -> Self {
+        if podcast_config.published_after.is_some() || podcast_config.published_before.is_some() {
+            return Self::date_range(podcast_config);
+        }
+
+        if let Some(count) = podcast_config.latest_episodes {
+            return Self::latest(podcast_config, count);
+        }
+
+        let backlog =
+            podcast_config.backlog_start.is_some() || podcast_config.backlog_interval.is_some();
+        if podcast_config.backlog_max_age.is_some() && !backlog {
+            eprintln!("'backlog_max_age' only applies to backlog mode.");
+            std::process::exit(1);
+        }
+
         match (
             podcast_config.backlog_start.clone(),
             podcast_config.backlog_interval.clone(),
@@ -509,6 +1139,8 @@ impl DownloadMode {
                                 .expect("failed to parse earliest_date string")
                         })
                 },
+                // Depends on the state of the podcast, so it's filled in when the feed is loaded.
+                subscribed_at: None,
             },
             (Some(_), None) => {
                 eprintln!("missing backlog_interval");
@@ -540,13 +1172,91 @@ impl DownloadMode {
                     std::process::exit(1);
                 };
 
+                let start = std::time::Duration::from_secs(start.timestamp() as u64);
+                let earliest =
+                    podcast_config.backlog_max_age.as_ref().map(
+                        |age| match utils::str_to_duration(age) {
+                            Ok(age) => start.saturating_sub(age),
+                            Err(e) => {
+                                eprintln!("invalid backlog_max_age: {}", e);
+                                std::process::exit(1);
+                            }
+                        },
+                    );
+
                 DownloadMode::Backlog {
-                    start: std::time::Duration::from_secs(start.timestamp() as u64),
+                    start,
                     interval: Unix::from_secs(interval as u64 * 86400),
+                    earliest,
+                    skipped: 0,
                 }
             }
         }
     }
+
+    fn latest(podcast_config: &PodcastConfig, count: usize) -> Self {
+        if podcast_config.backlog_start.is_some() || podcast_config.backlog_interval.is_some() {
+            eprintln!("'latest_episodes' not compatible with backlog mode.");
+            std::process::exit(1);
+        }
+
+        for (key, enabled) in [
+            ("max_days", podcast_config.max_days.is_enabled()),
+            ("max_episodes", podcast_config.max_episodes.is_enabled()),
+            ("earliest_date", podcast_config.earliest_date.is_enabled()),
+        ] {
+            if enabled {
+                eprintln!("'{}' not compatible with 'latest_episodes'.", key);
+                std::process::exit(1);
+            }
+        }
+
+        DownloadMode::LatestN { count }
+    }
+
+    fn date_range(podcast_config: &PodcastConfig) -> Self {
+        if podcast_config.backlog_start.is_some() || podcast_config.backlog_interval.is_some() {
+            eprintln!("'published_after' and 'published_before' not compatible with backlog mode.");
+            std::process::exit(1);
+        }
+
+        if podcast_config.latest_episodes.is_some() {
+            eprintln!("'latest_episodes' not compatible with date range mode.");
+            std::process::exit(1);
+        }
+
+        if podcast_config.max_days.is_enabled() {
+            eprintln!("'max_days' not compatible with date range mode.");
+            std::process::exit(1);
+        }
+
+        if podcast_config.max_episodes.is_enabled() {
+            eprintln!("'max_episodes' not compatible with date range mode.");
+            std::process::exit(1);
+        }
+
+        if podcast_config.earliest_date.is_enabled() {
+            eprintln!("'earliest_date' not compatible with date range mode.");
+            eprintln!("Consider using 'published_after' instead.");
+            std::process::exit(1);
+        }
+
+        let parse = |key: &str, date: &Option<String>| {
+            date.as_ref()
+                .map(|date| match utils::date_str_to_unix(date) {
+                    Ok(date) => date,
+                    Err(_) => {
+                        eprintln!("invalid {} format.", key);
+                        std::process::exit(1);
+                    }
+                })
+        };
+
+        DownloadMode::DateRange {
+            after: parse("published_after", &podcast_config.published_after),
+            before: parse("published_before", &podcast_config.published_before),
+        }
+    }
 }
 
 impl Default for DownloadMode {
@@ -555,109 +1265,1201 @@ impl Default for DownloadMode {
             max_time: None,
             earliest_date: None,
             max_episodes: None,
+            subscribed_at: None,
+        }
+    }
+}
+
+/// Downloads the latest episodes of a feed that isn't in the podcasts file to `out`, returning
+/// their paths.
+///
+/// The download tracker is temporary and the episode index is left alone, so the feed can be
+/// downloaded again and nothing about it is remembered.
+pub async fn download_feed(
+    mut global_config: GlobalConfig,
+    url: String,
+    latest: usize,
+    out: &Path,
+    since: Option<Unix>,
+    dry_run: bool,
+) -> Vec<PathBuf> {
+    global_config.episode_index = Some(false);
+
+    let tracker_path = std::env::temp_dir().join(format!("talecast-{}.downloaded", process::id()));
+    let config = PodcastConfig::one_off(url.clone(), out, &tracker_path, latest);
+    let client = init_reqwest_client(&global_config);
+    let rate_limiter = global_config
+        .max_download_rate()
+        .map(|rate| Arc::new(utils::RateLimiter::new(rate)));
+
+    // The directory is named after the podcast, which is only known once the feed is fetched.
+    let hidden = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let ui = DownloadBar::new(url.clone(), global_config.style(), &hidden, url.len());
+    let xml = match Podcast::fetch_feed(&config, &global_config, &client, &ui).await {
+        Ok(xml) => xml,
+        Err(e) => {
+            eprintln!("failed to fetch {}: {}", &url, e);
+            return vec![];
+        }
+    };
+    let name = Podcast::feed_title(&url, &xml, &ui)
+        .map(sanitize_filename::sanitize)
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| "podcast".to_string());
+
+    let plain = !dry_run && global_config.style().plain();
+    let mp = match dry_run || plain {
+        true => hidden,
+        false => MultiProgress::new(),
+    };
+    let observer = plain.then(|| Arc::new(PlainProgress::default()) as Arc<dyn SyncObserver>);
+    let mut ui = DownloadBar::new(name.clone(), global_config.style(), &mp, name.len())
+        .with_observer(observer);
+    let podcast = Podcast::from_feed(
+        name.clone(),
+        &xml,
+        config,
+        &global_config,
+        client,
+        rate_limiter,
+        &ui,
+    );
+    let report = match podcast.await {
+        Ok(podcast) => podcast.since(since).sync(&mut ui, dry_run).await,
+        Err(e) => {
+            eprintln!("failed to parse {}: {}", &url, e);
+            return vec![];
         }
+    };
+    let _ = fs::remove_file(&tracker_path);
+
+    if let Some(e) = &report.error {
+        eprintln!("{}: {}", &name, e);
     }
+
+    report.downloaded
 }
 
-fn init_reqwest_client(config: &GlobalConfig) -> Arc<reqwest::Client> {
-    reqwest::Client::builder()
+pub fn init_reqwest_client(config: &GlobalConfig) -> Arc<reqwest::Client> {
+    // Compressed responses are decoded transparently, except for range requests of enclosures.
+    let mut builder = reqwest::Client::builder()
         .user_agent(&config.user_agent())
+        .connect_timeout(config.connect_timeout())
+        .redirect(utils::redirect_policy(config.max_redirects()))
+        .gzip(true)
+        .brotli(true)
+        .deflate(true);
+
+    if let Some(timeout) = config.request_timeout() {
+        builder = builder.timeout(timeout);
+    }
+
+    // Without an explicit proxy, reqwest uses the HTTP_PROXY/HTTPS_PROXY environment variables.
+    if let Some(proxy) = &config.proxy {
+        match reqwest::Proxy::all(proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy.no_proxy(reqwest::NoProxy::from_env())),
+            Err(e) => {
+                eprintln!("invalid proxy url: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    builder
         .build()
         .map(Arc::new)
         .expect("error: failed to instantiate reqwest client")
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PodcastConfigs(HashMap<String, PodcastConfig>);
-
-impl PodcastConfigs {
-    pub async fn sync(self, global_config: GlobalConfig, log_file: &Path) -> Vec<PathBuf> {
-        eprintln!("syncing {} podcasts", self.len());
-        log::info!("syncing podcasts..");
-
-        let mp = MultiProgress::new();
-        let global_config = Arc::new(global_config);
-        let client = init_reqwest_client(&global_config);
-
-        let Some(longest_name) = self.longest_name() else {
-            return vec![];
-        };
+/// Runs the hook once after all podcasts are synced, even if some of them failed.
+///
+/// The downloaded paths are passed on stdin, one per line, and the number of podcasts that
+/// failed in the `TALECAST_FAILED_FEEDS` environment variable.
+async fn run_post_sync_hook(hook: &Path, reports: &[SyncReport]) {
+    use tokio::io::AsyncWriteExt;
+
+    let failed = reports
+        .iter()
+        .filter(|report| report.error.is_some())
+        .count();
+    let mut input = String::new();
+    for path in reports.iter().flat_map(|report| &report.downloaded) {
+        input.push_str(&path.to_string_lossy());
+        input.push('\n');
+    }
 
-        let error_occured = Arc::new(AtomicBool::new(false));
+    log::info!("running post sync hook: {:?}", hook);
+    let child = tokio::process::Command::new(hook)
+        .env("TALECAST_FAILED_FEEDS", failed.to_string())
+        .stdin(std::process::Stdio::piped())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("failed to run post sync hook: {}", e);
+            return;
+        }
+    };
 
-        let futures = self
-            .into_inner()
-            .into_iter()
-            .map(|(name, config)| {
-                let client = Arc::clone(&client);
-                let settings = global_config.style();
-                let mut ui = DownloadBar::new(name.clone(), settings, &mp, longest_name);
-                let global_config = Arc::clone(&global_config);
-                let val = error_occured.clone();
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = stdin.write_all(input.as_bytes()).await {
+            log::warn!("failed to pass paths to post sync hook: {}", e);
+        }
+    }
 
-                tokio::task::spawn(async move {
-                    match Podcast::new(name, config, &global_config, client, &ui).await {
-                        Ok(podcast) => podcast.sync(&mut ui).await,
-                        Err(e) => {
-                            ui.error(&e);
-                            val.store(true, Ordering::SeqCst);
-                            vec![]
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
+    match child.wait().await {
+        Ok(status) if !status.success() => eprintln!("post sync hook exited with {}", status),
+        Ok(_) => {}
+        Err(e) => eprintln!("failed to run post sync hook: {}", e),
+    }
+}
 
-        let paths: Vec<PathBuf> = future::join_all(futures)
-            .await
-            .into_iter()
-            .filter_map(Result::ok)
-            .flatten()
-            .collect();
+/// Shows the funding links of the podcasts that have them, once per podcast.
+fn show_funding(reports: &[SyncReport]) {
+    for report in reports {
+        if report.funding.is_empty() || crate::cache::FundingNotice::is_shown(&report.url) {
+            continue;
+        }
 
-        if let Some(p) = global_config.log().path() {
-            if true || error_occured.load(Ordering::SeqCst) {
-                utils::create_dir(p);
-                let log_name = log_file.file_name().unwrap();
-                let new_path = p.join(log_name);
-                fs::rename(log_file, new_path).unwrap();
+        for funding in &report.funding {
+            match &funding.label {
+                Some(label) => eprintln!(
+                    "Support {}: {} ({})",
+                    report.display_name(),
+                    &funding.url,
+                    label
+                ),
+                None => eprintln!("Support {}: {}", report.display_name(), &funding.url),
             }
         }
 
-        paths
+        if crate::cache::FundingNotice::mark_shown(&report.url).is_none() {
+            log::warn!(
+                "failed to remember that funding of {} was shown",
+                &report.name
+            );
+        }
     }
+}
 
-    pub fn load() -> Self {
-        let Ok(config_str) = fs::read_to_string(&Self::path()) else {
-            eprintln!("error: failed to read podcasts.toml file");
-            process::exit(1);
-        };
-
-        match toml::from_str(&config_str) {
-            Ok(s) => Self(s),
-            Err(e) => {
-                eprintln!("failed to deserialize podcasts.toml file\n{:?}", e);
-                process::exit(1);
-            }
+/// The stats of the downloaded episodes that are found through the cached feed of the podcast.
+async fn cached_stats(
+    name: &str,
+    mut config: PodcastConfig,
+    global_config: &GlobalConfig,
+    client: &Arc<reqwest::Client>,
+    mp: &MultiProgress,
+) -> Option<LibraryStats> {
+    let feed = crate::cache::FeedCache::get(&config.url)?;
+    // Only the first page is cached, the others would have to be downloaded.
+    config.max_feed_pages = Some(0);
+
+    let ui = DownloadBar::new(
+        name.to_string(),
+        global_config.style(),
+        mp,
+        name.chars().count(),
+    );
+    let client = Arc::clone(client);
+    match Podcast::from_feed(
+        name.to_string(),
+        &feed.xml,
+        config,
+        global_config,
+        client,
+        None,
+        &ui,
+    )
+    .await
+    {
+        Ok(podcast) => Some(podcast.library_stats()),
+        Err(e) => {
+            log::warn!("failed to read cached feed of {}: {}", name, e);
+            None
         }
     }
+}
 
-    fn into_inner(self) -> HashMap<String, PodcastConfig> {
-        self.0
+fn stats_json(stats: Option<&LibraryStats>) -> serde_json::Value {
+    serde_json::json!({
+        "episodes": stats.map(|stats| stats.episodes),
+        "size": stats.map(|stats| stats.size),
+        "oldest": stats.and_then(|stats| stats.oldest).and_then(rfc3339),
+        "newest": stats.and_then(|stats| stats.newest).and_then(rfc3339),
+    })
+}
+
+fn stats_cells(name: &str, stats: Option<&LibraryStats>, last_sync: Option<Unix>) -> [String; 6] {
+    let date = |unix: Option<Unix>, format: &str| {
+        unix.and_then(|unix| chrono::DateTime::from_timestamp(unix.as_secs() as i64, 0))
+            .map(|date| {
+                date.with_timezone(&chrono::Local)
+                    .format(format)
+                    .to_string()
+            })
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    match stats {
+        Some(stats) => [
+            name.to_string(),
+            stats.episodes.to_string(),
+            indicatif::HumanBytes(stats.size).to_string(),
+            date(stats.oldest, "%Y-%m-%d"),
+            date(stats.newest, "%Y-%m-%d"),
+            date(last_sync, "%Y-%m-%d %H:%M"),
+        ],
+        None => [
+            name.to_string(),
+            "-".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+            date(last_sync, "%Y-%m-%d %H:%M"),
+        ],
     }
+}
 
-    pub fn filter(mut self, filter: Option<Regex>) -> Self {
-        self.0.retain(|name, _| match filter {
-            Some(ref filter) => filter.is_match(&name),
-            None => true,
-        });
+fn rfc3339(unix: Unix) -> Option<String> {
+    chrono::DateTime::from_timestamp(unix.as_secs() as i64, 0).map(|date| date.to_rfc3339())
+}
 
-        self
+/// Lists the episodes that were skipped after failing, by podcast.
+fn show_failures(reports: &[SyncReport]) {
+    for report in reports.iter().filter(|report| !report.failed.is_empty()) {
+        eprintln!(
+            "{}: skipped {} failed episodes",
+            report.display_name(),
+            report.failed.len()
+        );
+        for failed in &report.failed {
+            eprintln!("  {}: {}", &failed.title, &failed.error);
+        }
     }
+}
 
-    pub fn assert_not_empty(self) -> Self {
-        if self.is_empty() {
-            eprintln!("No podcasts configured!");
+/// Tells about the feeds that permanently moved, replacing their url in the podcasts file if
+/// they're in `auto_update`.
+///
+/// Feeds that declare a new url with `<itunes:new-feed-url>` are handled the same way, with
+/// `follow_new` listing the ones whose url gets replaced.
+fn handle_moved_feeds(reports: &[SyncReport], auto_update: &[String], follow_new: &[String]) {
+    for report in reports {
+        if let Some(new_url) = &report.moved_to {
+            if !auto_update.contains(&report.name) {
+                eprintln!(
+                    "{}: feed permanently moved to {}, update its url in podcasts.toml",
+                    &report.name, new_url
+                );
+                continue;
+            }
+
+            match PodcastConfigs::update_url(&report.name, new_url) {
+                Ok(()) => eprintln!("{}: feed moved, url updated to {}", &report.name, new_url),
+                Err(e) => eprintln!(
+                    "{}: feed permanently moved to {}, but failed to update its url: {}",
+                    &report.name, new_url, e
+                ),
+            }
+            continue;
+        }
+
+        let Some(new_url) = &report.new_feed_url else {
+            continue;
+        };
+
+        if !follow_new.contains(&report.name) {
+            eprintln!(
+                "{}: feed declares a new url, update it in podcasts.toml or set follow_new_feed_url\n  old: {}\n  new: {}",
+                &report.name, &report.url, new_url
+            );
+            continue;
+        }
+
+        match PodcastConfigs::update_url(&report.name, new_url) {
+            Ok(()) => eprintln!(
+                "{}: feed declares a new url, url updated\n  old: {}\n  new: {}",
+                &report.name, &report.url, new_url
+            ),
+            Err(e) => eprintln!(
+                "{}: feed declares a new url, but failed to update it: {}\n  old: {}\n  new: {}",
+                &report.name, e, &report.url, new_url
+            ),
+        }
+    }
+}
+
+/// Summarizes a sync, e.g. "Downloaded 4 new episodes across 2 podcasts, 1 feed failed".
+fn sync_summary(reports: &[SyncReport]) -> String {
+    let episodes: usize = reports.iter().map(|report| report.downloaded.len()).sum();
+    let podcasts = reports
+        .iter()
+        .filter(|report| !report.downloaded.is_empty())
+        .count();
+    let failed = reports
+        .iter()
+        .filter(|report| report.error.is_some())
+        .count();
+
+    let mut summary = match (episodes, podcasts) {
+        (0, _) => "No new episodes".to_string(),
+        (1, _) => "Downloaded 1 new episode".to_string(),
+        (episodes, 1) => format!("Downloaded {} new episodes", episodes),
+        (episodes, podcasts) => format!(
+            "Downloaded {} new episodes across {} podcasts",
+            episodes, podcasts
+        ),
+    };
+
+    match failed {
+        0 => {}
+        1 => summary.push_str(", 1 feed failed"),
+        failed => summary.push_str(&format!(", {} feeds failed", failed)),
+    }
+
+    summary
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PodcastConfigs(HashMap<String, PodcastConfig>);
+
+impl PodcastConfigs {
+    /// Syncs all the podcasts, returning the paths of the downloaded episodes.
+    ///
+    /// With `json` enabled the progress bars are hidden and a summary of each podcast is
+    /// printed to stdout as JSON instead. With `dry_run` enabled the episodes that would be
+    /// downloaded are listed, and their would-be paths returned.
+    ///
+    /// The temporary `log_file` is moved to the configured log directory afterwards.
+    pub async fn sync(
+        self,
+        global_config: GlobalConfig,
+        log_file: Option<&Path>,
+        json: bool,
+        dry_run: bool,
+        since: Option<Unix>,
+    ) -> Vec<PathBuf> {
+        let disabled = self.disabled(&global_config);
+        for name in &disabled {
+            eprintln!("{}: skipped (disabled)", name);
+        }
+        eprintln!("syncing {} podcasts", self.len() - disabled.len());
+
+        let auto_update: Vec<String> = self
+            .0
+            .iter()
+            .filter(|(_, config)| config.auto_update_feed_url(&global_config))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let follow_new: Vec<String> = self
+            .0
+            .iter()
+            .filter(|(_, config)| config.follow_new_feed_url(&global_config))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let plain = !json && !dry_run && global_config.style().plain();
+        let mp = if json || dry_run || plain {
+            MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        };
+        let observer = plain.then(|| Arc::new(PlainProgress::default()) as Arc<dyn SyncObserver>);
+        let log_dir = global_config.log().path().map(Path::to_path_buf);
+
+        let mut reports = self
+            .sync_with_progress(global_config, &mp, observer, dry_run, since)
+            .await;
+
+        if json {
+            reports.sort_by(|a, b| a.name.cmp(&b.name));
+            match serde_json::to_string_pretty(&reports) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("failed to serialize sync summary: {:?}", e),
+            }
+        } else if !dry_run {
+            show_funding(&reports);
+            show_failures(&reports);
+        }
+
+        if !dry_run {
+            handle_moved_feeds(&reports, &auto_update, &follow_new);
+        }
+
+        let paths: Vec<PathBuf> = reports
+            .into_iter()
+            .flat_map(|report| report.downloaded)
+            .collect();
+
+        if let (Some(p), Some(log_file)) = (log_dir, log_file) {
+            utils::create_dir(&p);
+            let log_name = log_file.file_name().unwrap();
+            let new_path = p.join(log_name);
+            fs::rename(log_file, new_path).unwrap();
+        }
+
+        paths
+    }
+
+    /// Syncs all the podcasts without printing anything, returning a report of each.
+    ///
+    /// The progress bars are drawn to `mp`, which can be hidden with
+    /// [`indicatif::ProgressDrawTarget::hidden`] when there's no terminal to draw them to.
+    /// The `observer` is told about the progress as well. Disabled podcasts are skipped.
+    pub async fn sync_with_progress(
+        mut self,
+        global_config: GlobalConfig,
+        mp: &MultiProgress,
+        observer: Option<Arc<dyn SyncObserver>>,
+        dry_run: bool,
+        since: Option<Unix>,
+    ) -> Vec<SyncReport> {
+        log::info!("syncing podcasts..");
+        let start = time::Instant::now();
+
+        self.0.retain(|name, config| {
+            let enabled = config.enabled(&global_config);
+            if !enabled {
+                log::info!("skipping disabled podcast: {}", name);
+            }
+            enabled
+        });
+
+        let global_config = Arc::new(global_config);
+        let client = init_reqwest_client(&global_config);
+        let rate_limiter = global_config
+            .max_download_rate()
+            .map(|rate| Arc::new(utils::RateLimiter::new(rate)));
+
+        let Some(longest_name) = self.longest_display_name() else {
+            return vec![];
+        };
+
+        let (permit_senders, futures): (Vec<_>, Vec<_>) = self
+            .into_prioritized()
+            .into_iter()
+            .map(|(name, config)| {
+                let client = Arc::clone(&client);
+                let settings = global_config.style();
+                let display_name = config.display_name(&name).to_string();
+                let mut ui = DownloadBar::new(display_name, settings, mp, longest_name)
+                    .with_observer(observer.clone());
+                let global_config = Arc::clone(&global_config);
+                let rate_limiter = rate_limiter.clone();
+                let (permit_sender, permit) = oneshot::channel();
+
+                let future = tokio::task::spawn(async move {
+                    ui.waiting();
+                    let _permit = permit.await;
+                    let url = config.url.clone();
+                    let display_title = config.display_title.clone();
+                    if utils::is_interrupted() {
+                        let e = CringeError::Interrupted;
+                        ui.error(&e);
+                        return SyncReport {
+                            name,
+                            display_title,
+                            url,
+                            error: Some(e),
+                            ..Default::default()
+                        };
+                    }
+
+                    let podcast = Podcast::new(
+                        name.clone(),
+                        config,
+                        &global_config,
+                        client,
+                        rate_limiter,
+                        &ui,
+                    );
+                    match podcast.await {
+                        Ok(podcast) => podcast.since(since).sync(&mut ui, dry_run).await,
+                        Err(e) => {
+                            ui.error(&e);
+                            SyncReport {
+                                name,
+                                display_title,
+                                url,
+                                error: Some(e),
+                                ..Default::default()
+                            }
+                        }
+                    }
+                });
+
+                (permit_sender, future)
+            })
+            .unzip();
+
+        // The permits are handed out in order of priority, rather than to whichever task happens
+        // to ask first.
+        let semaphore = Arc::new(Semaphore::new(global_config.max_concurrent_podcasts()));
+        tokio::task::spawn(async move {
+            for permit_sender in permit_senders {
+                let Ok(permit) = Arc::clone(&semaphore).acquire_owned().await else {
+                    return;
+                };
+                let _ = permit_sender.send(permit);
+            }
+        });
+
+        let reports: Vec<SyncReport> = future::join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect();
+
+        if global_config.notify.unwrap_or(false) && !dry_run {
+            utils::notify(sync_summary(&reports)).await;
+        }
+
+        if let Some(path) = global_config.metrics_path.as_ref().filter(|_| !dry_run) {
+            if let Err(e) = crate::metrics::write(path, &reports, start.elapsed()) {
+                log::error!("failed to write metrics to {:?}: {}", path, e);
+            }
+        }
+
+        if let Some(hook) = global_config.post_sync_hook.as_ref().filter(|_| !dry_run) {
+            run_post_sync_hook(hook, &reports).await;
+        }
+
+        reports
+    }
+
+    /// Prints the episodes each podcast would download, grouped by podcast, without syncing.
+    pub async fn list_pending(
+        self,
+        global_config: GlobalConfig,
+        order: ListOrder,
+        limit: Option<usize>,
+        since: Option<Unix>,
+    ) {
+        let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let global_config = Arc::new(global_config);
+        let client = init_reqwest_client(&global_config);
+        let semaphore = Arc::new(Semaphore::new(global_config.max_concurrent_podcasts()));
+
+        let Some(longest_name) = self.longest_name() else {
+            return;
+        };
+
+        let futures = self
+            .into_inner()
+            .into_iter()
+            .map(|(name, config)| {
+                let client = Arc::clone(&client);
+                let settings = global_config.style();
+                let ui = DownloadBar::new(name.clone(), settings, &mp, longest_name);
+                let global_config = Arc::clone(&global_config);
+                let semaphore = Arc::clone(&semaphore);
+
+                tokio::task::spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    let podcast =
+                        Podcast::new(name.clone(), config, &global_config, client, None, &ui);
+                    let listing = match podcast.await {
+                        Ok(podcast) => podcast.since(since).list_pending(order, limit, &ui),
+                        Err(e) => Some(format!("{}:\n  error: {}", name, e)),
+                    };
+                    (name, listing)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut listings: Vec<(String, String)> = future::join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter_map(|(name, listing)| Some((name, listing?)))
+            .collect();
+
+        if listings.is_empty() {
+            eprintln!("No pending episodes.");
+            return;
+        }
+
+        listings.sort_by(|a, b| a.0.cmp(&b.0));
+        let listings: Vec<String> = listings.into_iter().map(|(_, listing)| listing).collect();
+        println!("{}", listings.join("\n\n"));
+    }
+
+    /// Lets the user pick a podcast and then which of its episodes to download, regardless of
+    /// the download mode. Returns the paths of the downloaded episodes.
+    pub async fn interactive(self, global_config: GlobalConfig) -> Vec<PathBuf> {
+        let mut podcasts: Vec<(String, PodcastConfig)> = self.into_inner().into_iter().collect();
+        podcasts.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let names: Vec<String> = podcasts.iter().map(|(name, _)| name.clone()).collect();
+        let index = match tui::pick_podcast(&names) {
+            Ok(Some(index)) => index,
+            Ok(None) => return vec![],
+            Err(e) => {
+                eprintln!("failed to run interactive mode: {}", e);
+                return vec![];
+            }
+        };
+        let (name, config) = podcasts.swap_remove(index);
+
+        let client = init_reqwest_client(&global_config);
+        let rate_limiter = global_config
+            .max_download_rate()
+            .map(|rate| Arc::new(utils::RateLimiter::new(rate)));
+
+        eprintln!("fetching {}...", name);
+        // Bars are only drawn for the downloads, one for the fetching would be left behind
+        // above the episode list.
+        let hidden = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let ui = DownloadBar::new(name.clone(), global_config.style(), &hidden, name.len());
+        let podcast = Podcast::new(
+            name.clone(),
+            config,
+            &global_config,
+            client,
+            rate_limiter,
+            &ui,
+        );
+        let podcast = match podcast.await {
+            Ok(podcast) => podcast,
+            Err(e) => {
+                eprintln!("failed to fetch {}: {}", name, e);
+                return vec![];
+            }
+        };
+
+        let menu = podcast.menu(&ui);
+        if menu.is_empty() {
+            eprintln!("{} has no episodes.", name);
+            return vec![];
+        }
+
+        let selected = match tui::pick_episodes(&name, &menu) {
+            Ok(Some(selected)) => selected,
+            Ok(None) => return vec![],
+            Err(e) => {
+                eprintln!("failed to run interactive mode: {}", e);
+                return vec![];
+            }
+        };
+
+        let mp = MultiProgress::new();
+        let mut ui = DownloadBar::new(name.clone(), global_config.style(), &mp, name.len());
+        let report = podcast.download_selected(&selected, &mut ui).await;
+        show_funding(std::slice::from_ref(&report));
+
+        report.downloaded
+    }
+
+    /// Downloads the episodes with the guids again, whether they were downloaded before or not,
+    /// overwriting their files.
+    ///
+    /// Nothing is downloaded if any of the guids isn't in the feed of one of the podcasts.
+    pub async fn redownload(self, global_config: GlobalConfig, guids: &[String]) -> Vec<PathBuf> {
+        let mut podcasts: Vec<(String, PodcastConfig)> = self.into_inner().into_iter().collect();
+        podcasts.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let client = init_reqwest_client(&global_config);
+        let rate_limiter = global_config
+            .max_download_rate()
+            .map(|rate| Arc::new(utils::RateLimiter::new(rate)));
+
+        let hidden = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let mut fetched = vec![];
+        for (name, config) in podcasts {
+            let ui = DownloadBar::new(name.clone(), global_config.style(), &hidden, name.len());
+            let podcast = Podcast::new(
+                name.clone(),
+                config,
+                &global_config,
+                Arc::clone(&client),
+                rate_limiter.clone(),
+                &ui,
+            );
+            match podcast.await {
+                Ok(podcast) => fetched.push((name, podcast)),
+                Err(e) => {
+                    eprintln!("failed to fetch {}: {}", name, e);
+                    process::exit(1);
+                }
+            }
+        }
+
+        let missing: Vec<&String> = guids
+            .iter()
+            .filter(|guid| !fetched.iter().any(|(_, podcast)| podcast.has_guid(guid)))
+            .collect();
+        if !missing.is_empty() {
+            for guid in missing {
+                eprintln!("no episode with guid '{}' in the current feed", guid);
+            }
+            process::exit(1);
+        }
+
+        let mp = MultiProgress::new();
+        let mut reports = vec![];
+        for (name, podcast) in fetched {
+            if !guids.iter().any(|guid| podcast.has_guid(guid)) {
+                continue;
+            }
+
+            let mut ui = DownloadBar::new(name.clone(), global_config.style(), &mp, name.len());
+            reports.push(podcast.redownload(guids, &mut ui).await);
+        }
+        show_failures(&reports);
+
+        reports
+            .into_iter()
+            .flat_map(|report| report.downloaded)
+            .collect()
+    }
+
+    /// Fetches the feed of every podcast and records its title as the `display_title` in the
+    /// podcasts file. The names stay as they are.
+    pub async fn refresh_titles(self, global_config: GlobalConfig) {
+        let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let global_config = Arc::new(global_config);
+        let client = init_reqwest_client(&global_config);
+        let semaphore = Arc::new(Semaphore::new(global_config.max_concurrent_podcasts()));
+
+        let Some(longest_name) = self.longest_name() else {
+            return;
+        };
+
+        let futures = self
+            .into_inner()
+            .into_iter()
+            .map(|(name, config)| {
+                let client = Arc::clone(&client);
+                let settings = global_config.style();
+                let ui = DownloadBar::new(name.clone(), settings, &mp, longest_name);
+                let global_config = Arc::clone(&global_config);
+                let semaphore = Arc::clone(&semaphore);
+
+                tokio::task::spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    let title =
+                        match Podcast::fetch_feed(&config, &global_config, &client, &ui).await {
+                            Ok(xml) => Podcast::feed_title(&config.url, &xml, &ui)
+                                .filter(|title| !title.is_empty())
+                                .ok_or_else(|| "feed has no title".to_string()),
+                            Err(e) => Err(e.to_string()),
+                        };
+                    (name, config.display_title, title)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut results: Vec<_> = future::join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut titles = HashMap::new();
+        for (name, old, title) in results {
+            match title {
+                Ok(title) if old.as_ref() == Some(&title) => {
+                    println!("{:<longest_name$}  {} (unchanged)", name, title);
+                }
+                Ok(title) => {
+                    println!("{:<longest_name$}  {}", name, title);
+                    titles.insert(name, title);
+                }
+                Err(e) => eprintln!("{:<longest_name$}  FAILED: {}", name, e),
+            }
+        }
+
+        if titles.is_empty() {
+            return;
+        }
+
+        let mut podcasts = Self::load_unresolved();
+        for (name, title) in titles {
+            if let Some(config) = podcasts.0.get_mut(&name) {
+                config.display_title = Some(title);
+            }
+        }
+        podcasts.save_to_file();
+    }
+
+    /// Fetches and parses the feed of every podcast without downloading anything, printing
+    /// whether each of them worked and how long it took.
+    ///
+    /// Returns false if any of them failed.
+    pub async fn validate(self, global_config: GlobalConfig) -> bool {
+        let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let global_config = Arc::new(global_config);
+        let client = init_reqwest_client(&global_config);
+        let semaphore = Arc::new(Semaphore::new(global_config.max_concurrent_podcasts()));
+
+        let Some(longest_name) = self.longest_name() else {
+            return true;
+        };
+
+        let futures = self
+            .into_inner()
+            .into_iter()
+            .map(|(name, config)| {
+                let client = Arc::clone(&client);
+                let settings = global_config.style();
+                let ui = DownloadBar::new(name.clone(), settings, &mp, longest_name);
+                let global_config = Arc::clone(&global_config);
+                let semaphore = Arc::clone(&semaphore);
+
+                tokio::task::spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    let start = time::Instant::now();
+                    let podcast =
+                        Podcast::new(name.clone(), config, &global_config, client, None, &ui);
+                    let result = podcast.await.map(|podcast| podcast.episode_qty());
+                    (name, result, start.elapsed())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut results: Vec<_> = future::join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut failed = 0;
+        for (name, result, elapsed) in &results {
+            let secs = elapsed.as_secs_f64();
+            match result {
+                Ok(episodes) => {
+                    println!(
+                        "{:<longest_name$}  OK ({} episodes, {:.2}s)",
+                        name, episodes, secs
+                    )
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!("{:<longest_name$}  FAILED ({:.2}s): {}", name, secs, e);
+                }
+            }
+        }
+
+        if failed > 0 {
+            eprintln!("{} of {} feeds failed.", failed, results.len());
+        }
+
+        failed == 0
+    }
+
+    /// Deletes the downloaded episodes older than their `max_age`, `max_age` overrides the
+    /// configured one for every podcast that isn't marked with `keep`.
+    pub async fn prune(self, global_config: GlobalConfig, max_age: Option<time::Duration>) {
+        let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let global_config = Arc::new(global_config);
+        let client = init_reqwest_client(&global_config);
+        let semaphore = Arc::new(Semaphore::new(global_config.max_concurrent_podcasts()));
+
+        let Some(longest_name) = self.longest_name() else {
+            return;
+        };
+
+        let futures = self
+            .into_inner()
+            .into_iter()
+            .filter_map(|(name, config)| {
+                let max_age = max_age
+                    .filter(|_| !config.keep.unwrap_or(false))
+                    .or_else(|| config.max_age(&global_config))?;
+
+                let client = Arc::clone(&client);
+                let settings = global_config.style();
+                let ui = DownloadBar::new(name.clone(), settings, &mp, longest_name);
+                let global_config = Arc::clone(&global_config);
+                let semaphore = Arc::clone(&semaphore);
+
+                Some(tokio::task::spawn(async move {
+                    let _permit = semaphore.acquire().await;
+                    let podcast =
+                        Podcast::new(name.clone(), config, &global_config, client, None, &ui);
+                    let pruned = match podcast.await {
+                        Ok(podcast) => Ok(podcast.prune_older_than(max_age, &ui)),
+                        Err(e) => Err(e),
+                    };
+                    (name, pruned)
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        if futures.is_empty() {
+            eprintln!("No podcasts with a max_age to prune.");
+            return;
+        }
+
+        let mut results: Vec<_> = future::join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut total_files = 0;
+        let mut total_bytes = 0;
+        for (name, pruned) in results {
+            match pruned {
+                Ok((0, _)) => {}
+                Ok((files, bytes)) => {
+                    println!(
+                        "{}: {} files, {}",
+                        name,
+                        files,
+                        indicatif::HumanBytes(bytes)
+                    );
+                    total_files += files;
+                    total_bytes += bytes;
+                }
+                Err(e) => eprintln!("{}: error: {}", name, e),
+            }
+        }
+
+        println!(
+            "Pruned {} files, freed {}",
+            total_files,
+            indicatif::HumanBytes(total_bytes)
+        );
+    }
+
+    /// Shows how much of each podcast is downloaded, without fetching anything.
+    ///
+    /// The episode index is used for the podcasts it has, the others are read from their cached
+    /// feed. Podcasts whose feed was never cached are listed without stats.
+    pub async fn stats(self, global_config: GlobalConfig, json: bool) {
+        let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let client = init_reqwest_client(&global_config);
+
+        let mut indexed = match global_config.episode_index() {
+            Some(path) => crate::episode_index::stats(&path).unwrap_or_else(|e| {
+                eprintln!("failed to read episode index: {}", e);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+
+        let mut podcasts: Vec<_> = self.into_inner().into_iter().collect();
+        podcasts.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut rows = vec![];
+        let mut total = LibraryStats::default();
+        let mut latest_sync = None;
+        for (name, config) in podcasts {
+            let last_sync = crate::cache::LastSync::get(&config.url);
+            let stats = match indexed.remove(&name) {
+                Some(stats) => Some(stats),
+                None => cached_stats(&name, config, &global_config, &client, &mp).await,
+            };
+
+            if let Some(stats) = &stats {
+                total.merge(stats);
+            }
+            latest_sync = latest_sync.max(last_sync);
+            rows.push((name, stats, last_sync));
+        }
+
+        if json {
+            let podcasts: Vec<_> = rows
+                .iter()
+                .map(|(name, stats, last_sync)| {
+                    let mut value = stats_json(stats.as_ref());
+                    value["name"] = name.clone().into();
+                    value["last_sync"] = last_sync.and_then(rfc3339).into();
+                    value
+                })
+                .collect();
+            let mut total = stats_json(Some(&total));
+            total["last_sync"] = latest_sync.and_then(rfc3339).into();
+            let summary = serde_json::json!({ "podcasts": podcasts, "total": total });
+            match serde_json::to_string_pretty(&summary) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("failed to serialize stats: {:?}", e),
+            }
+            return;
+        }
+
+        let mut table = vec![[
+            "PODCAST".to_string(),
+            "EPISODES".to_string(),
+            "SIZE".to_string(),
+            "OLDEST".to_string(),
+            "NEWEST".to_string(),
+            "LAST SYNC".to_string(),
+        ]];
+        for (name, stats, last_sync) in &rows {
+            table.push(stats_cells(name, stats.as_ref(), *last_sync));
+        }
+        table.push(stats_cells("TOTAL", Some(&total), latest_sync));
+
+        let mut widths = [0; 6];
+        for row in &table {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in &table {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .enumerate()
+                .map(|(idx, (cell, width))| match idx {
+                    // The numbers are aligned to the right.
+                    1 | 2 => format!("{:>width$}", cell),
+                    _ => format!("{:<width$}", cell),
+                })
+                .collect();
+            println!("{}", line.join("  ").trim_end());
+        }
+    }
+
+    /// Loads the podcasts.toml file, with the `${VAR}` placeholders in urls and credentials
+    /// filled in from the environment.
+    pub fn load() -> Self {
+        let mut podcasts = Self::load_unresolved();
+
+        for (name, podcast) in &mut podcasts.0 {
+            if let Err(e) = podcast.resolve_env() {
+                eprintln!("invalid config for podcast '{}': {}", name, e);
+                process::exit(1);
+            }
+        }
+
+        podcasts
+    }
+
+    /// Loads the podcasts.toml file as it's written, which is what has to be saved back so the
+    /// secrets from the environment don't end up in it, or in an export.
+    pub(crate) fn load_unresolved() -> Self {
+        let Ok(config_str) = fs::read_to_string(&Self::path()) else {
+            eprintln!("error: failed to read podcasts.toml file");
+            process::exit(1);
+        };
+
+        let podcasts: HashMap<String, PodcastConfig> = match toml::from_str(&config_str) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("failed to deserialize podcasts.toml file\n{:?}", e);
+                process::exit(1);
+            }
+        };
+
+        for (name, podcast) in &podcasts {
+            if let Err(e) = podcast.episode_filter() {
+                eprintln!("invalid config for podcast '{}': {}", name, e);
+                process::exit(1);
+            }
+        }
+
+        Self(podcasts)
+    }
+
+    /// Podcasts configured in code rather than in the podcasts.toml file, keyed by name.
+    pub fn new(podcasts: HashMap<String, PodcastConfig>) -> Self {
+        Self(podcasts)
+    }
+
+    pub fn into_inner(self) -> HashMap<String, PodcastConfig> {
+        self.0
+    }
+
+    /// The podcasts ordered by descending priority, then by name.
+    pub fn into_prioritized(self) -> Vec<(String, PodcastConfig)> {
+        let mut podcasts: Vec<_> = self.0.into_iter().collect();
+        podcasts.sort_by(|(a_name, a), (b_name, b)| {
+            b.priority()
+                .cmp(&a.priority())
+                .then_with(|| a_name.cmp(b_name))
+        });
+        podcasts
+    }
+
+    pub fn filter(mut self, filter: Option<Regex>) -> Self {
+        self.0.retain(|name, _| match filter {
+            Some(ref filter) => filter.is_match(&name),
+            None => true,
+        });
+
+        self
+    }
+
+    /// Keeps only the podcast with the given name, enabling it if it's disabled.
+    pub fn only(mut self, name: Option<&str>) -> Self {
+        let Some(name) = name else {
+            return self;
+        };
+
+        let Some(mut config) = self.0.remove(name) else {
+            eprintln!("no podcast named '{}'", name);
+            process::exit(1);
+        };
+        config.enabled = Some(true);
+
+        Self(HashMap::from([(name.to_string(), config)]))
+    }
+
+    /// Keeps only the podcasts with the given names, or matching them as globs with `*` and `?`.
+    /// Like with [`Self::only`], podcasts picked by their exact name are enabled.
+    pub fn select(mut self, names: &[String]) -> Self {
+        if names.is_empty() {
+            return self;
+        }
+
+        let mut selected = HashMap::new();
+        for name in names {
+            if let Some(mut config) = self.0.remove(name) {
+                config.enabled = Some(true);
+                selected.insert(name.clone(), config);
+                continue;
+            }
+
+            let glob = format!("^{}$", regex::escape(name))
+                .replace(r"\*", ".*")
+                .replace(r"\?", ".");
+            let glob = Regex::new(&glob).unwrap();
+            let matching: Vec<String> = self
+                .0
+                .keys()
+                .filter(|podcast| glob.is_match(podcast))
+                .cloned()
+                .collect();
+
+            // A podcast may be matched by an earlier name already.
+            if matching.is_empty() && !selected.keys().any(|podcast| glob.is_match(podcast)) {
+                eprintln!("no podcast named '{}'", name);
+                process::exit(1);
+            }
+
+            for podcast in matching {
+                let config = self.0.remove(&podcast).unwrap();
+                selected.insert(podcast, config);
+            }
+        }
+
+        Self(selected)
+    }
+
+    /// The names of the podcasts that are disabled, in alphabetical order.
+    pub fn disabled(&self, global_config: &GlobalConfig) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .0
+            .iter()
+            .filter(|(_, config)| !config.enabled(global_config))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn assert_not_empty(self) -> Self {
+        if self.is_empty() {
+            eprintln!("No podcasts configured!");
             eprintln!("You can add podcasts with the following methods:\n");
             eprintln!("* \"{} --search <name of podcast>\"", crate::APPNAME);
             eprintln!(
@@ -678,10 +2480,17 @@ impl PodcastConfigs {
         self.0.iter().map(|(name, _)| name.chars().count()).max()
     }
 
+    pub fn longest_display_name(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .map(|(name, config)| config.display_name(name).chars().count())
+            .max()
+    }
+
     /// All podcasts matching the regex will only download upcoming episodes.
     /// time. Podcasts with backlog mode ignored.
     pub fn catch_up(filter: Option<Regex>) {
-        let mut podcasts = Self::load().filter(filter);
+        let mut podcasts = Self::load_unresolved().filter(filter);
 
         for (name, config) in &mut podcasts.0 {
             if config.catch_up() {
@@ -701,7 +2510,7 @@ impl PodcastConfigs {
     }
 
     pub fn save_modified(self) {
-        let mut all_podcasts = Self::load();
+        let mut all_podcasts = Self::load_unresolved();
         for (name, config) in self.0 {
             all_podcasts.0.insert(name, config);
         }
@@ -709,6 +2518,24 @@ impl PodcastConfigs {
         all_podcasts.save_to_file();
     }
 
+    /// Replaces the url of the podcast in the podcasts.toml file.
+    ///
+    /// Urls with environment variables are left alone, since the new url would store the values
+    /// of them in the file.
+    fn update_url(name: &str, url: &str) -> Result<(), String> {
+        let mut podcasts = Self::load_unresolved();
+        let Some(config) = podcasts.0.get_mut(name) else {
+            return Err("podcast not found in podcasts.toml".into());
+        };
+        if config.url.contains("${") {
+            return Err("its url contains environment variables".into());
+        }
+
+        config.url = url.to_string();
+        podcasts.save_to_file();
+        Ok(())
+    }
+
     pub fn save_to_file(self) {
         use std::fs::File;
 
@@ -721,11 +2548,18 @@ impl PodcastConfigs {
         };
     }
 
+    /// Adds the podcasts to the `podcasts.toml` file, skipping the ones already configured.
     pub fn extend(new_podcasts: HashMap<String, PodcastConfig>) {
-        let mut podcasts = Self::load();
+        let mut podcasts = Self::load_unresolved();
         for (name, podcast) in new_podcasts {
-            if !podcasts.0.contains_key(&name) {
-                podcasts.0.insert(name, podcast);
+            match podcasts.0.entry(name) {
+                std::collections::hash_map::Entry::Occupied(entry) => {
+                    eprintln!("'{}' already exists!", entry.key());
+                }
+                std::collections::hash_map::Entry::Vacant(entry) => {
+                    eprintln!("'{}' added!", entry.key());
+                    entry.insert(podcast);
+                }
             }
         }
 
@@ -737,7 +2571,7 @@ impl PodcastConfigs {
     /// If a podcast with the same name already exist,
     /// it does nothing and will return false. Otherwise true.
     pub fn push(name: String, podcast: PodcastConfig) -> bool {
-        let mut podcasts = Self::load();
+        let mut podcasts = Self::load_unresolved();
         if podcasts.0.contains_key(&name) {
             false
         } else {
@@ -758,22 +2592,134 @@ impl PodcastConfigs {
         path
     }
 
-    pub fn into_outlines(self) -> Vec<opml::Outline> {
-        self.0
+    /// Parses the podcasts of an OPML file.
+    ///
+    /// Podcasts in nested folders are flattened, optionally with the folder names
+    /// prefixed to the name of the podcast.
+    pub fn from_opml(path: &Path, prefix_folders: bool) -> Self {
+        let opml_string = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("failed to read opml file {:?}: {:?}", path, e);
+                process::exit(1);
+            }
+        };
+
+        let opml = match opml::OPML::from_str(&opml_string) {
+            Ok(opml) => opml,
+            Err(e) => {
+                eprintln!("failed to parse opml file {:?}: {:?}", path, e);
+                process::exit(1);
+            }
+        };
+
+        let mut podcasts = HashMap::default();
+        Self::extend_from_outlines(&mut podcasts, opml.body.outlines, None, prefix_folders);
+        Self(podcasts)
+    }
+
+    fn extend_from_outlines(
+        podcasts: &mut HashMap<String, PodcastConfig>,
+        outlines: Vec<opml::Outline>,
+        folder: Option<&str>,
+        prefix_folders: bool,
+    ) {
+        for outline in outlines {
+            let title = {
+                let title = outline.title.unwrap_or(outline.text);
+
+                if title.is_empty() {
+                    None
+                } else {
+                    Some(title)
+                }
+            };
+
+            // Outlines without a feed url but with children are folders.
+            if outline.xml_url.is_none() && !outline.outlines.is_empty() {
+                let folder = match (folder, title) {
+                    (Some(parent), Some(title)) if prefix_folders => {
+                        Some(format!("{} - {}", parent, title))
+                    }
+                    (Some(parent), _) => Some(parent.to_string()),
+                    (None, title) => title,
+                };
+
+                Self::extend_from_outlines(
+                    podcasts,
+                    outline.outlines,
+                    folder.as_deref(),
+                    prefix_folders,
+                );
+                continue;
+            }
+
+            let (name, podcast) = match (title, outline.xml_url) {
+                (None, None) => {
+                    eprintln!("importing failed due to feed with missing title and url");
+                    process::exit(1);
+                }
+                (Some(title), None) => {
+                    eprintln!(
+                        "importing failed due to following podcast missing its' url: {}",
+                        title
+                    );
+                    process::exit(1);
+                }
+                (None, Some(url)) => {
+                    eprintln!(
+                        "importing failed due to podcast with following url missing a title: {}",
+                        url
+                    );
+                    process::exit(1);
+                }
+                (Some(title), Some(url)) => (title, PodcastConfig::new(url)),
+            };
+
+            let name = match folder {
+                Some(folder) if prefix_folders => format!("{} - {}", folder, name),
+                _ => name,
+            };
+
+            podcasts.insert(name, podcast);
+        }
+    }
+
+    /// The `text` of an outline is the title of the podcast if it's recorded or has been fetched before,
+    /// while the `title` is the configured name so that importing it gives back the same name.
+    pub fn outlines(&self) -> Vec<opml::Outline> {
+        let mut podcasts: Vec<_> = self.0.iter().collect();
+        podcasts.sort_by_key(|(name, _)| *name);
+
+        podcasts
             .into_iter()
             .map(|(name, pod)| opml::Outline {
-                text: name.clone(),
+                text: pod
+                    .display_title
+                    .clone()
+                    .or_else(|| crate::cache::TitleMap::get_title(&pod.url))
+                    .unwrap_or_else(|| name.clone()),
                 r#type: Some("rss".to_string()),
                 xml_url: Some(pod.url.clone()),
-                title: Some(name),
+                title: Some(name.clone()),
                 ..opml::Outline::default()
             })
             .collect()
     }
+
+    pub fn to_opml(&self) -> String {
+        match opml::OPML::from(self).to_string() {
+            Ok(xml_string) => xml_string,
+            Err(e) => {
+                eprintln!("failed to serialize opml: {:?}", e);
+                process::exit(1);
+            }
+        }
+    }
 }
 
-impl From<PodcastConfigs> for opml::OPML {
-    fn from(podcasts: PodcastConfigs) -> opml::OPML {
+impl From<&PodcastConfigs> for opml::OPML {
+    fn from(podcasts: &PodcastConfigs) -> opml::OPML {
         use opml::{Body, Head, OPML};
 
         let mut opml = OPML {
@@ -785,7 +2731,7 @@ impl From<PodcastConfigs> for opml::OPML {
             ..Default::default()
         };
 
-        let outlines = podcasts.into_outlines();
+        let outlines = podcasts.outlines();
 
         opml.body = Body { outlines };
 
@@ -802,6 +2748,15 @@ impl IntoIterator for PodcastConfigs {
     }
 }
 
+impl<'a> IntoIterator for &'a mut PodcastConfigs {
+    type Item = (&'a String, &'a mut PodcastConfig);
+    type IntoIter = std::collections::hash_map::IterMut<'a, String, PodcastConfig>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
 impl<'a> IntoIterator for &'a PodcastConfigs {
     type Item = (&'a String, &'a PodcastConfig);
     type IntoIter = std::collections::hash_map::Iter<'a, String, PodcastConfig>;
@@ -816,40 +2771,354 @@ impl<'a> IntoIterator for &'a PodcastConfigs {
 #[serde(deny_unknown_fields)]
 pub struct PodcastConfig {
     pub url: String,
+    display_title: Option<String>,
     name_pattern: Option<String>,
+    filename_template: Option<String>,
+    max_filename_length: Option<usize>,
+    ascii_filenames: Option<bool>,
     id_pattern: Option<String>,
     #[serde(alias = "path")]
     download_path: Option<String>,
     partial_path: Option<String>,
     backlog_start: Option<String>,
     backlog_interval: Option<i64>,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    id3_tags: HashMap<String, String>,
+    backlog_max_age: Option<String>,
+    published_after: Option<String>,
+    published_before: Option<String>,
+    latest_episodes: Option<usize>,
+    prune_old: Option<bool>,
     max_days: ConfigOption<i64>,
     max_episodes: ConfigOption<i64>,
+    max_downloads_per_run: ConfigOption<usize>,
     earliest_date: ConfigOption<String>,
+    subscribed_at: ConfigOption<String>,
     download_hook: ConfigOption<PathBuf>,
     tracker_path: ConfigOption<String>,
     symlink: Option<String>,
+    download_transcripts: Option<bool>,
+    embed_artwork: Option<bool>,
+    transcode_to: ConfigOption<TranscodeFormat>,
+    keep_original: Option<bool>,
+    normalize_loudness: Option<bool>,
+    target_lufs: Option<f64>,
+    write_metadata_sidecar: Option<bool>,
+    save_cover: Option<bool>,
+    set_mtime_from_pubdate: Option<bool>,
+    verify_length: Option<bool>,
+    probe_size: Option<bool>,
+    chunked_download: Option<bool>,
+    chunk_size: Option<String>,
+    skip_if_file_exists: Option<bool>,
+    preferred_format: Option<String>,
+    preferred_bitrate: Option<u64>,
+    all_enclosures: Option<bool>,
+    embed_chapters: Option<bool>,
+    extract_soundbites: Option<bool>,
+    generate_playlist: Option<bool>,
+    latest_symlink: Option<bool>,
+    sort_by_episode_number: Option<bool>,
+    on_error: Option<OnError>,
+    max_feed_pages: Option<usize>,
+    username: Option<String>,
+    password: Option<String>,
+    user_agent: Option<String>,
+    title_include: Option<String>,
+    title_exclude: Option<String>,
+    min_duration: Option<String>,
+    max_duration: Option<String>,
+    require_duration: Option<bool>,
+    max_age: Option<String>,
+    keep: Option<bool>,
+    keep_recent: Option<usize>,
+    priority: Option<i64>,
+    ignore_block: Option<bool>,
+    enabled: Option<bool>,
+    auto_update_feed_url: Option<bool>,
+    follow_new_feed_url: Option<bool>,
+    tag_title_template: Option<String>,
+    tag_album_template: Option<String>,
+    // Tables have to come after the plain values, otherwise toml fails to serialize it.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    id3_tags: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    headers: HashMap<String, String>,
 }
 
 impl PodcastConfig {
     pub fn new(url: String) -> Self {
         Self {
             url,
+            display_title: Default::default(),
             name_pattern: Default::default(),
+            filename_template: Default::default(),
+            max_filename_length: Default::default(),
+            ascii_filenames: Default::default(),
             id_pattern: Default::default(),
             download_path: Default::default(),
             backlog_start: Default::default(),
             backlog_interval: Default::default(),
+            backlog_max_age: Default::default(),
+            published_after: Default::default(),
+            published_before: Default::default(),
+            latest_episodes: Default::default(),
+            prune_old: Default::default(),
             id3_tags: Default::default(),
             max_days: Default::default(),
             max_episodes: Default::default(),
+            max_downloads_per_run: Default::default(),
             earliest_date: Default::default(),
+            subscribed_at: Default::default(),
             download_hook: Default::default(),
             tracker_path: Default::default(),
             symlink: Default::default(),
             partial_path: Default::default(),
+            download_transcripts: Default::default(),
+            embed_artwork: Default::default(),
+            transcode_to: Default::default(),
+            keep_original: Default::default(),
+            normalize_loudness: Default::default(),
+            target_lufs: Default::default(),
+            write_metadata_sidecar: Default::default(),
+            save_cover: Default::default(),
+            set_mtime_from_pubdate: Default::default(),
+            verify_length: Default::default(),
+            probe_size: Default::default(),
+            chunked_download: Default::default(),
+            chunk_size: Default::default(),
+            skip_if_file_exists: Default::default(),
+            preferred_format: Default::default(),
+            preferred_bitrate: Default::default(),
+            all_enclosures: Default::default(),
+            embed_chapters: Default::default(),
+            extract_soundbites: Default::default(),
+            generate_playlist: Default::default(),
+            latest_symlink: Default::default(),
+            sort_by_episode_number: Default::default(),
+            on_error: Default::default(),
+            max_feed_pages: Default::default(),
+            username: Default::default(),
+            password: Default::default(),
+            user_agent: Default::default(),
+            title_include: Default::default(),
+            title_exclude: Default::default(),
+            min_duration: Default::default(),
+            max_duration: Default::default(),
+            require_duration: Default::default(),
+            max_age: Default::default(),
+            keep: Default::default(),
+            keep_recent: Default::default(),
+            priority: Default::default(),
+            ignore_block: Default::default(),
+            enabled: Default::default(),
+            auto_update_feed_url: Default::default(),
+            follow_new_feed_url: Default::default(),
+            tag_title_template: Default::default(),
+            tag_album_template: Default::default(),
+            headers: Default::default(),
+        }
+    }
+
+    /// A feed that's downloaded once, into a directory under `out` named after the podcast.
+    fn one_off(url: String, out: &Path, tracker_path: &Path, latest: usize) -> Self {
+        let mut config = Self::new(url);
+        config.download_path = Some(out.join("{podname}").to_string_lossy().into_owned());
+        config.tracker_path = ConfigOption::Enabled(tracker_path.to_string_lossy().into_owned());
+        config.latest_episodes = Some(latest);
+        config
+    }
+
+    pub fn episode_filter(&self) -> Result<EpisodeFilter, String> {
+        let compile = |key: &str, pattern: &Option<String>| -> Result<Option<Regex>, String> {
+            match pattern {
+                Some(pattern) => Regex::new(pattern)
+                    .map(Some)
+                    .map_err(|e| format!("invalid regex in {}: {}", key, e)),
+                None => Ok(None),
+            }
+        };
+
+        let duration = |key: &str, duration: &Option<String>| -> Result<_, String> {
+            match duration {
+                Some(duration) => utils::str_to_duration(duration)
+                    .map(Some)
+                    .map_err(|e| format!("{} in {}", e, key)),
+                None => Ok(None),
+            }
+        };
+
+        Ok(EpisodeFilter {
+            title_include: compile("title_include", &self.title_include)?,
+            title_exclude: compile("title_exclude", &self.title_exclude)?,
+            min_duration: duration("min_duration", &self.min_duration)?,
+            max_duration: duration("max_duration", &self.max_duration)?,
+            require_duration: self.require_duration.unwrap_or(false),
+        })
+    }
+
+    /// How many additional pages of a paginated feed are fetched, 0 disables pagination.
+    pub fn max_feed_pages(&self, global_config: &GlobalConfig) -> usize {
+        self.max_feed_pages
+            .or(global_config.max_feed_pages)
+            .unwrap_or(10)
+    }
+
+    /// Whether episodes outside of the `latest_episodes` window are deleted.
+    pub fn prune_old(&self) -> bool {
+        self.latest_episodes.is_some() && self.prune_old.unwrap_or(false)
+    }
+
+    /// Podcasts with a higher priority are synced first.
+    pub fn priority(&self) -> i64 {
+        self.priority.unwrap_or(0)
+    }
+
+    /// How long downloaded episodes are kept before `--prune` deletes them, `keep` exempts the podcast.
+    pub fn max_age(&self, global_config: &GlobalConfig) -> Option<time::Duration> {
+        if self.keep.unwrap_or(false) {
+            return None;
+        }
+
+        let age = self.max_age.as_ref().or(global_config.max_age.as_ref())?;
+        match utils::str_to_duration(age) {
+            Ok(age) => Some(age),
+            Err(e) => {
+                eprintln!("invalid max_age: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    /// How many of the most recent downloaded episodes are kept on disk after a sync, `keep`
+    /// exempts the podcast.
+    pub fn keep_recent(&self, global_config: &GlobalConfig) -> Option<usize> {
+        if self.keep.unwrap_or(false) {
+            return None;
+        }
+
+        self.keep_recent.or(global_config.keep_recent)
+    }
+
+    /// The configured `subscribed_at` date. When it's not set, the date of the first sync is
+    /// used instead, so this is `UseGlobal`.
+    pub fn subscribed_at(&self) -> Result<ConfigOption<Unix>, String> {
+        match &self.subscribed_at {
+            ConfigOption::Enabled(date) => utils::date_str_to_unix(date).map(ConfigOption::Enabled),
+            ConfigOption::Disabled => Ok(ConfigOption::Disabled),
+            ConfigOption::UseGlobal => Ok(ConfigOption::UseGlobal),
+        }
+    }
+
+    /// Disabled podcasts are skipped by syncs without fetching their feed.
+    pub fn enabled(&self, global_config: &GlobalConfig) -> bool {
+        self.enabled.or(global_config.enabled).unwrap_or(true)
+    }
+
+    /// The templates of the title and album tags, which default to the titles of the episode
+    /// and the podcast.
+    pub fn tag_templates(&self, global_config: &GlobalConfig) -> (Option<String>, Option<String>) {
+        let title = self
+            .tag_title_template
+            .clone()
+            .or_else(|| global_config.tag_title_template.clone());
+        let album = self
+            .tag_album_template
+            .clone()
+            .or_else(|| global_config.tag_album_template.clone());
+        (title, album)
+    }
+
+    /// What the podcast is called in progress bars and summaries, which is the title recorded
+    /// with `--refresh-titles` if there is one.
+    pub fn display_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.display_title.as_deref().unwrap_or(name)
+    }
+
+    pub fn display_title(&self) -> Option<&str> {
+        self.display_title.as_deref()
+    }
+
+    /// Whether the url in the podcasts file is replaced when the feed permanently redirects.
+    pub fn auto_update_feed_url(&self, global_config: &GlobalConfig) -> bool {
+        self.auto_update_feed_url
+            .or(global_config.auto_update_feed_url)
+            .unwrap_or(false)
+    }
+
+    /// Whether the url in the podcasts file is replaced when the feed declares a new one with
+    /// `<itunes:new-feed-url>`.
+    pub fn follow_new_feed_url(&self, global_config: &GlobalConfig) -> bool {
+        self.follow_new_feed_url
+            .or(global_config.follow_new_feed_url)
+            .unwrap_or(false)
+    }
+
+    /// Whether episodes are downloaded even if the feed blocks them with `<itunes:block>`.
+    pub fn ignore_block(&self, global_config: &GlobalConfig) -> bool {
+        self.ignore_block
+            .or(global_config.ignore_block)
+            .unwrap_or(false)
+    }
+
+    pub fn latest_symlink(&self, global_config: &GlobalConfig) -> bool {
+        self.latest_symlink
+            .or(global_config.latest_symlink)
+            .unwrap_or(false)
+    }
+
+    pub fn generate_playlist(&self, global_config: &GlobalConfig) -> bool {
+        self.generate_playlist
+            .or(global_config.generate_playlist)
+            .unwrap_or(false)
+    }
+
+    pub fn sort_by_episode_number(&self, global_config: &GlobalConfig) -> bool {
+        self.sort_by_episode_number
+            .or(global_config.sort_by_episode_number)
+            .unwrap_or(false)
+    }
+
+    /// Whether every enclosure of an episode is downloaded, rather than just the first audio one.
+    pub fn all_enclosures(&self, global_config: &GlobalConfig) -> bool {
+        self.all_enclosures
+            .or(global_config.all_enclosures)
+            .unwrap_or(false)
+    }
+
+    pub fn on_error(&self, global_config: &GlobalConfig) -> OnError {
+        self.on_error.or(global_config.on_error).unwrap_or_default()
+    }
+
+    /// How many episodes may be downloaded in a single sync, the rest are left for the next one.
+    pub fn max_downloads_per_run(&self, global_config: &GlobalConfig) -> Option<usize> {
+        self.max_downloads_per_run
+            .into_val(global_config.max_downloads_per_run.as_ref())
+    }
+
+    /// Fills in the `${VAR}` placeholders of the url, credentials and headers from the
+    /// environment.
+    fn resolve_env(&mut self) -> Result<(), String> {
+        self.url = utils::expand_env(&self.url)?;
+
+        for value in [&mut self.username, &mut self.password]
+            .into_iter()
+            .flatten()
+        {
+            *value = utils::expand_env(value)?;
+        }
+
+        for value in self.headers.values_mut() {
+            *value = utils::expand_env(value)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn request_settings(&self) -> RequestSettings {
+        RequestSettings {
+            username: self.username.clone(),
+            password: self.password.clone(),
+            user_agent: self.user_agent.clone(),
+            headers: self.headers.clone(),
         }
     }
 
@@ -870,6 +3139,14 @@ impl PodcastConfig {
             return false;
         }
 
+        if self.published_after.is_some() || self.published_before.is_some() {
+            return false;
+        }
+
+        if self.latest_episodes.is_some() {
+            return false;
+        }
+
         self.earliest_date = ConfigOption::Enabled(current_date.clone());
 
         true
@@ -900,3 +3177,68 @@ impl LogConfig {
         self == &Self::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shaped like the exports of common podcast apps, with folders nested two levels deep.
+    const OPML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head><title>Podcast subscriptions</title></head>
+  <body>
+    <outline text="Tech" title="Tech">
+      <outline type="rss" text="Syntax" title="Syntax" xmlUrl="https://feed.syntax.fm/rss" htmlUrl="https://syntax.fm"/>
+      <outline text="Rust">
+        <outline type="rss" text="Rustacean Station" xmlUrl="https://rustacean-station.org/podcast.rss"/>
+      </outline>
+    </outline>
+    <outline type="rss" text="Hardcore History" title="Dan Carlin's Hardcore History" xmlUrl="https://feeds.feedburner.com/dancarlin/history"/>
+  </body>
+</opml>"#;
+
+    fn import(prefix_folders: bool) -> HashMap<String, String> {
+        let path = std::env::temp_dir().join(format!(
+            "talecast-test-{}-{}.opml",
+            std::process::id(),
+            prefix_folders
+        ));
+        fs::write(&path, OPML).unwrap();
+        let podcasts = PodcastConfigs::from_opml(&path, prefix_folders);
+        fs::remove_file(&path).unwrap();
+
+        podcasts
+            .0
+            .into_iter()
+            .map(|(name, config)| (name, config.url))
+            .collect()
+    }
+
+    #[test]
+    fn opml_import_flattens_nested_folders() {
+        let podcasts = import(false);
+        assert_eq!(podcasts.len(), 3);
+        assert_eq!(podcasts["Syntax"], "https://feed.syntax.fm/rss");
+        assert_eq!(
+            podcasts["Rustacean Station"],
+            "https://rustacean-station.org/podcast.rss"
+        );
+        assert_eq!(
+            podcasts["Dan Carlin's Hardcore History"],
+            "https://feeds.feedburner.com/dancarlin/history"
+        );
+    }
+
+    #[test]
+    fn opml_import_prefixes_folders() {
+        let podcasts = import(true);
+        assert_eq!(podcasts.len(), 3);
+        assert_eq!(podcasts["Tech - Syntax"], "https://feed.syntax.fm/rss");
+        assert_eq!(
+            podcasts["Tech - Rust - Rustacean Station"],
+            "https://rustacean-station.org/podcast.rss"
+        );
+        // Podcasts outside of any folder keep their name.
+        assert!(podcasts.contains_key("Dan Carlin's Hardcore History"));
+    }
+}
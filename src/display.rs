@@ -1,18 +1,30 @@
 use crate::config::IndicatifSettings;
 use crate::episode::Episode;
+use crate::observer::SyncObserver;
 use crate::utils;
 use indicatif::MultiProgress;
 use indicatif::ProgressBar;
 use indicatif::ProgressStyle;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
 
-#[derive(Debug)]
+/// Shows the progress of a podcast in the terminal, and passes it on to the observer if any.
 pub struct DownloadBar {
     bar: Option<ProgressBar>,
     podcast_name: String,
     longest_podcast_name: usize,
     settings: Arc<IndicatifSettings>,
     completed: bool,
+    mp: MultiProgress,
+    observer: Option<Arc<dyn SyncObserver>>,
+    /// The title and size of the episode being downloaded, for the observer.
+    episode: Option<String>,
+    total_size: AtomicU64,
 }
 
 impl DownloadBar {
@@ -34,6 +46,43 @@ impl DownloadBar {
             podcast_name,
             longest_podcast_name,
             completed: false,
+            mp: mp.clone(),
+            observer: None,
+            episode: None,
+            total_size: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_observer(mut self, observer: Option<Arc<dyn SyncObserver>>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Creates a new bar for the same podcast, placed right below this one.
+    pub fn child(&self) -> Self {
+        let bar = self
+            .bar
+            .as_ref()
+            .map(|pb| self.mp.insert_after(pb, ProgressBar::new_spinner()));
+
+        Self {
+            bar,
+            podcast_name: self.podcast_name.clone(),
+            longest_podcast_name: self.longest_podcast_name,
+            settings: Arc::clone(&self.settings),
+            completed: false,
+            mp: self.mp.clone(),
+            observer: self.observer.clone(),
+            episode: None,
+            total_size: AtomicU64::new(0),
+        }
+    }
+
+    /// Removes the bar from the terminal.
+    pub fn clear(&self) {
+        if let Some(pb) = &self.bar {
+            pb.finish_and_clear();
+            self.mp.remove(pb);
         }
     }
 
@@ -67,6 +116,16 @@ impl DownloadBar {
         format!("{}{}", self.prefix(), msg)
     }
 
+    /// Shown while waiting for other podcasts to finish syncing.
+    pub fn waiting(&self) {
+        if let Some(pb) = &self.bar {
+            let template = IndicatifSettings::waiting_template();
+            pb.set_style(ProgressStyle::default_bar().template(&template).unwrap());
+            pb.set_message(self.prefix());
+            pb.enable_steady_tick(self.settings.spinner_speed());
+        }
+    }
+
     pub fn fetching(&self) {
         if let Some(pb) = &self.bar {
             let template = IndicatifSettings::podcast_fetch_template();
@@ -86,7 +145,31 @@ impl DownloadBar {
         }
     }
 
-    pub fn begin_download(&self, episode: &Episode, index: usize, episode_qty: usize) {
+    /// Status of the podcast when multiple episodes are downloading at the same time.
+    pub fn concurrent_status(&self, finished: usize, episode_qty: usize) {
+        if let Some(pb) = &self.bar {
+            self.set_template(&IndicatifSettings::concurrent_status_template());
+            pb.set_message(format!(
+                "{}{}/{} episodes downloaded",
+                self.prefix(),
+                finished,
+                episode_qty
+            ));
+        }
+    }
+
+    pub fn feed_fetched(&self, episode_qty: usize) {
+        if let Some(observer) = &self.observer {
+            observer.feed_fetched(&self.podcast_name, episode_qty);
+        }
+    }
+
+    pub fn begin_download(&mut self, episode: &Episode, index: usize, episode_qty: usize) {
+        if let Some(observer) = &self.observer {
+            observer.episode_started(&self.podcast_name, episode.attrs.title());
+        }
+        self.episode = Some(episode.attrs.title().to_string());
+
         if let Some(pb) = &self.bar {
             let fitted_episode_title = {
                 let title_length = self.settings.title_length();
@@ -108,6 +191,58 @@ impl DownloadBar {
         }
     }
 
+    /// Shown while a downloaded episode is processed, as the next one is downloading.
+    pub fn processing(&mut self, episode: &Episode) {
+        self.episode = Some(episode.attrs.title().to_string());
+
+        if let Some(pb) = &self.bar {
+            self.set_template(&IndicatifSettings::concurrent_status_template());
+            let title =
+                utils::truncate_string(episode.attrs.title(), self.settings.title_length(), true);
+            pb.set_message(format!("{}processing {}", self.prefix(), title));
+            pb.enable_steady_tick(self.settings.spinner_speed());
+        }
+    }
+
+    /// Appends the retry status to the current message.
+    pub fn retrying(&self, attempt: u32, max_retries: u32) {
+        self.log_info(format!("retrying ({}/{})", attempt, max_retries));
+        if let Some(pb) = &self.bar {
+            let msg = pb.message();
+            let msg = msg.split(Self::RETRY_MARKER).next().unwrap_or_default();
+            pb.set_message(format!(
+                "{}{}({}/{}) ",
+                msg,
+                Self::RETRY_MARKER,
+                attempt,
+                max_retries
+            ));
+        }
+    }
+
+    /// Shows that the server rate-limited the request, and how long is waited before retrying.
+    pub fn rate_limited(&self, delay: std::time::Duration) {
+        let status = format!("rate-limited, waiting {}s", delay.as_secs());
+        self.log_warn(&status);
+        if let Some(pb) = &self.bar {
+            let msg = pb.message();
+            let msg = msg.split(Self::RETRY_MARKER).next().unwrap_or_default();
+            pb.set_message(format!("{}{}({}) ", msg, Self::RETRY_MARKER, status));
+        }
+    }
+
+    /// Removes the retry status from the message.
+    pub fn retry_done(&self) {
+        if let Some(pb) = &self.bar {
+            let msg = pb.message();
+            if let Some((msg, _)) = msg.split_once(Self::RETRY_MARKER) {
+                pb.set_message(msg.to_string());
+            }
+        }
+    }
+
+    const RETRY_MARKER: &'static str = "retrying ";
+
     pub fn set_template(&self, style: &str) {
         if let Some(pb) = &self.bar {
             pb.set_style(ProgressStyle::default_bar().template(style).unwrap());
@@ -119,7 +254,10 @@ impl DownloadBar {
         self.set_template(&template);
     }
 
+    /// A total size of 0 means it's unknown.
     pub fn init_download_bar(&self, start_point: u64, total_size: u64) {
+        self.total_size.store(total_size, Ordering::Relaxed);
+        self.notify_progress(start_point);
         if let Some(pb) = &self.bar {
             pb.set_length(total_size);
             pb.set_position(start_point);
@@ -127,26 +265,63 @@ impl DownloadBar {
     }
 
     pub fn set_progress(&self, progress: u64) {
+        self.notify_progress(progress);
         if let Some(pb) = &self.bar {
             pb.set_position(progress);
         }
     }
 
-    pub fn error(&mut self, msg: &str) {
+    /// Only the progress of episodes is passed on, not that of the feed.
+    fn notify_progress(&self, progress: u64) {
+        if let (Some(observer), Some(episode)) = (&self.observer, &self.episode) {
+            let total_size = self.total_size.load(Ordering::Relaxed);
+            let total_size = (total_size > 0).then_some(total_size);
+            observer.progress(&self.podcast_name, episode, progress, total_size);
+        }
+    }
+
+    pub fn finish_download(&mut self, path: &Path) {
+        if let (Some(observer), Some(episode)) = (&self.observer, self.episode.take()) {
+            observer.episode_finished(&self.podcast_name, &episode, path);
+        }
+    }
+
+    pub fn error(&mut self, error: &(impl fmt::Display + ?Sized)) {
         if self.completed {
             return;
         }
 
+        let msg = error.to_string();
+        self.log_error(&msg);
+        if let Some(observer) = &self.observer {
+            observer.error(&self.podcast_name, &msg);
+        }
         if let Some(pb) = &self.bar {
-            self.log_error(msg);
             let template = self.settings.error_template();
             self.set_template(&template);
-            let msg = self.msg_with_prefix(msg);
+            let msg = self.msg_with_prefix(&msg);
             pb.finish_with_message(msg);
             self.completed = true;
         }
     }
 
+    /// Completes the podcast when only some of the pending episodes were downloaded.
+    pub fn complete_deferred(&mut self, downloaded: usize, pending: usize) {
+        if self.completed {
+            return;
+        }
+
+        if let Some(pb) = &self.bar {
+            let template = self.settings.completion_template();
+            self.set_template(&template);
+            pb.finish_with_message(format!(
+                "{} ({} of {} downloaded, rest deferred)",
+                self.podcast_name, downloaded, pending
+            ));
+            self.completed = true;
+        }
+    }
+
     pub fn complete(&mut self) {
         if self.completed {
             return;
@@ -160,3 +335,67 @@ impl DownloadBar {
         }
     }
 }
+
+/// Prints the progress of a sync as lines of status instead of drawing bars, for when there's no
+/// terminal to redraw them in, like the journal of a systemd service.
+#[derive(Default)]
+pub struct PlainProgress {
+    /// When the progress of each downloading episode was last printed, by podcast and title.
+    printed_at: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl PlainProgress {
+    /// How often the progress of a download is printed.
+    const INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+}
+
+impl SyncObserver for PlainProgress {
+    fn feed_fetched(&self, podcast: &str, episodes: usize) {
+        eprintln!("{}: fetched feed with {} episodes", podcast, episodes);
+    }
+
+    fn episode_started(&self, podcast: &str, episode: &str) {
+        eprintln!("{}: downloading {}", podcast, episode);
+        self.printed_at
+            .lock()
+            .unwrap()
+            .insert((podcast.to_string(), episode.to_string()), Instant::now());
+    }
+
+    fn progress(&self, podcast: &str, episode: &str, downloaded: u64, total: Option<u64>) {
+        let mut printed_at = self.printed_at.lock().unwrap();
+        let key = (podcast.to_string(), episode.to_string());
+        if printed_at
+            .get(&key)
+            .is_some_and(|printed| printed.elapsed() < Self::INTERVAL)
+        {
+            return;
+        }
+        printed_at.insert(key, Instant::now());
+
+        let downloaded_bytes = indicatif::HumanBytes(downloaded);
+        match total.filter(|total| *total > 0) {
+            Some(total) => eprintln!(
+                "{}: {} {}% ({}/{})",
+                podcast,
+                episode,
+                downloaded * 100 / total,
+                downloaded_bytes,
+                indicatif::HumanBytes(total)
+            ),
+            None => eprintln!("{}: {} {}", podcast, episode, downloaded_bytes),
+        }
+    }
+
+    fn episode_finished(&self, podcast: &str, episode: &str, path: &Path) {
+        self.printed_at
+            .lock()
+            .unwrap()
+            .remove(&(podcast.to_string(), episode.to_string()));
+        eprintln!("{}: downloaded {} to {}", podcast, episode, path.display());
+    }
+
+    fn error(&self, podcast: &str, error: &str) {
+        eprintln!("{}: failed: {}", podcast, error);
+    }
+}
@@ -3,6 +3,11 @@ use crate::utils;
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
+use std::sync::Mutex;
+
+/// Serializes the writes to tracker files, as episodes are downloaded in parallel and several
+/// podcasts may share a tracker file.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
 
 /// Keeps track of which episodes have already been downloaded.
 #[derive(Debug, Default)]
@@ -34,9 +39,42 @@ impl DownloadedEpisodes {
         Self(hashmap)
     }
 
-    pub fn append(path: &Path, id: &str, episode: &DownloadedEpisode) -> Result<(), String> {
-        use std::io::Write;
+    /// Removes the episode from the tracker file, so it's downloaded again if it's pending.
+    pub fn remove(path: &Path, episode_id: &str) -> Result<(), String> {
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let s = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(_) => return Err("failed to read tracker file".to_string()),
+        };
+
+        let mut kept = String::with_capacity(s.len());
+        for line in s.lines() {
+            if line.split_whitespace().next() != Some(episode_id) {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+
+        write_atomic(path, &kept)
+    }
 
+    /// When the episode was downloaded, according to the tracker file.
+    pub fn download_time(path: &Path, episode_id: &str) -> Option<std::time::Duration> {
+        let s = fs::read_to_string(path).ok()?;
+
+        s.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            if parts.next() != Some(episode_id) {
+                return None;
+            }
+            let secs = parts.next()?.parse().ok()?;
+            Some(std::time::Duration::from_secs(secs))
+        })
+    }
+
+    pub fn append(path: &Path, id: &str, episode: &DownloadedEpisode) -> Result<(), String> {
         if path.is_dir() {
             eprintln!("error: invalid download tracker path: {:?}", path);
             eprintln!("download tracker cannot point to a directory");
@@ -47,21 +85,111 @@ impl DownloadedEpisodes {
             utils::create_dir(&parent)
         }
 
-        let mut file = fs::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(path)
-            .map_err(|_| "failed to open tracker file".to_string())?;
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
 
-        writeln!(
-            file,
-            "{} {} \"{}\"",
+        let existing = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(_) => return Err("failed to read tracker file".to_string()),
+        };
+
+        // An episode that's downloaded again replaces its old entry, so the download time is
+        // the latest one. Going line by line also keeps a line that was cut off from swallowing
+        // the new entry.
+        let mut s = String::with_capacity(existing.len());
+        for line in existing.lines() {
+            if line.split_whitespace().next() != Some(id) {
+                s.push_str(line);
+                s.push('\n');
+            }
+        }
+
+        s.push_str(&format!(
+            "{} {} \"{}\"\n",
             id,
             utils::current_unix().as_secs(),
             episode.inner().attrs.title()
-        )
-        .unwrap();
+        ));
+
+        write_atomic(path, &s)
+    }
+}
+
+/// Replaces the tracker file by writing to a temporary file and renaming it, so that a sync
+/// that's killed halfway never leaves a truncated tracker behind.
+fn write_atomic(path: &Path, contents: &str) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = std::path::PathBuf::from(tmp_path);
+
+    let write = || -> std::io::Result<()> {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    };
+
+    write().map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        format!("failed to write tracker file: {}", e)
+    })
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::episode::{Attributes, Episode, RawEpisode};
+    use crate::podcast::RawPodcast;
+    use std::sync::Arc;
+
+    fn episode(title: &str) -> Episode {
+        let raw = serde_json::json!({
+            "title": title,
+            "guid": title,
+            "enclosure": { "@url": "https://example.com/episode.mp3" },
+        });
+        let serde_json::Value::Object(raw) = raw else {
+            unreachable!()
+        };
+        let attrs = Attributes::new(RawEpisode::new(raw)).unwrap();
+        let podcast = Arc::new(RawPodcast::new(Default::default()));
+        Episode::new(attrs, 0, Default::default(), None, None, podcast)
+    }
+
+    #[test]
+    fn concurrent_appends_keep_every_episode() {
+        let path =
+            std::env::temp_dir().join(format!("talecast-test-{}-tracker.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let threads = 8;
+        let per_thread = 25;
+        std::thread::scope(|scope| {
+            for thread in 0..threads {
+                let path = &path;
+                scope.spawn(move || {
+                    for n in 0..per_thread {
+                        let id = format!("episode-{}-{}", thread, n);
+                        let episode = episode(&id);
+                        let downloaded =
+                            DownloadedEpisode::new(&episode, path.clone(), String::new());
+                        DownloadedEpisodes::append(path, &id, &downloaded).unwrap();
+                    }
+                });
+            }
+        });
+
+        let tracker = DownloadedEpisodes::load(&path);
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(lines, threads * per_thread);
+        for thread in 0..threads {
+            for n in 0..per_thread {
+                assert!(tracker.contains_episode(&format!("episode-{}-{}", thread, n)));
+            }
+        }
     }
 }
@@ -3,14 +3,22 @@ use crate::config::Config;
 use crate::config::DownloadMode;
 use crate::display::DownloadBar;
 use crate::download_tracker::DownloadedEpisodes;
+use crate::episode_index;
+use crate::error::CringeError;
+use crate::podcast::Funding;
+use crate::podcast::RawPodcast;
+use crate::tags::{Container, TagWriter};
 use crate::utils;
+use crate::vorbis::VorbisComments;
 use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
 use std::cmp;
 use std::fs;
 use std::io::Seek;
 use std::io::Write as IOWrite;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time;
 use tokio::task::JoinHandle;
 
@@ -57,57 +65,404 @@ impl RawEpisode {
     }
 }
 
+/// A transcript of an episode, from the `<podcast:transcript>` tag.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub url: String,
+    pub mime: Option<String>,
+}
+
+impl Transcript {
+    /// The file extension of the transcript, if it's in a supported format.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self.mime.as_deref()? {
+            "text/vtt" => Some("vtt"),
+            "application/srt" | "application/x-subrip" | "text/srt" => Some("srt"),
+            "text/plain" => Some("txt"),
+            _ => None,
+        }
+    }
+
+    /// Lower is better. VTT is preferred over SRT which is preferred over plaintext.
+    fn priority(&self) -> Option<usize> {
+        let ext = self.extension()?;
+        ["vtt", "srt", "txt"].iter().position(|x| x == &ext)
+    }
+}
+
+/// A highlight of an episode, from the `<podcast:soundbite>` tag.
+#[derive(Debug, Clone)]
+pub struct Soundbite {
+    pub start: time::Duration,
+    pub duration: time::Duration,
+    pub title: Option<String>,
+}
+
+/// Another version of the enclosure, from the `<podcast:alternateEnclosure>` tag.
+#[derive(Debug, Clone)]
+pub struct AlternateEnclosure {
+    pub url: String,
+    pub mime: String,
+    pub length: Option<u64>,
+    /// In bits per second.
+    pub bitrate: Option<f64>,
+    pub codecs: Option<String>,
+}
+
+impl AlternateEnclosure {
+    /// Whether the format is either a mime type like `audio/opus`, or a codec or extension
+    /// like `opus`.
+    fn matches_format(&self, format: &str) -> bool {
+        let format = format.trim().to_lowercase();
+        let mime = self.mime.to_lowercase();
+        let essence = mime.split(';').next().unwrap_or_default().trim();
+
+        if format.contains('/') {
+            return essence == format;
+        }
+
+        let codec_matches = self.codecs.as_ref().is_some_and(|codecs| {
+            codecs
+                .to_lowercase()
+                .split(',')
+                .any(|codec| codec.trim().starts_with(&format))
+        });
+
+        // The mime type of m4a files is usually given as `audio/mp4`, which isn't mapped to it.
+        let m4a = format == "m4a" && essence == "audio/mp4";
+
+        codec_matches
+            || m4a
+            || essence.split('/').nth(1) == Some(format.as_str())
+            || mime_guess::get_mime_extensions_str(essence)
+                .is_some_and(|extensions| extensions.contains(&format.as_str()))
+    }
+}
+
+/// An `<enclosure>` of an episode. A few feeds attach more than one, like an audio and a video
+/// version, or the cover image.
+#[derive(Debug, Clone)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime: Option<String>,
+    pub length: Option<u64>,
+}
+
+impl Enclosure {
+    fn parse(obj: &serde_json::Map<String, serde_json::Value>) -> Option<Self> {
+        let url = ["@url", "url", "@href", "src", "uri"]
+            .iter()
+            .find_map(|key| obj.get(*key)?.as_str())?
+            .trim();
+
+        Some(Self {
+            url: Some(url).filter(|url| utils::is_http_url(url))?.to_string(),
+            mime: obj.get("@type").and_then(|x| Some(x.as_str()?.to_string())),
+            length: obj
+                .get("@length")
+                .and_then(utils::val_to_number)
+                .map(|length| length as u64)
+                .filter(|length| *length > 0),
+        })
+    }
+
+    fn is_audio(&self) -> bool {
+        self.mime
+            .as_deref()
+            .is_some_and(|mime| mime.trim().to_lowercase().starts_with("audio/"))
+    }
+
+    /// Images are recognized by their extension as well, since their type is often missing.
+    fn is_image(&self) -> bool {
+        if let Some(mime) = &self.mime {
+            return mime.trim().to_lowercase().starts_with("image/");
+        }
+
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        matches!(
+            extension.as_deref(),
+            Some("jpg" | "jpeg" | "png" | "gif" | "webp")
+        )
+    }
+}
+
+/// A chapter from a `<podcast:chapters>` json file, the times are in seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chapter {
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+    #[serde(default)]
+    pub title: String,
+    /// Chapters that shouldn't be shown in the table of contents, only used for artwork etc.
+    pub toc: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ChaptersFile {
+    chapters: Vec<Chapter>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Attributes {
     pub title: String,
     pub url: String,
     pub mime: Option<String>,
+    pub length: Option<u64>,
     pub guid: String,
-    pub published: time::Duration,
+    pub published: Option<time::Duration>,
+    /// Counts the enclosures after the first one, when all of them are downloaded.
+    pub enclosure_number: Option<usize>,
     pub raw: RawEpisode,
 }
 
 impl Attributes {
     pub fn new(raw: RawEpisode) -> Result<Self, String> {
-        let title = raw.get_string("title")?;
-        let enclosure = raw.get_val("enclosure")?;
+        // Titles end up in file names, so they shouldn't contain any markup.
+        let title = utils::clean_html(&raw.get_string("title")?, true);
 
-        let url = enclosure
-            .get("@url")
-            .ok_or_else(|| "url not found".to_string())?
-            .to_string();
-        let url = utils::trim_quotes(&url);
+        // Items without anything to download, like announcements, are skipped rather than
+        // failing once they're downloaded.
+        if raw.get_val("enclosure").is_err() {
+            return Err(format!("skipping episode without enclosure: {}", title));
+        }
+        let enclosures = Self::parse_enclosures(&raw);
+        let Some(enclosure) = enclosures
+            .iter()
+            .find(|enclosure| enclosure.is_audio())
+            .or(enclosures.first())
+        else {
+            return Err(format!(
+                "skipping episode without a valid enclosure url: {}",
+                title
+            ));
+        };
+        let Enclosure { url, mime, length } = enclosure.clone();
 
-        let mime = enclosure
-            .get("@type")
-            .and_then(|x| Some(x.as_str()?.to_string()));
+        // Episodes without a valid date are kept, they're sorted by their position in the feed.
+        let published = raw
+            .get_str("pubDate")
+            .ok()
+            .and_then(|date| utils::parse_pubdate(date).ok());
 
-        let published = raw.get_str("pubDate")?;
-        let published = utils::date_str_to_unix(published)?;
-        let guid = raw.get_string("guid")?;
+        // Not all feeds have guids, in which case the enclosure url is the most stable identity.
+        let guid = Self::parse_guid(&raw).unwrap_or_else(|| url.clone());
 
         Ok(Self {
             title,
             url,
             mime,
+            length,
             guid,
             published,
+            enclosure_number: None,
             raw,
         })
     }
 
-    pub fn published(&self) -> time::Duration {
+    /// The enclosures of the episode in the order of the feed, leaving out the images.
+    pub fn enclosures(&self) -> Vec<Enclosure> {
+        Self::parse_enclosures(&self.raw)
+    }
+
+    fn parse_enclosures(raw: &RawEpisode) -> Vec<Enclosure> {
+        let Ok(val) = raw.get_val("enclosure") else {
+            return vec![];
+        };
+
+        utils::val_to_objects(val)
+            .into_iter()
+            .filter_map(Enclosure::parse)
+            .filter(|enclosure| !enclosure.is_image())
+            .collect()
+    }
+
+    /// The other enclosures as episodes of their own, to download along with this one.
+    ///
+    /// They get a guid of their own so they're tracked separately.
+    pub fn extra_enclosures(&self) -> Vec<Self> {
+        self.enclosures()
+            .into_iter()
+            .filter(|enclosure| enclosure.url != self.url)
+            .enumerate()
+            .map(|(idx, enclosure)| {
+                let number = idx + 2;
+                Self {
+                    url: enclosure.url,
+                    mime: enclosure.mime,
+                    length: enclosure.length,
+                    guid: format!("{}#{}", self.guid, number),
+                    enclosure_number: Some(number),
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// The versions of the enclosure in `<podcast:alternateEnclosure>` tags.
+    ///
+    /// Only the first http source of each is used, other kinds like torrents aren't supported.
+    pub fn alternate_enclosures(&self) -> Vec<AlternateEnclosure> {
+        let Ok(val) = self.raw.get_val("alternateEnclosure") else {
+            return vec![];
+        };
+
+        utils::val_to_objects(val)
+            .into_iter()
+            .filter_map(|obj| {
+                let url = utils::val_to_objects(obj.get("source")?)
+                    .into_iter()
+                    .filter_map(|source| source.get("@uri")?.as_str())
+                    .find(|uri| utils::is_http_url(uri))?
+                    .to_string();
+
+                Some(AlternateEnclosure {
+                    url,
+                    mime: obj.get("@type")?.as_str()?.to_string(),
+                    length: obj
+                        .get("@length")
+                        .and_then(utils::val_to_number)
+                        .map(|length| length as u64),
+                    bitrate: obj.get("@bitrate").and_then(utils::val_to_number),
+                    codecs: obj
+                        .get("@codecs")
+                        .and_then(|codecs| Some(codecs.as_str()?.to_string())),
+                })
+            })
+            .collect()
+    }
+
+    /// Switches to the version of the enclosure that best matches the preferred format and
+    /// bitrate, in kbps, among the other enclosures and the alternate ones. The standard
+    /// enclosure is kept if none of them match.
+    ///
+    /// With a preferred bitrate, the highest bitrate that doesn't exceed it is picked, or the
+    /// lowest one if they all do.
+    pub fn select_enclosure(&mut self, format: Option<&str>, bitrate: Option<u64>) {
+        if format.is_none() && bitrate.is_none() {
+            return;
+        }
+
+        let standard = AlternateEnclosure {
+            url: self.url.clone(),
+            mime: self.mime.clone().unwrap_or_default(),
+            length: self.length,
+            bitrate: None,
+            codecs: None,
+        };
+
+        let mut candidates = vec![standard];
+        candidates.extend(
+            self.enclosures()
+                .into_iter()
+                .filter(|enclosure| enclosure.url != self.url)
+                .map(|enclosure| AlternateEnclosure {
+                    url: enclosure.url,
+                    mime: enclosure.mime.unwrap_or_default(),
+                    length: enclosure.length,
+                    bitrate: None,
+                    codecs: None,
+                }),
+        );
+        candidates.extend(self.alternate_enclosures());
+        if candidates.len() == 1 {
+            return;
+        }
+
+        if let Some(format) = format {
+            candidates.retain(|candidate| candidate.matches_format(format));
+        }
+
+        let selected = match bitrate {
+            Some(kbps) => {
+                let max = kbps as f64 * 1000.;
+                let with_bitrate: Vec<(&AlternateEnclosure, f64)> = candidates
+                    .iter()
+                    .filter_map(|candidate| Some((candidate, candidate.bitrate?)))
+                    .collect();
+
+                let below = with_bitrate
+                    .iter()
+                    .filter(|(_, bitrate)| *bitrate <= max)
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                let lowest = with_bitrate.iter().min_by(|a, b| a.1.total_cmp(&b.1));
+
+                below
+                    .or(lowest)
+                    .map(|(candidate, _)| *candidate)
+                    .or(candidates.first())
+            }
+            None => candidates.first(),
+        };
+
+        if let Some(selected) = selected.filter(|selected| selected.url != self.url) {
+            self.url = selected.url.clone();
+            self.mime = Some(selected.mime.clone());
+            self.length = selected.length;
+        }
+    }
+
+    /// Reads the `<guid>` element, which is either plain text or has an `isPermaLink` attribute.
+    ///
+    /// Permalinks are used as-is, the same as opaque guids, since both identify the episode.
+    fn parse_guid(raw: &RawEpisode) -> Option<String> {
+        let val = raw.get_val("guid").ok()?;
+        let val = match val.as_object() {
+            Some(obj) => obj.get("#text")?,
+            None => val,
+        };
+
+        // Numeric guids are parsed as numbers rather than strings.
+        let guid = match val {
+            serde_json::Value::String(s) => s.trim().to_string(),
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => return None,
+        };
+
+        (!guid.is_empty()).then_some(guid)
+    }
+
+    pub fn published(&self) -> Option<time::Duration> {
         self.published
     }
 
-    pub fn _mime(&self) -> Option<&str> {
+    /// The `type` attribute of the enclosure.
+    pub fn enclosure_mime(&self) -> Option<&str> {
         self.mime.as_deref()
     }
 
+    /// The file extension of the enclosure according to its mime type, if it's a common one.
+    ///
+    /// This is more reliable than the extension of the url, which may be missing or followed by
+    /// tracking arguments.
+    pub fn enclosure_extension(&self) -> Option<&'static str> {
+        let mime = self.enclosure_mime()?.to_lowercase();
+        match mime.split(';').next().unwrap_or_default().trim() {
+            "audio/mpeg" | "audio/mp3" | "audio/mpeg3" => Some("mp3"),
+            "audio/mp4" | "audio/x-m4a" | "audio/m4a" => Some("m4a"),
+            "audio/x-m4b" => Some("m4b"),
+            "audio/ogg" | "audio/vorbis" => Some("ogg"),
+            "audio/opus" => Some("opus"),
+            "audio/flac" | "audio/x-flac" => Some("flac"),
+            "audio/wav" | "audio/x-wav" => Some("wav"),
+            "video/mp4" => Some("mp4"),
+            _ => None,
+        }
+    }
+
     pub fn title(&self) -> &str {
         &self.title
     }
 
+    /// The title as it's written in the feed, without decoding entities or stripping markup.
+    pub fn raw_title(&self) -> Result<&str, String> {
+        self.get_str("title")
+    }
+
     pub fn guid(&self) -> &str {
         &self.guid
     }
@@ -120,6 +475,26 @@ impl Attributes {
         self.raw.get_str(key)
     }
 
+    /// Like [`Self::get_str`], but also handles values that were parsed as numbers.
+    pub fn get_text(&self, key: &str) -> Option<String> {
+        let val = self.raw.get_val(key).ok()?;
+        let val = val
+            .as_object()
+            .and_then(|obj| obj.get("#text"))
+            .unwrap_or(val);
+
+        match val {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Size of the enclosure in bytes, as advertised by the feed.
+    pub fn enclosure_length(&self) -> Option<u64> {
+        self.length
+    }
+
     pub fn image(&self) -> Result<&str, String> {
         let key = "itunes:image";
         self.raw.get_url(key)
@@ -128,18 +503,138 @@ impl Attributes {
         self.get_str("author")
     }
 
-    pub fn description(&self) -> Result<&str, String> {
-        self.get_str("description")
+    /// The description as plain text.
+    pub fn description(&self) -> Result<String, String> {
+        self.raw_description()
+            .map(|description| utils::clean_html(description, true))
+    }
+
+    /// The description as it's written in the feed, which is often HTML.
+    ///
+    /// The full show notes of `content:encoded` are preferred over the description, which some
+    /// feeds keep short, and `itunes:summary` is the last resort.
+    pub fn raw_description(&self) -> Result<&str, String> {
+        // The namespace prefix of `content:encoded` is dropped when the xml is parsed.
+        self.get_str("encoded")
+            .or_else(|_| self.get_str("description"))
+            .or_else(|_| self.get_str("itunes:summary"))
+            .map_err(|_| "no description".to_string())
     }
 
-    pub fn itunes_episode(&self) -> Result<&str, String> {
-        let key = "itunes:episode";
-        self.get_str(&key)
+    pub fn explicit(&self) -> Option<bool> {
+        utils::parse_explicit(&self.get_text("itunes:explicit")?)
     }
 
-    pub fn itunes_duration(&self) -> Result<&str, String> {
-        let key = "itunes:duration";
-        self.get_str(&key)
+    /// Whether the episode asks not to be distributed with `<itunes:block>`.
+    pub fn blocked(&self) -> bool {
+        self.get_text("itunes:block")
+            .is_some_and(|block| utils::parse_block(&block))
+    }
+
+    pub fn episode_number(&self) -> Option<u32> {
+        self.get_text("itunes:episode")?.trim().parse().ok()
+    }
+
+    pub fn season(&self) -> Option<u32> {
+        self.get_text("itunes:season")?.trim().parse().ok()
+    }
+
+    /// The `itunes:duration` of the episode, either in seconds or in the `HH:MM:SS` format.
+    pub fn duration(&self) -> Option<time::Duration> {
+        let val = self.raw.get_val("itunes:duration").ok()?;
+
+        // Durations in plain seconds are parsed as numbers rather than strings.
+        // Values like `inf` or `1e20` are rejected rather than overflowing the duration.
+        if let Some(secs) = val.as_f64() {
+            return time::Duration::try_from_secs_f64(secs).ok();
+        }
+
+        let mut secs = 0.;
+        for part in utils::val_to_str(val)?.trim().split(':') {
+            let part: f64 = part.trim().parse().ok()?;
+            if !part.is_finite() || part < 0. {
+                return None;
+            }
+            secs = secs * 60. + part;
+        }
+
+        time::Duration::try_from_secs_f64(secs).ok()
+    }
+
+    pub fn transcripts(&self) -> Vec<Transcript> {
+        let Ok(val) = self.raw.get_val("transcript") else {
+            return vec![];
+        };
+
+        utils::val_to_objects(val)
+            .into_iter()
+            .filter_map(|obj| {
+                let url = obj.get("@url")?.as_str()?.to_string();
+                let mime = obj.get("@type").and_then(|x| Some(x.as_str()?.to_string()));
+
+                Some(Transcript { url, mime })
+            })
+            .collect()
+    }
+
+    /// The soundbites with a valid start time and a duration, in seconds.
+    pub fn soundbites(&self) -> Vec<Soundbite> {
+        let Ok(val) = self.raw.get_val("soundbite") else {
+            return vec![];
+        };
+
+        let secs = |obj: &serde_json::Map<String, serde_json::Value>, key: &str| {
+            let secs = match obj.get(key)? {
+                serde_json::Value::Number(n) => n.as_f64()?,
+                serde_json::Value::String(s) => s.trim().parse().ok()?,
+                _ => return None,
+            };
+            // Negative, infinite and out of range times are left out.
+            time::Duration::try_from_secs_f64(secs).ok()
+        };
+
+        utils::val_to_objects(val)
+            .into_iter()
+            .filter_map(|obj| {
+                let start = secs(obj, "@startTime")?;
+                let duration = secs(obj, "@duration").filter(|d| !d.is_zero())?;
+                let title = obj
+                    .get("#text")
+                    .and_then(|title| title.as_str())
+                    .map(|title| title.trim().to_string())
+                    .filter(|title| !title.is_empty());
+
+                Some(Soundbite {
+                    start,
+                    duration,
+                    title,
+                })
+            })
+            .collect()
+    }
+
+    /// Link to the json file with the chapters of the episode.
+    pub fn chapters_url(&self) -> Option<&str> {
+        let val = self.raw.get_val("chapters").ok()?;
+        let links = utils::val_to_objects(val);
+
+        // Other formats are allowed, but json is the one that's specified.
+        links
+            .iter()
+            .find(|link| {
+                link.get("@type").and_then(|ty| ty.as_str()) == Some("application/json+chapters")
+            })
+            .or(links.first())?
+            .get("@url")?
+            .as_str()
+    }
+
+    /// The transcript in the most preferred supported format.
+    pub fn preferred_transcript(&self) -> Option<Transcript> {
+        self.transcripts()
+            .into_iter()
+            .filter(|transcript| transcript.priority().is_some())
+            .min_by_key(|transcript| transcript.priority())
     }
 }
 
@@ -150,6 +645,7 @@ pub struct Episode {
     pub index: usize,
     pub attrs: Attributes,
     pub image_url: Option<String>,
+    pub podcast: Arc<RawPodcast>,
 }
 
 impl Episode {
@@ -159,6 +655,7 @@ impl Episode {
         config: Config,
         tags: Option<id3::Tag>,
         image_url: Option<String>,
+        podcast: Arc<RawPodcast>,
     ) -> Self {
         Self {
             attrs,
@@ -166,9 +663,28 @@ impl Episode {
             tags,
             index,
             image_url,
+            podcast,
+        }
+    }
+
+    /// Episodes without an explicit tag inherit the one of the podcast.
+    pub fn explicit(&self) -> Option<bool> {
+        self.attrs.explicit().or_else(|| self.podcast.explicit())
+    }
+
+    /// Whether the artwork comes from the episode itself or falls back to the one of the podcast.
+    pub fn artwork_source(&self) -> &'static str {
+        match self.attrs.image() {
+            Ok(_) => "episode",
+            Err(_) => "podcast",
         }
     }
 
+    /// A blocked feed blocks all of its episodes.
+    pub fn blocked(&self) -> bool {
+        self.attrs.blocked() || self.podcast.blocked()
+    }
+
     const TITLELEN: usize = 30;
 
     pub fn _log_error(&self, ui: &DownloadBar, msg: impl Into<String>) {
@@ -183,6 +699,12 @@ impl Episode {
         ui.log_warn(msg);
     }
 
+    pub fn log_info(&self, ui: &DownloadBar, msg: impl Into<String>) {
+        let ep_name = utils::truncate_string(self.attrs.title(), Self::TITLELEN, true);
+        let msg = format!("{}: {}", ep_name, msg.into());
+        ui.log_info(msg);
+    }
+
     pub fn log_trace(&self, ui: &DownloadBar, msg: impl Into<String>) {
         let ep_name = utils::truncate_string(self.attrs.title(), Self::TITLELEN, true);
         let msg = format!("{}: {}", ep_name, msg.into());
@@ -195,41 +717,288 @@ impl Episode {
         ui.log_debug(msg);
     }
 
-    fn is_downloaded(&self) -> bool {
+    /// The directory and file name of the episode after it's been downloaded.
+    ///
+    /// The extension is not included since it's only known after downloading.
+    fn destination(&self) -> Result<(PathBuf, String), String> {
+        let config = &self.config;
+
+        let (dir, file_name) = match &config.filename_template {
+            Some(template) => {
+                let path = config.download_path.join(template);
+                let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                    return Err("filename_template evaluated to an invalid path".to_string());
+                };
+                let file_name = file_name.to_string();
+                let dir = path.parent().unwrap_or(&config.download_path).to_path_buf();
+                (dir, file_name)
+            }
+            None => (
+                config.download_path.clone(),
+                sanitize_filename::sanitize(&config.name_pattern),
+            ),
+        };
+
+        let mut file_name = utils::normalize_file_name(&file_name, config.ascii_filenames);
+        if let Some(number) = self.attrs.enclosure_number {
+            file_name.push_str(&format!(" ({})", number));
+        }
+        Ok((dir, file_name))
+    }
+
+    /// Joins the name and extension, truncating the name to fit within `max_filename_length`.
+    fn file_path(&self, dir: &Path, name: String, extension: Option<&str>) -> PathBuf {
+        let max_len = self.config.max_filename_length;
+
+        // The extension is appended rather than set, since the name might contain dots.
+        match extension {
+            Some(extension) => {
+                let ext_len = extension.len() + 1; // + 1 for the dot.
+                let name = utils::truncate_graphemes(&name, max_len.saturating_sub(ext_len));
+                dir.join(format!("{}.{}", name, extension))
+            }
+            None => dir.join(utils::truncate_graphemes(&name, max_len)),
+        }
+    }
+
+    /// Where the episode would end up if it was downloaded now.
+    ///
+    /// The extension is guessed from the mime type or url since there's no response to check.
+    pub fn planned_path(&self) -> Result<PathBuf, String> {
+        let (dir, name) = self.destination()?;
+
+        let url = self.attrs.url();
+        let url = url.split(['?', '#']).next().unwrap_or(url);
+        let extension = self
+            .attrs
+            .enclosure_extension()
+            .map(String::from)
+            .or_else(|| {
+                Path::new(url)
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .map(String::from)
+            })
+            .or_else(|| {
+                let extensions = mime_guess::get_mime_extensions_str(self.attrs.mime.as_ref()?)?;
+                match extensions.contains(&"mp3") {
+                    true => Some("mp3".to_string()),
+                    false => extensions.first().map(|ext| ext.to_string()),
+                }
+            });
+
+        Ok(self.file_path(&dir, name, extension.as_deref()))
+    }
+
+    /// Finds the file of an episode that has been downloaded, either now or in a previous sync.
+    pub fn downloaded_path(&self) -> Option<PathBuf> {
+        if !self.is_downloaded() {
+            return None;
+        }
+
+        self.find_file()
+    }
+
+    /// Finds a file at the destination of the episode, regardless of the download tracker.
+    fn find_file(&self) -> Option<PathBuf> {
+        let (dir, name) = self.destination().ok()?;
+
+        for entry in fs::read_dir(&dir).ok()? {
+            let path = entry.ok()?.path();
+            let Some((stem, ext)) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.rsplit_once('.'))
+            else {
+                continue;
+            };
+
+            // Files next to the episode, like transcripts, share the same name.
+            if ["vtt", "srt", "txt", "json", "partial", "url", "m3u8"].contains(&ext) {
+                continue;
+            }
+
+            // Names that are too long are truncated when renaming.
+            let max_len = self
+                .config
+                .max_filename_length
+                .saturating_sub(ext.len() + 1);
+            if stem == utils::truncate_graphemes(&name, max_len) {
+                return Some(path);
+            }
+        }
+
+        None
+    }
+
+    pub fn is_downloaded(&self) -> bool {
         let id = self.get_id();
         let path = self.tracker_path();
         DownloadedEpisodes::load(&path).contains_episode(&id)
     }
 
+    /// Whether the episode is older than what backlog mode goes back to. Like with
+    /// `earliest_date`, episodes without a date can't be shown to be recent enough.
+    pub fn before_backlog(&self, earliest: Option<time::Duration>) -> bool {
+        earliest.is_some_and(|earliest| {
+            self.attrs
+                .published
+                .is_none_or(|published| earliest > published)
+        })
+    }
+
     pub fn should_download(&self, mode: &DownloadMode, episode_qty: usize) -> bool {
         let passed_filter = match mode {
-            DownloadMode::Backlog { start, interval } => {
+            DownloadMode::Backlog {
+                start,
+                interval,
+                earliest,
+                skipped,
+            } => {
                 let time_passed = utils::current_unix() - *start;
                 let intervals_passed = time_passed.as_secs() / interval.as_secs();
-                intervals_passed >= self.index as u64
+                // The skipped episodes don't hold up the ones after them.
+                !self.before_backlog(*earliest)
+                    && intervals_passed >= self.index.saturating_sub(*skipped) as u64
             }
 
             DownloadMode::Standard {
                 max_time,
                 max_episodes,
                 earliest_date,
+                subscribed_at,
             } => {
-                let max_time_exceeded = max_time.map_or(false, |max_time| {
-                    (utils::current_unix() - self.attrs.published) > max_time
+                // Episodes without a date can't be shown to be recent enough.
+                let max_time_exceeded = max_time.is_some_and(|max_time| {
+                    self.attrs.published.is_none_or(|published| {
+                        utils::current_unix().saturating_sub(published) > max_time
+                    })
+                });
+
+                let max_episodes_exceeded = max_episodes.is_some_and(|max_episodes| {
+                    episode_qty.saturating_sub(max_episodes as usize) > self.index
+                });
+
+                let episode_too_old = earliest_date.is_some_and(|date| {
+                    self.attrs
+                        .published
+                        .is_none_or(|published| date > published)
                 });
 
-                let max_episodes_exceeded = max_episodes.map_or(false, |max_episodes| {
-                    (episode_qty - max_episodes as usize) > self.index
+                // Unlike with `earliest_date`, episodes without a date are let through, since
+                // the subscription applies to every podcast and some feeds don't date episodes.
+                let before_subscription = subscribed_at.is_some_and(|date| {
+                    self.attrs
+                        .published
+                        .is_some_and(|published| date > published)
                 });
 
-                let episode_too_old =
-                    earliest_date.map_or(false, |date| date > self.attrs.published);
+                !max_time_exceeded
+                    && !max_episodes_exceeded
+                    && !episode_too_old
+                    && !before_subscription
+            }
+
+            DownloadMode::LatestN { count } => !self.outside_window(*count, episode_qty),
 
-                !max_time_exceeded && !max_episodes_exceeded && !episode_too_old
+            DownloadMode::DateRange { after, before } => {
+                let Some(published) = self.attrs.published else {
+                    return false;
+                };
+                let too_old = after.is_some_and(|after| published < after);
+                let too_new = before.is_some_and(|before| published >= before);
+                !too_old && !too_new
             }
         };
 
-        passed_filter && !self.is_downloaded()
+        passed_filter && !self.is_downloaded() && !self.exists_on_disk()
+    }
+
+    /// With `skip_if_file_exists` enabled, whether the episode is already in the download path
+    /// even though it's not tracked, e.g. because the tracker file was lost.
+    ///
+    /// Files smaller than the enclosure length are incomplete, unless the file was converted,
+    /// in which case the sizes can't be compared.
+    fn exists_on_disk(&self) -> bool {
+        if !self.config.skip_if_file_exists {
+            return false;
+        }
+
+        let Some(path) = self.find_file() else {
+            return false;
+        };
+
+        let converted =
+            self.config.transcode_to.is_some() || self.config.normalize_loudness.is_some();
+        match self.attrs.enclosure_length().filter(|_| !converted) {
+            Some(expected) => fs::metadata(&path).is_ok_and(|meta| {
+                meta.len() as f64 >= expected as f64 * (1. - DownloadedEpisode::LENGTH_TOLERANCE)
+            }),
+            None => true,
+        }
+    }
+
+    /// Whether the episode is older than the `count` most recent ones.
+    pub fn outside_window(&self, count: usize, episode_qty: usize) -> bool {
+        self.index < episode_qty.saturating_sub(count)
+    }
+
+    /// Deletes a downloaded episode from disk along with its transcript and metadata,
+    /// and removes it from the download tracker.
+    pub fn prune(&self, ui: &DownloadBar) -> Result<(), String> {
+        self.remove_files(ui)?;
+        DownloadedEpisodes::remove(self.tracker_path(), &self.get_id())
+    }
+
+    /// Deletes the file of a downloaded episode along with its transcript and metadata,
+    /// returning how many bytes were freed.
+    ///
+    /// Files outside of the download path are never deleted.
+    pub fn remove_files(&self, ui: &DownloadBar) -> Result<u64, String> {
+        let Some(path) = self.downloaded_path() else {
+            return Ok(0);
+        };
+
+        let inside = match (
+            path.canonicalize(),
+            self.config.download_path.canonicalize(),
+        ) {
+            (Ok(path), Ok(dir)) => path.starts_with(dir),
+            _ => false,
+        };
+        if !inside {
+            return Err(format!("{:?} is outside of the download path", path));
+        }
+
+        self.log_debug(ui, format!("pruning {:?}", &path));
+        let mut freed = fs::metadata(&path).map_or(0, |meta| meta.len());
+        fs::remove_file(&path).map_err(|_| "failed to remove episode file".to_string())?;
+
+        for ext in ["vtt", "srt", "txt", "json"] {
+            let sidecar = path.with_extension(ext);
+            if let Ok(meta) = fs::metadata(&sidecar) {
+                if fs::remove_file(&sidecar).is_ok() {
+                    freed += meta.len();
+                }
+            }
+        }
+
+        Ok(freed)
+    }
+
+    /// How long ago the episode was downloaded, going by the download tracker or otherwise
+    /// the modification time of the file.
+    pub fn download_age(&self) -> Option<time::Duration> {
+        let downloaded_at = DownloadedEpisodes::download_time(self.tracker_path(), &self.get_id())
+            .or_else(|| {
+                let modified = fs::metadata(self.downloaded_path()?)
+                    .ok()?
+                    .modified()
+                    .ok()?;
+                modified.duration_since(time::UNIX_EPOCH).ok()
+            })?;
+
+        Some(utils::current_unix().saturating_sub(downloaded_at))
     }
 
     /// Filename of episode when it's being downloaded.
@@ -238,6 +1007,27 @@ impl Episode {
         format!("{}.partial", file_name)
     }
 
+    fn partial_path(&self) -> PathBuf {
+        self.config
+            .partial_path
+            .clone()
+            .unwrap_or_else(|| self.config.download_path.clone())
+            .join(self.partial_name())
+    }
+
+    /// Deletes a partial download of the episode, so that downloading it again starts over
+    /// instead of resuming.
+    pub fn discard_partial(&self) {
+        let partial_path = self.partial_path();
+        for path in [
+            partial_path.with_extension("url"),
+            partial_path.with_extension("size"),
+            partial_path,
+        ] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
     fn get_id(&self) -> String {
         self.config.id_pattern.replace(" ", "_")
     }
@@ -246,111 +1036,441 @@ impl Episode {
         self.config.tracker_path.as_path()
     }
 
-    fn into_downloaded(&self, path: PathBuf) -> DownloadedEpisode<'_> {
-        DownloadedEpisode::new(self, path)
+    fn into_downloaded(&self, path: PathBuf, url: String) -> DownloadedEpisode<'_> {
+        DownloadedEpisode::new(self, path, url)
     }
 
     pub async fn download<'a>(
         &'a self,
         client: &reqwest::Client,
         ui: &DownloadBar,
-    ) -> Result<DownloadedEpisode<'a>, String> {
-        self.log_debug(ui, "downloading episode");
-        let audio_file = self.download_enclosure(client, ui).await?;
-        let mut episode = self.into_downloaded(audio_file);
-        episode.process(ui).await?;
-        episode.run_download_hook(ui);
-        episode.mark_downloaded()?;
+    ) -> Result<DownloadedEpisode<'a>, CringeError> {
+        let mut episode = self.fetch(client, ui).await?;
+        episode.finish(client, ui).await?;
         Ok(episode)
     }
 
+    /// Downloads the enclosure, leaving the processing to [`DownloadedEpisode::finish`].
+    pub async fn fetch<'a>(
+        &'a self,
+        client: &reqwest::Client,
+        ui: &DownloadBar,
+    ) -> Result<DownloadedEpisode<'a>, CringeError> {
+        self.log_debug(ui, "downloading episode");
+        let (audio_file, url) = self.download_enclosure(client, ui).await?;
+        Ok(self.into_downloaded(audio_file, url))
+    }
+
+    async fn request_enclosure(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        downloaded: u64,
+        ui: &DownloadBar,
+    ) -> Result<reqwest::Response, CringeError> {
+        self.log_trace(ui, format!("connecting to url: {:?}", url));
+        let range = match self.config.chunk_size {
+            Some(size) => format!("bytes={}-{}", downloaded, downloaded + size - 1),
+            None => format!("bytes={}-", downloaded),
+        };
+        let request = client.get(url).header(reqwest::header::RANGE, range);
+        let request = self.config.request.apply(request);
+        let response = utils::send_with_retries(request, &self.config.retry, ui).await;
+        Ok(response?)
+    }
+
+    /// Asks for the size of the enclosure with a HEAD request, along with the url it redirects
+    /// to. Nothing is returned if the server doesn't support HEAD requests or doesn't say.
+    async fn probe_size(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        ui: &DownloadBar,
+    ) -> Option<(u64, String)> {
+        self.log_trace(ui, format!("probing size of {:?}", url));
+        let request = self.config.request.apply(client.head(url));
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                self.log_debug(
+                    ui,
+                    format!("failed to probe size: {}", CringeError::from(e)),
+                );
+                return None;
+            }
+        };
+
+        let status = response.status();
+        if status == reqwest::StatusCode::METHOD_NOT_ALLOWED {
+            self.log_debug(ui, "server doesn't support HEAD requests");
+            return None;
+        } else if !status.is_success() {
+            self.log_debug(
+                ui,
+                format!("failed to probe size: server responded with {}", status),
+            );
+            return None;
+        }
+
+        // The body of a HEAD response is empty, so the length has to be read from the header.
+        let size = response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok())
+            .filter(|len| *len > 0)?;
+
+        self.log_debug(ui, format!("probed size: {} bytes", size));
+        Some((size, response.url().to_string()))
+    }
+
+    /// Downloads the enclosure, returning its path along with the url it was downloaded from
+    /// after following any redirects.
     async fn download_enclosure<'a>(
         &'a self,
         client: &reqwest::Client,
         ui: &DownloadBar,
-    ) -> Result<PathBuf, String> {
+    ) -> Result<(PathBuf, String), CringeError> {
         let config = &self.config;
-
-        let partial_path = config
-            .partial_path
-            .clone()
-            .unwrap_or_else(|| config.download_path.clone())
-            .join(self.partial_name());
+        let partial_path = self.partial_path();
+
+        for dir in [partial_path.parent(), Some(config.download_path.as_path())]
+            .into_iter()
+            .flatten()
+        {
+            fs::create_dir_all(dir).map_err(CringeError::io(format!(
+                "failed to create directory: {:?}",
+                dir
+            )))?;
+        }
 
         let mut file = fs::OpenOptions::new()
             .write(true)
             .create(true)
             .open(&partial_path)
-            .map_err(|_| "failed to write file".to_string())?;
+            .map_err(CringeError::io("failed to write file"))?;
 
         let mut downloaded = file
             .seek(std::io::SeekFrom::End(0))
-            .map_err(|_| "file error".to_string())?;
+            .map_err(CringeError::io("file error"))?;
+
+        // Tracking redirectors may send each request to a different file, so a download is
+        // resumed from the url the redirects led to the first time.
+        let url_path = partial_path.with_extension("url");
+        let resume_url = match downloaded {
+            0 => None,
+            _ => fs::read_to_string(&url_path).ok(),
+        };
 
-        self.log_trace(ui, format!("connecting to url: {:?}", self.as_ref().url()));
-        let response = client
-            .get(self.as_ref().url())
-            .header(reqwest::header::RANGE, format!("bytes={}-", downloaded))
-            .send()
-            .await;
+        // The probed size is remembered, so a resumed download doesn't have to ask again.
+        let size_path = partial_path.with_extension("size");
+        let mut probed_size = fs::read_to_string(&size_path)
+            .ok()
+            .and_then(|size| size.trim().parse::<u64>().ok());
+
+        let mut resolved_url = resume_url;
+        if probed_size.is_none() && config.probe_size {
+            let url = resolved_url.as_deref().unwrap_or(self.as_ref().url());
+            if let Some((size, probed_url)) = self.probe_size(client, url, ui).await {
+                if fs::write(&size_path, size.to_string()).is_err() {
+                    self.log_warn(ui, "failed to save the probed size");
+                }
+                probed_size = Some(size);
+                if probed_url != self.as_ref().url() {
+                    resolved_url = Some(probed_url);
+                }
+            }
+        }
 
-        let response = utils::short_handle_response(response)?;
+        let url = resolved_url.as_deref().unwrap_or(self.as_ref().url());
+        let mut response = self.request_enclosure(client, url, downloaded, ui).await;
+
+        // The resolved url may have expired, e.g. if it was signed.
+        if resolved_url.is_some() && !response.as_ref().is_ok_and(|r| r.status().is_success()) {
+            self.log_debug(
+                ui,
+                "resolved url failed, requesting the enclosure url instead",
+            );
+            let _ = fs::remove_file(&url_path);
+            response = self
+                .request_enclosure(client, self.as_ref().url(), downloaded, ui)
+                .await;
+        }
 
-        let total_size = response.content_length().unwrap_or(0);
-        let extension = utils::get_extension_from_response(&response, &self);
+        let response = response?;
 
-        ui.init_download_bar(downloaded, total_size);
+        let status = response.status();
+        if !status.is_success() {
+            if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                // Partial file is likely corrupted, discard it so the next attempt starts over.
+                let _ = fs::remove_file(&partial_path);
+                let _ = fs::remove_file(&url_path);
+                let _ = fs::remove_file(&size_path);
+            }
+            return Err(CringeError::Status(status));
+        }
 
-        let mut stream = response.bytes_stream();
+        let effective_url = response.url().to_string();
+        if effective_url != self.as_ref().url() {
+            self.log_debug(ui, format!("redirected to {}", &effective_url));
+            if fs::write(&url_path, &effective_url).is_err() {
+                self.log_warn(ui, "failed to save the resolved url");
+            }
+        }
 
-        while let Some(item) = stream.next().await {
-            let chunk = item.map_err(|_| "failed to load chunk".to_string())?;
-            file.write_all(&chunk)
-                .map_err(|_| "failed to write chunk to file".to_string())?;
-            downloaded = cmp::min(downloaded + (chunk.len() as u64), total_size);
-            ui.set_progress(downloaded);
+        // A 200 response means the server ignored the range header and sends the whole file.
+        let ranged = status == reqwest::StatusCode::PARTIAL_CONTENT;
+        if !ranged && downloaded > 0 {
+            self.log_debug(
+                ui,
+                "server does not support range requests, restarting download",
+            );
+            file.set_len(0)
+                .and_then(|_| file.seek(std::io::SeekFrom::Start(0)))
+                .map_err(CringeError::io("file error"))?;
+            downloaded = 0;
+        } else if downloaded > 0 {
+            self.log_debug(ui, format!("resuming download from byte {}", downloaded));
         }
 
-        let path = {
-            let mut path = config
-                .download_path
-                .to_path_buf()
-                .join(&self.partial_name());
-            path.set_extension(extension);
-            path
+        // Chunks are requested one after another if the server supports ranges, otherwise the
+        // whole file is downloaded at once.
+        let chunk_size = config.chunk_size.filter(|_| ranged);
+        let total_size = match chunk_size {
+            Some(_) => content_range_total(&response),
+            None => response.content_length().map(|len| len + downloaded),
         };
+        // The probed size is only used for the progress, as the file may be different with each
+        // request, e.g. when ads are inserted into it.
+        let expected_size = total_size.or(probed_size).unwrap_or(0);
+        let extension = utils::get_extension_from_response(&response, &self);
 
-        fs::rename(partial_path, &path).map_err(|_| "failed to rename episode file".to_string())?;
-
-        Ok(path)
-    }
+        ui.init_download_bar(downloaded, expected_size);
+
+        let mut response = response;
+        let mut retries = 0;
+        loop {
+            let start = downloaded;
+            let mut dropped = None;
+            let mut stream = response.bytes_stream();
+
+            while let Some(item) = stream.next().await {
+                let chunk = match item {
+                    Ok(chunk) => chunk,
+                    // Only the current chunk has to be requested again.
+                    Err(e) if chunk_size.is_some() => {
+                        dropped = Some(e);
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                if let Some(limiter) = &config.rate_limiter {
+                    limiter.consume(chunk.len()).await;
+                }
+                file.write_all(&chunk)
+                    .map_err(CringeError::io("failed to write chunk to file"))?;
+                downloaded += chunk.len() as u64;
+                ui.set_progress(cmp::min(downloaded, expected_size));
+            }
+
+            let Some(chunk_size) = chunk_size else {
+                break;
+            };
+
+            match dropped {
+                Some(e) if retries < config.retry.max_retries => {
+                    retries += 1;
+                    self.log_warn(ui, format!("chunk failed: {}", CringeError::from(e)));
+                    ui.retrying(retries, config.retry.max_retries);
+                    tokio::time::sleep(config.retry.delay(retries)).await;
+                }
+                Some(e) => return Err(e.into()),
+                None => {
+                    if retries > 0 {
+                        ui.retry_done();
+                        retries = 0;
+                    }
+
+                    // Without a total size, the last chunk is the one that comes up short.
+                    let done = match total_size {
+                        Some(total_size) => downloaded >= total_size,
+                        None => downloaded - start < chunk_size,
+                    };
+                    if done {
+                        break;
+                    }
+                }
+            }
+
+            response = self
+                .request_enclosure(client, &effective_url, downloaded, ui)
+                .await?;
+            // A file that's a multiple of the chunk size only turns out to be done once the
+            // range after its end is requested.
+            if total_size.is_none()
+                && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+            {
+                break;
+            }
+            if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+                return Err(CringeError::Status(response.status()));
+            }
+        }
+
+        // Keep the partial file around so it can be resumed on the next run.
+        if total_size.is_some_and(|total_size| downloaded < total_size) {
+            return Err(CringeError::Incomplete);
+        }
+
+        let path = {
+            let mut path = config
+                .download_path
+                .to_path_buf()
+                .join(&self.partial_name());
+            path.set_extension(extension);
+            path
+        };
+
+        fs::rename(partial_path, &path)
+            .map_err(CringeError::io("failed to rename episode file"))?;
+        let _ = fs::remove_file(&url_path);
+        let _ = fs::remove_file(&size_path);
+
+        Ok((path, effective_url))
+    }
+}
+
+/// The size of the whole file from the `Content-Range` header of a ranged response, like
+/// `bytes 0-1023/4096`.
+fn content_range_total(response: &reqwest::Response) -> Option<u64> {
+    let range = response.headers().get(reqwest::header::CONTENT_RANGE)?;
+    let (_, total) = range.to_str().ok()?.rsplit_once('/')?;
+    total.trim().parse().ok()
+}
+
+/// Everything known about an episode, written next to it so the library can be rebuilt
+/// without fetching the feed again.
+#[derive(Serialize)]
+struct EpisodeMetadata<'a> {
+    podcast: Option<&'a str>,
+    title: &'a str,
+    description: Option<String>,
+    /// RFC 3339 date.
+    published: Option<String>,
+    /// Duration in seconds.
+    duration: Option<u64>,
+    season: Option<u32>,
+    episode: Option<u32>,
+    guid: &'a str,
+    author: Option<String>,
+    /// The author of the podcast, which may differ from the author of the episode.
+    podcast_author: Option<&'a str>,
+    copyright: Option<&'a str>,
+    language: Option<&'a str>,
+    categories: Vec<String>,
+    explicit: Option<bool>,
+    url: &'a str,
+    /// Where the url redirected to, if anywhere.
+    resolved_url: Option<&'a str>,
+    mime: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    funding: Vec<Funding>,
+}
+
+impl<'a> EpisodeMetadata<'a> {
+    fn new(episode: &'a Episode, resolved_url: &'a str) -> Self {
+        let attrs = &episode.attrs;
+        let podcast = &episode.podcast;
+
+        let published = attrs
+            .published()
+            .and_then(|published| chrono::DateTime::from_timestamp(published.as_secs() as i64, 0))
+            .map(|date| date.to_rfc3339());
+
+        let author = attrs
+            .get_text("itunes:author")
+            .or_else(|| attrs.author().ok().map(String::from))
+            .or_else(|| podcast.author().map(String::from));
+
+        Self {
+            podcast: podcast.get_str("title"),
+            title: attrs.title(),
+            description: attrs.description().ok(),
+            published,
+            duration: attrs.duration().map(|duration| duration.as_secs()),
+            season: attrs.season(),
+            episode: attrs.episode_number(),
+            guid: &attrs.guid,
+            author,
+            podcast_author: podcast.author(),
+            copyright: podcast.copyright(),
+            language: podcast.language(),
+            categories: podcast.categories(),
+            explicit: episode.explicit(),
+            url: attrs.url(),
+            resolved_url: (resolved_url != attrs.url()).then_some(resolved_url),
+            mime: attrs.mime.as_deref(),
+            funding: podcast.funding(),
+        }
+    }
 }
 
 pub struct DownloadedEpisode<'a> {
     inner: &'a Episode,
     /// Where the episode is downloaded.
     path: PathBuf,
+    /// The url the episode was downloaded from, after following redirects.
+    url: String,
     /// The handle to the process of an optional post-download hook.
-    handle: Option<JoinHandle<()>>,
+    handle: Option<JoinHandle<Result<(), String>>>,
+    /// Hash of the file as it was downloaded, with `dedupe` enabled.
+    content_hash: Option<String>,
 }
 
 impl<'a> DownloadedEpisode<'a> {
-    pub fn new(inner: &'a Episode, path: PathBuf) -> DownloadedEpisode<'a> {
+    pub fn new(inner: &'a Episode, path: PathBuf, url: String) -> DownloadedEpisode<'a> {
         Self {
             inner,
             path,
+            url,
             handle: None,
+            content_hash: None,
         }
     }
 
+    /// Processes the downloaded file, starts the download hook and marks the episode as
+    /// downloaded.
+    pub async fn finish(
+        &mut self,
+        client: &reqwest::Client,
+        ui: &DownloadBar,
+    ) -> Result<(), CringeError> {
+        self.process(client, ui).await?;
+        self.run_download_hook(ui);
+        self.mark_downloaded(ui)?;
+        self.inner
+            .log_info(ui, format!("downloaded to {:?}", self.path()));
+        Ok(())
+    }
+
     pub fn into_path(self) -> PathBuf {
         self.path
     }
 
-    pub fn mark_downloaded(&self) -> Result<(), String> {
+    pub fn mark_downloaded(&self, ui: &DownloadBar) -> Result<(), String> {
         let id = self.inner.config.id_pattern.replace(" ", "_");
         let path = self.inner.config.tracker_path.as_path();
-        DownloadedEpisodes::append(&path, &id, self)
+        DownloadedEpisodes::append(&path, &id, self)?;
+
+        // The index is only informational, so it's not worth failing the download over.
+        if let Some(index) = &self.inner.config.episode_index {
+            if let Err(e) = episode_index::upsert(index, &self.inner.config.podcast_name, self) {
+                self.inner
+                    .log_warn(ui, format!("failed to update episode index: {}", e));
+            }
+        }
+
+        Ok(())
     }
 
     pub fn inner(&self) -> &Episode {
@@ -361,36 +1481,49 @@ impl<'a> DownloadedEpisode<'a> {
         &self.path
     }
 
-    pub async fn normalize_id3v2(&self, ui: &DownloadBar) {
+    pub fn content_hash(&self) -> Option<&str> {
+        self.content_hash.as_deref()
+    }
+
+    pub async fn normalize_id3v2(&self, client: &reqwest::Client, ui: &DownloadBar) {
         use id3::TagLike;
-        if self.path.extension().is_some_and(|ext| ext == "mp3") {
+        if Container::detect(&self.path) == Some(Container::Mp3) {
             self.inner.log_trace(ui, "normalizing id3 tags");
             if let Some(xml_tags) = &self.inner.tags {
                 let mut file_tags = id3::Tag::read_from_path(&self.path()).unwrap_or_default();
-
-                for frame in xml_tags.frames() {
-                    if !file_tags.get(frame.id()).is_some() {
-                        file_tags.add_frame(frame.to_owned());
-                        self.inner
-                            .log_trace(ui, format!("adding frame: {:?}", &frame));
-                    }
-                }
+                file_tags.merge_feed_tags(xml_tags);
 
                 for (id, value) in &self.inner.config.id3_tags {
                     file_tags.set_text(id, value);
                 }
 
-                if !file_tags
-                    .pictures()
-                    .any(|pic| pic.picture_type == id3::frame::PictureType::CoverFront)
+                // The resolved url points at the file itself rather than some tracker.
+                if file_tags.get("WOAF").is_none() {
+                    file_tags.add_frame(id3::Frame::link("WOAF", &self.url));
+                }
+
+                if self.inner.config.embed_artwork
+                    && !file_tags
+                        .pictures()
+                        .any(|pic| pic.picture_type == id3::frame::PictureType::CoverFront)
                 {
                     if let Some(img_url) = self.inner.image_url.as_ref() {
-                        if let Some(frame) =
-                            cache::get_image(img_url, id3::frame::PictureType::CoverFront, ui).await
+                        if let Some(frame) = cache::get_image(
+                            client,
+                            img_url,
+                            id3::frame::PictureType::CoverFront,
+                            ui,
+                        )
+                        .await
                         {
                             file_tags.add_frame(frame);
-                            self.inner
-                                .log_debug(ui, "added cover image to podcast episode");
+                            self.inner.log_debug(
+                                ui,
+                                format!(
+                                    "added {} artwork to the file",
+                                    self.inner.artwork_source()
+                                ),
+                            );
                         } else {
                             self.inner.log_warn(
                                 ui,
@@ -410,17 +1543,440 @@ impl<'a> DownloadedEpisode<'a> {
         };
     }
 
+    /// Writes the tags from the feed as MP4 atoms, along with the cover image as a `covr` atom
+    /// and the advisory rating.
+    pub async fn normalize_mp4_tags(&self, client: &reqwest::Client, ui: &DownloadBar) {
+        if Container::detect(&self.path) != Some(Container::Mp4) {
+            return;
+        }
+
+        let explicit = self.inner.explicit();
+        let img_url = self
+            .inner
+            .image_url
+            .as_ref()
+            .filter(|_| self.inner.config.embed_artwork);
+
+        let xml_tags = self.inner.tags.as_ref();
+        if xml_tags.is_none() && img_url.is_none() && explicit.is_none() {
+            return;
+        }
+
+        let mut tag = match mp4ameta::Tag::read_from_path(&self.path) {
+            Ok(tag) => tag,
+            Err(e) => {
+                self.inner
+                    .log_warn(ui, format!("failed to read mp4 tags: {:?}", e));
+                return;
+            }
+        };
+
+        let mut changed = false;
+
+        if let Some(xml_tags) = xml_tags {
+            self.inner.log_trace(ui, "normalizing mp4 tags");
+            tag.merge_feed_tags(xml_tags);
+            changed = true;
+        }
+
+        if let Some(explicit) = explicit.filter(|_| tag.advisory_rating().is_none()) {
+            tag.set_advisory_rating(match explicit {
+                true => mp4ameta::AdvisoryRating::Explicit,
+                false => mp4ameta::AdvisoryRating::Inoffensive,
+            });
+            changed = true;
+        }
+
+        if let Some(img_url) = img_url.filter(|_| tag.artwork().is_none()) {
+            match cache::get_image_data(client, img_url, ui).await {
+                Some((data, mime)) => {
+                    let image = match mime.as_str() {
+                        "image/png" => mp4ameta::Img::png(data),
+                        "image/bmp" => mp4ameta::Img::bmp(data),
+                        _ => mp4ameta::Img::jpeg(data),
+                    };
+
+                    tag.set_artwork(image);
+                    changed = true;
+                    self.inner.log_debug(
+                        ui,
+                        format!("added {} artwork to the file", self.inner.artwork_source()),
+                    );
+                }
+                None => self
+                    .inner
+                    .log_warn(ui, format!("failed to fetch image from url: {:?}", img_url)),
+            }
+        }
+
+        if !changed {
+            return;
+        }
+
+        if let Err(e) = tag.write_to_path(&self.path) {
+            ui.log_error(format!("failed to write tags to file: {:?}", e));
+        } else {
+            self.inner
+                .log_debug(ui, "updated mp4 tags of podcast episode");
+        }
+    }
+
+    /// Writes the tags from the feed as Vorbis comments, along with the cover image as a
+    /// `METADATA_BLOCK_PICTURE`.
+    pub async fn normalize_vorbis_comments(&self, client: &reqwest::Client, ui: &DownloadBar) {
+        if Container::detect(&self.path) != Some(Container::Ogg) {
+            return;
+        }
+
+        let img_url = self
+            .inner
+            .image_url
+            .as_ref()
+            .filter(|_| self.inner.config.embed_artwork);
+
+        let xml_tags = self.inner.tags.as_ref();
+        if xml_tags.is_none() && img_url.is_none() {
+            return;
+        }
+
+        let mut comments = match VorbisComments::read_from_path(&self.path) {
+            Ok(comments) => comments,
+            Err(e) => {
+                self.inner
+                    .log_warn(ui, format!("failed to read vorbis comments: {}", e));
+                return;
+            }
+        };
+
+        if let Some(xml_tags) = xml_tags {
+            self.inner.log_trace(ui, "normalizing vorbis comments");
+            comments.merge_feed_tags(xml_tags);
+        }
+
+        if let Some(img_url) = img_url.filter(|_| !comments.has_picture()) {
+            match cache::get_image_data(client, img_url, ui).await {
+                Some((data, mime)) => {
+                    comments.set_picture(&data, &mime);
+                    self.inner.log_debug(
+                        ui,
+                        format!("added {} artwork to the file", self.inner.artwork_source()),
+                    );
+                }
+                None => self
+                    .inner
+                    .log_warn(ui, format!("failed to fetch image from url: {:?}", img_url)),
+            }
+        }
+
+        if let Err(e) = comments.write_to_path(&self.path) {
+            ui.log_error(format!("failed to write tags to file: {}", e));
+        } else {
+            self.inner
+                .log_debug(ui, "updated vorbis comments of podcast episode");
+        }
+    }
+
+    /// How much the file size may differ from the enclosure length, as a fraction of it.
+    const LENGTH_TOLERANCE: f64 = 0.01;
+
+    /// Compares the file size to the length the feed advertises for the enclosure.
+    ///
+    /// Feeds aren't always accurate about the length, so a mismatch is only an error when
+    /// `verify_length` is enabled, in which case the file is removed.
+    fn verify_length(&self, ui: &DownloadBar) -> Result<(), String> {
+        let Some(expected) = self.inner.attrs.enclosure_length() else {
+            return Ok(());
+        };
+
+        let actual = fs::metadata(&self.path)
+            .map_err(|_| "failed to read file size".to_string())?
+            .len();
+
+        if actual.abs_diff(expected) as f64 <= expected as f64 * Self::LENGTH_TOLERANCE {
+            return Ok(());
+        }
+
+        let msg = format!(
+            "file size of {} bytes doesn't match enclosure length of {} bytes",
+            actual, expected
+        );
+
+        if self.inner.config.verify_length {
+            let _ = fs::remove_file(&self.path);
+            Err(msg)
+        } else {
+            self.inner.log_warn(ui, msg);
+            Ok(())
+        }
+    }
+
+    /// Converts the episode to the configured format with ffmpeg.
+    ///
+    /// Failing to transcode isn't fatal, the original file is kept in that case.
+    /// Tags are copied over by ffmpeg, and re-applied by the tag normalization afterwards.
+    async fn transcode(&mut self, ui: &DownloadBar) {
+        let Some(format) = self.inner.config.transcode_to else {
+            return;
+        };
+
+        let extension = format.extension();
+        if self
+            .path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        {
+            self.inner
+                .log_trace(ui, "skipping transcoding: already in target format");
+            return;
+        }
+
+        // Appended when there's no extension, since the name might contain dots.
+        let new_path = match self.path.extension() {
+            Some(_) => self.path.with_extension(extension),
+            None => PathBuf::from(format!("{}.{}", self.path.display(), extension)),
+        };
+        self.inner
+            .log_debug(ui, format!("transcoding to {}", extension));
+
+        let mut command = tokio::process::Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(&self.path)
+            // Cover images are dropped here and embedded again after transcoding.
+            .args(["-vn", "-map_metadata", "0"])
+            .args(format.codec_args())
+            .arg(&new_path);
+
+        match command.output().await {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => {
+                let _ = fs::remove_file(&new_path);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                self.inner.log_warn(
+                    ui,
+                    format!("ffmpeg failed, keeping original: {}", stderr.trim()),
+                );
+                return;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                ui.log_warn("ffmpeg not found, keeping original file");
+                return;
+            }
+            Err(e) => {
+                self.inner
+                    .log_warn(ui, format!("failed to run ffmpeg: {}", e));
+                return;
+            }
+        }
+
+        if !self.inner.config.keep_original {
+            if let Err(e) = fs::remove_file(&self.path) {
+                self.inner
+                    .log_warn(ui, format!("failed to remove original file: {}", e));
+            }
+        }
+
+        self.path = new_path;
+    }
+
+    /// Runs the episode through ffmpeg's `loudnorm` filter, replacing the file.
+    ///
+    /// Like transcoding, failing to normalize isn't fatal. The tags are copied over from
+    /// the original file, since ffmpeg drops some of them, like cover images.
+    async fn normalize_loudness(&self, ui: &DownloadBar) {
+        let Some(target) = self.inner.config.normalize_loudness else {
+            return;
+        };
+
+        let is_audio = mime_guess::from_path(&self.path)
+            .first()
+            .is_some_and(|mime| mime.type_() == mime_guess::mime::AUDIO);
+        if !is_audio {
+            self.inner
+                .log_trace(ui, "skipping loudness normalization: not an audio file");
+            return;
+        }
+
+        let Some(extension) = self.path.extension().and_then(|ext| ext.to_str()) else {
+            return;
+        };
+
+        // loudnorm upsamples to 192kHz, and opus only supports up to 48kHz.
+        let sample_rate = match extension.to_lowercase().as_str() {
+            "opus" | "ogg" => "48000",
+            _ => "44100",
+        };
+
+        let tmp_path = self.path.with_extension(format!("loudnorm.{}", extension));
+        self.inner
+            .log_debug(ui, format!("normalizing loudness to {} LUFS", target));
+
+        let mut command = tokio::process::Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(&self.path)
+            .args(["-vn", "-map_metadata", "0", "-af"])
+            .arg(format!("loudnorm=I={}:TP=-1.5:LRA=11", target))
+            .args(["-ar", sample_rate])
+            .arg(&tmp_path);
+
+        match command.output().await {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => {
+                let _ = fs::remove_file(&tmp_path);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                self.inner.log_warn(
+                    ui,
+                    format!("ffmpeg failed to normalize loudness: {}", stderr.trim()),
+                );
+                return;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                ui.log_warn("ffmpeg not found, skipping loudness normalization");
+                return;
+            }
+            Err(e) => {
+                self.inner
+                    .log_warn(ui, format!("failed to run ffmpeg: {}", e));
+                return;
+            }
+        }
+
+        if let Err(e) = copy_tags(&self.path, &tmp_path) {
+            self.inner
+                .log_warn(ui, format!("failed to copy tags to normalized file: {}", e));
+        }
+
+        if let Err(e) = fs::rename(&tmp_path, &self.path) {
+            let _ = fs::remove_file(&tmp_path);
+            self.inner.log_warn(
+                ui,
+                format!("failed to replace file with normalized one: {}", e),
+            );
+        }
+    }
+
+    /// Cuts the soundbites of the episode into files of their own next to it with ffmpeg, named
+    /// after the episode and the title of the soundbite.
+    ///
+    /// Like transcoding, failing to extract them isn't fatal.
+    async fn extract_soundbites(&self, ui: &DownloadBar) {
+        let soundbites = self.inner.attrs.soundbites();
+        if soundbites.is_empty() {
+            return;
+        }
+
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.path.extension().map(|ext| ext.to_string_lossy());
+        self.inner
+            .log_debug(ui, format!("extracting {} soundbites", soundbites.len()));
+
+        for (index, soundbite) in soundbites.iter().enumerate() {
+            let title = match &soundbite.title {
+                Some(title) => sanitize_filename::sanitize(title),
+                None => format!("soundbite {}", index + 1),
+            };
+            let name = match &extension {
+                Some(extension) => format!("{} - {}.{}", stem, title, extension),
+                None => format!("{} - {}", stem, title),
+            };
+            let clip_path = self.path.with_file_name(name);
+
+            let mut command = tokio::process::Command::new("ffmpeg");
+            command
+                .args(["-y", "-loglevel", "error", "-ss"])
+                .arg(format!("{:.3}", soundbite.start.as_secs_f64()))
+                .arg("-t")
+                .arg(format!("{:.3}", soundbite.duration.as_secs_f64()))
+                .arg("-i")
+                .arg(&self.path)
+                .args(["-map_metadata", "0", "-c", "copy"])
+                .arg(&clip_path);
+
+            match command.output().await {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => {
+                    let _ = fs::remove_file(&clip_path);
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    self.inner.log_warn(
+                        ui,
+                        format!("ffmpeg failed to extract soundbite: {}", stderr.trim()),
+                    );
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    ui.log_warn("ffmpeg not found, skipping soundbites");
+                    return;
+                }
+                Err(e) => {
+                    self.inner
+                        .log_warn(ui, format!("failed to run ffmpeg: {}", e));
+                    return;
+                }
+            }
+        }
+    }
+
     fn file_name(&self) -> &str {
         self.path.file_name().unwrap().to_str().unwrap()
     }
 
+    /// Waits for the download hook to finish. A failing hook only warns, so the remaining
+    /// episodes are still downloaded.
     pub async fn await_handle(&mut self, ui: &DownloadBar) {
         if let Some(handle) = self.handle.take() {
             self.inner.log_debug(ui, "awaiting download hook");
-            let _ = handle.await;
+            match handle.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => self.inner.log_warn(ui, e),
+                Err(e) => self
+                    .inner
+                    .log_warn(ui, format!("download hook panicked: {}", e)),
+            }
         }
     }
 
+    /// The environment variables describing the episode that are passed to the download hook.
+    ///
+    /// Each one is set with both the `CRINGE_` and the `TALECAST_` prefix.
+    fn hook_env(&self) -> Vec<(String, String)> {
+        let attrs = &self.inner.attrs;
+        let podcast = self
+            .inner
+            .podcast
+            .get_str("title")
+            .unwrap_or(&self.inner.config.podcast_name);
+
+        let published = attrs
+            .published()
+            .and_then(|published| chrono::DateTime::from_timestamp(published.as_secs() as i64, 0))
+            .map(|date| date.to_rfc3339())
+            .unwrap_or_default();
+
+        let duration = attrs
+            .duration()
+            .map(|duration| duration.as_secs().to_string())
+            .unwrap_or_default();
+
+        let vars = [
+            ("TITLE", attrs.title().to_string()),
+            ("PODCAST", podcast.to_string()),
+            ("FILE", self.path.to_string_lossy().into_owned()),
+            ("GUID", attrs.guid().to_string()),
+            ("PUBDATE", published),
+            ("URL", self.url.clone()),
+            ("DURATION", duration),
+        ];
+
+        vars.into_iter()
+            .flat_map(|(name, val)| {
+                [
+                    (format!("CRINGE_{}", name), val.clone()),
+                    (format!("TALECAST_{}", name), val),
+                ]
+            })
+            .collect()
+    }
+
     fn run_download_hook(&mut self, ui: &DownloadBar) {
         let Some(script_path) = self.inner.config.download_hook.clone() else {
             self.inner.log_trace(ui, "no download hook configured");
@@ -430,12 +1986,24 @@ impl<'a> DownloadedEpisode<'a> {
         self.inner.log_debug(ui, "running download hook");
 
         let path = self.path().to_owned();
+        let env = self.hook_env();
 
         let handle = tokio::task::spawn_blocking(move || {
-            std::process::Command::new(script_path)
+            let output = std::process::Command::new(&script_path)
                 .arg(path)
+                .envs(env)
                 .output()
-                .unwrap();
+                .map_err(|e| format!("failed to run download hook {:?}: {}", script_path, e))?;
+
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "download hook exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ))
+            }
         });
 
         self.handle = Some(handle);
@@ -461,41 +2029,370 @@ impl<'a> DownloadedEpisode<'a> {
         Ok(())
     }
 
-    async fn process(&mut self, ui: &DownloadBar) -> Result<(), String> {
+    async fn process(&mut self, client: &reqwest::Client, ui: &DownloadBar) -> Result<(), String> {
         self.inner.log_debug(ui, "processing episode");
         self.rename()?;
+        self.verify_length(ui)?;
+
+        if self.link_duplicate(ui) {
+            return self.make_symlink(ui);
+        }
+
+        self.transcode(ui).await;
+        self.normalize_loudness(ui).await;
         self.make_symlink(ui)?;
-        self.normalize_id3v2(ui).await;
+        self.normalize_id3v2(client, ui).await;
+        self.normalize_mp4_tags(client, ui).await;
+        self.normalize_vorbis_comments(client, ui).await;
+
+        if self.inner.config.download_transcripts {
+            if let Err(e) = self.download_transcript(client, ui).await {
+                self.inner
+                    .log_warn(ui, format!("failed to download transcript: {}", e));
+            }
+        }
+
+        if self.inner.config.embed_chapters {
+            if let Err(e) = self.embed_chapters(client, ui).await {
+                self.inner
+                    .log_warn(ui, format!("failed to embed chapters: {}", e));
+            }
+        }
+
+        if self.inner.config.save_cover {
+            if let Err(e) = self.save_cover(client, ui).await {
+                self.inner
+                    .log_warn(ui, format!("failed to save podcast cover: {}", e));
+            }
+        }
+
+        if self.inner.config.extract_soundbites {
+            self.extract_soundbites(ui).await;
+        }
+
+        if self.inner.config.write_metadata_sidecar {
+            if let Err(e) = self.write_metadata_sidecar(ui) {
+                self.inner
+                    .log_warn(ui, format!("failed to write metadata sidecar: {}", e));
+            }
+        }
+
+        // Last, since writing the tags would change it again.
+        if self.inner.config.set_mtime_from_pubdate {
+            if let Err(e) = self.set_mtime_from_pubdate(ui) {
+                self.inner
+                    .log_warn(ui, format!("failed to set modification time: {}", e));
+            }
+        }
 
         Ok(())
     }
 
-    fn rename(&mut self) -> Result<(), String> {
-        let new_name = &self.inner.config.name_pattern;
-        let mut new_name = sanitize_filename::sanitize(new_name);
+    /// With `dedupe` enabled, replaces the file with a hard link to an identical one downloaded
+    /// before, e.g. by another podcast that shares the episode. Returns whether it was replaced.
+    ///
+    /// The hashes are of the files as they were downloaded, since the tags written afterwards
+    /// differ between podcasts. The linked file was processed already, so it keeps the tags of
+    /// the podcast that downloaded it first.
+    fn link_duplicate(&mut self, ui: &DownloadBar) -> bool {
+        let (Some(mode), Some(index)) =
+            (self.inner.config.dedupe, &self.inner.config.episode_index)
+        else {
+            return false;
+        };
 
-        let new_path = match self.path.extension() {
-            Some(extension) => {
-                let max_file_len: usize = 255;
-                let ext_len = extension.len() + 1; // + 1 for the dot.
-                let overflow = (new_name.len() + ext_len).saturating_sub(max_file_len);
-                for _ in 0..overflow {
-                    new_name.pop();
-                }
+        let hash = match episode_index::content_hash(&self.path, mode) {
+            Ok(hash) => hash,
+            Err(e) => {
+                self.inner
+                    .log_warn(ui, format!("failed to hash file: {}", e));
+                return false;
+            }
+        };
 
-                let mut new_path = self.path.with_file_name(new_name);
-                new_path.set_extension(extension);
-                new_path
+        let podcast = &self.inner.config.podcast_name;
+        let duplicate =
+            episode_index::find_duplicate(index, &hash, podcast, self.inner.attrs.guid());
+        self.content_hash = Some(hash);
+        let original = match duplicate {
+            Ok(Some(original)) => original,
+            Ok(None) => return false,
+            Err(e) => {
+                self.inner
+                    .log_warn(ui, format!("failed to look up duplicates: {}", e));
+                return false;
             }
-            None => self.path.with_file_name(new_name),
         };
 
+        // The original may have been converted to another format.
+        let link_path = match original.extension() {
+            Some(extension) => self.path.with_extension(extension),
+            None => self.path.clone(),
+        };
+
+        // Linked next to the file first, so nothing is lost if linking isn't possible, e.g.
+        // because the original is on another filesystem.
+        let mut tmp_path = link_path.clone().into_os_string();
+        tmp_path.push(".link");
+        let tmp_path = PathBuf::from(tmp_path);
+        let _ = fs::remove_file(&tmp_path);
+        if let Err(e) =
+            fs::hard_link(&original, &tmp_path).and_then(|_| fs::rename(&tmp_path, &link_path))
+        {
+            let _ = fs::remove_file(&tmp_path);
+            self.inner.log_warn(
+                ui,
+                format!("failed to link duplicate of {:?}: {}", &original, e),
+            );
+            return false;
+        }
+
+        if link_path != self.path {
+            let _ = fs::remove_file(&self.path);
+            self.path = link_path;
+        }
+
+        self.inner
+            .log_info(ui, format!("duplicate of {:?}, linked instead", &original));
+        true
+    }
+
+    /// Sets the modification time of the file to when the episode was published, so that the
+    /// files sort chronologically. Episodes without a valid date are left as they are.
+    fn set_mtime_from_pubdate(&self, ui: &DownloadBar) -> Result<(), String> {
+        let Some(published) = self.inner.attrs.published() else {
+            self.inner
+                .log_debug(ui, "not setting modification time: no publish date");
+            return Ok(());
+        };
+
+        let mtime = time::UNIX_EPOCH + published;
+        fs::File::options()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.set_modified(mtime))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Downloads the chapters of the episode, and writes them to the file as ID3 CHAP frames
+    /// for mp3 files, or as a chapter list for mp4 files.
+    async fn embed_chapters(
+        &self,
+        client: &reqwest::Client,
+        ui: &DownloadBar,
+    ) -> Result<(), String> {
+        let Some(url) = self.inner.attrs.chapters_url() else {
+            self.inner.log_trace(ui, "no chapters found");
+            return Ok(());
+        };
+
+        let container = Container::detect(&self.path);
+        let Some(container) = container.filter(|container| *container != Container::Ogg) else {
+            self.inner
+                .log_trace(ui, "skipping chapters: unsupported file format");
+            return Ok(());
+        };
+
+        self.inner.log_debug(ui, "downloading chapters");
+        let request = self.inner.config.request.apply(client.get(url));
+        let response = utils::short_handle_response(request.send().await)?;
+
+        if !response.status().is_success() {
+            return Err(format!("server responded with {}", response.status()));
+        }
+
+        let data = response
+            .bytes()
+            .await
+            .map_err(|_| "failed to load chapters".to_string())?;
+
+        let chapters: Vec<Chapter> = serde_json::from_slice::<ChaptersFile>(&data)
+            .map_err(|_| "invalid chapters file".to_string())?
+            .chapters
+            .into_iter()
+            .filter(|chapter| chapter.toc != Some(false) && chapter.start_time >= 0.)
+            .collect();
+
+        if chapters.is_empty() {
+            return Ok(());
+        }
+
+        match container {
+            Container::Mp3 => self.write_id3_chapters(&chapters),
+            Container::Mp4 => self.write_mp4_chapters(&chapters),
+            Container::Ogg => Ok(()),
+        }
+    }
+
+    fn write_id3_chapters(&self, chapters: &[Chapter]) -> Result<(), String> {
+        use id3::TagLike;
+
+        let mut tag = id3::Tag::read_from_path(&self.path).unwrap_or_default();
+        if tag.chapters().next().is_some() {
+            return Ok(());
+        }
+
+        let duration = self.inner.attrs.duration().map(|d| d.as_secs_f64());
+        let to_millis = |secs: f64| (secs * 1000.) as u32;
+        let mut ids = vec![];
+
+        for (index, chapter) in chapters.iter().enumerate() {
+            // Chapters without an end last until the next one starts.
+            let end = chapter
+                .end_time
+                .or_else(|| chapters.get(index + 1).map(|next| next.start_time))
+                .or(duration)
+                .unwrap_or(chapter.start_time);
+
+            let id = format!("chp{}", index);
+            tag.add_frame(id3::frame::Chapter {
+                element_id: id.clone(),
+                start_time: to_millis(chapter.start_time),
+                end_time: to_millis(end),
+                start_offset: u32::MAX,
+                end_offset: u32::MAX,
+                frames: vec![id3::Frame::text("TIT2", chapter.title.clone())],
+            });
+            ids.push(id);
+        }
+
+        tag.add_frame(id3::frame::TableOfContents {
+            element_id: "toc".to_string(),
+            top_level: true,
+            ordered: true,
+            elements: ids,
+            frames: vec![],
+        });
+
+        tag.write_to_path(&self.path, id3::Version::Id3v24)
+            .map_err(|e| format!("failed to write chapters: {:?}", e))
+    }
+
+    fn write_mp4_chapters(&self, chapters: &[Chapter]) -> Result<(), String> {
+        let mut tag = mp4ameta::Tag::read_from_path(&self.path)
+            .map_err(|e| format!("failed to read mp4 tags: {:?}", e))?;
+
+        if !tag.chapters().is_empty() {
+            return Ok(());
+        }
+
+        for chapter in chapters {
+            // Start times too large for a duration are skipped.
+            let Ok(start) = time::Duration::try_from_secs_f64(chapter.start_time) else {
+                continue;
+            };
+            tag.chapter_list_mut()
+                .push(mp4ameta::Chapter::new(start, chapter.title.clone()));
+        }
+
+        tag.write_to_path(&self.path)
+            .map_err(|e| format!("failed to write chapters: {:?}", e))
+    }
+
+    /// Saves the artwork of the podcast as `cover.jpg` in its download directory, unless there's
+    /// a cover there already. Any episode artwork is embedded in the files instead.
+    async fn save_cover(&self, client: &reqwest::Client, ui: &DownloadBar) -> Result<(), String> {
+        let dir = &self.inner.config.download_path;
+        let covers = ["cover.jpg", "cover.png"];
+        if covers.iter().any(|cover| dir.join(cover).exists()) {
+            return Ok(());
+        }
+
+        let Some(url) = self.inner.podcast.image() else {
+            self.inner
+                .log_debug(ui, "not saving cover: podcast has no image");
+            return Ok(());
+        };
+        let Some((data, mime_type)) = cache::get_image_data(client, url, ui).await else {
+            return Err(format!("failed to fetch image from url: {}", url));
+        };
+
+        let path = match mime_type.as_str() {
+            "image/png" => dir.join(covers[1]),
+            _ => dir.join(covers[0]),
+        };
+        self.inner
+            .log_debug(ui, format!("saving podcast cover to {:?}", &path));
+        fs::write(&path, data).map_err(|e| e.to_string())
+    }
+
+    /// Writes the metadata of the episode to a json file next to the audio file.
+    fn write_metadata_sidecar(&self, ui: &DownloadBar) -> Result<(), String> {
+        let metadata = EpisodeMetadata::new(self.inner, &self.url);
+        let json = serde_json::to_string_pretty(&metadata).map_err(|e| e.to_string())?;
+        let path = self.path.with_extension("json");
+
+        self.inner
+            .log_debug(ui, format!("writing metadata to {:?}", &path));
+        fs::write(&path, json).map_err(|_| "failed to write metadata file".to_string())
+    }
+
+    /// Downloads the transcript next to the audio file, with the same file stem.
+    async fn download_transcript(
+        &self,
+        client: &reqwest::Client,
+        ui: &DownloadBar,
+    ) -> Result<(), String> {
+        let Some(transcript) = self.inner.attrs.preferred_transcript() else {
+            self.inner.log_trace(ui, "no supported transcript found");
+            return Ok(());
+        };
+
+        self.inner.log_debug(ui, "downloading transcript");
+        let request = self.inner.config.request.apply(client.get(&transcript.url));
+        let response = request.send().await;
+        let response = utils::short_handle_response(response)?;
+
+        if !response.status().is_success() {
+            return Err(format!("server responded with {}", response.status()));
+        }
+
+        let data = response
+            .bytes()
+            .await
+            .map_err(|_| "failed to load transcript".to_string())?;
+
+        let path = self
+            .path
+            .with_extension(transcript.extension().unwrap_or("txt"));
+
+        fs::write(&path, &data).map_err(|_| "failed to write transcript file".to_string())?;
+
+        Ok(())
+    }
+
+    fn rename(&mut self) -> Result<(), String> {
+        let (dir, new_name) = self.inner.destination()?;
+        fs::create_dir_all(&dir).map_err(|_| format!("failed to create directory: {:?}", dir))?;
+
+        let extension = self.path.extension().and_then(|ext| ext.to_str());
+        let new_path = self.inner.file_path(&dir, new_name, extension);
+
         fs::rename(&self.path, &new_path).map_err(|_| "failed to rename episode".to_string())?;
         self.path = new_path;
         Ok(())
     }
 }
 
+/// Copies the ID3, MP4 or Vorbis tags of one file to another, other formats are left alone.
+fn copy_tags(from: &Path, to: &Path) -> Result<(), String> {
+    match Container::detect(from) {
+        Some(Container::Mp3) => match id3::Tag::read_from_path(from) {
+            Ok(tag) => tag
+                .write_to_path(to, id3::Version::Id3v24)
+                .map_err(|e| e.to_string()),
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        },
+        Some(Container::Mp4) => {
+            let tag = mp4ameta::Tag::read_from_path(from).map_err(|e| e.to_string())?;
+            tag.write_to_path(to).map_err(|e| e.to_string())
+        }
+        Some(Container::Ogg) => VorbisComments::read_from_path(from)?.write_to_path(to),
+        None => Ok(()),
+    }
+}
+
 impl AsRef<Episode> for DownloadedEpisode<'_> {
     fn as_ref(&self) -> &Episode {
         &self.inner
@@ -507,3 +2404,59 @@ impl AsRef<Attributes> for Episode {
         &self.attrs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An episode with the value at the key, along with the title and enclosure.
+    fn attributes(key: &str, val: serde_json::Value) -> Attributes {
+        let raw = serde_json::json!({
+            "title": "Episode",
+            "enclosure": { "@url": "https://example.com/episode.mp3" },
+            key: val,
+        });
+        let serde_json::Value::Object(raw) = raw else {
+            unreachable!()
+        };
+        Attributes::new(RawEpisode::new(raw)).unwrap()
+    }
+
+    #[test]
+    fn duration_parses_seconds_and_clock_times() {
+        let secs = |val: serde_json::Value| {
+            attributes("itunes:duration", val)
+                .duration()
+                .map(|d| d.as_secs())
+        };
+
+        assert_eq!(secs("01:02:03".into()), Some(3723));
+        assert_eq!(secs("62:03".into()), Some(3723));
+        assert_eq!(secs("3723".into()), Some(3723));
+        assert_eq!(secs(3723.into()), Some(3723));
+
+        for invalid in ["inf", "NaN", "-5", "1e20", "01:inf:00", "1e20:00", "abc"] {
+            assert_eq!(secs(invalid.into()), None, "{}", invalid);
+        }
+        assert_eq!(secs((-5).into()), None);
+        assert_eq!(secs(1e20.into()), None);
+    }
+
+    #[test]
+    fn soundbites_with_invalid_times_are_skipped() {
+        let soundbites = serde_json::json!([
+            { "@startTime": "73.5", "@duration": "60", "#text": "Highlight" },
+            { "@startTime": "1e20", "@duration": "60" },
+            { "@startTime": "inf", "@duration": "60" },
+            { "@startTime": "10", "@duration": "NaN" },
+            { "@startTime": "-5", "@duration": "60" },
+            { "@startTime": 30, "@duration": 1e20 },
+        ]);
+
+        let soundbites = attributes("soundbite", soundbites).soundbites();
+        assert_eq!(soundbites.len(), 1);
+        assert_eq!(soundbites[0].start, time::Duration::from_secs_f64(73.5));
+        assert_eq!(soundbites[0].duration, time::Duration::from_secs(60));
+        assert_eq!(soundbites[0].title.as_deref(), Some("Highlight"));
+    }
+}
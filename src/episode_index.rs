@@ -0,0 +1,239 @@
+//! Optional SQLite database with an entry for every downloaded episode.
+//!
+//! Unlike the download tracker, which only has to answer whether an episode was downloaded,
+//! the index keeps enough information around to query the library.
+
+use crate::config::DedupeMode;
+use crate::episode::DownloadedEpisode;
+use crate::podcast::LibraryStats;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub fn default_path() -> PathBuf {
+    crate::utils::data_dir()
+        .unwrap_or_else(crate::utils::config_dir)
+        .join("episodes.sqlite")
+}
+
+/// Inserts the episode into the index, or updates it if it was downloaded before.
+#[cfg(feature = "sqlite")]
+pub fn upsert(path: &Path, podcast: &str, episode: &DownloadedEpisode) -> Result<(), String> {
+    use rusqlite::params;
+
+    let conn = open(path)?;
+
+    let inner = episode.inner();
+    let size = std::fs::metadata(episode.path()).map_or(0, |meta| meta.len());
+
+    conn.execute(
+        "INSERT INTO episodes
+            (podcast, guid, title, published, path, size, downloaded_at, content_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT (podcast, guid) DO UPDATE SET
+            title = excluded.title,
+            published = excluded.published,
+            path = excluded.path,
+            size = excluded.size,
+            downloaded_at = excluded.downloaded_at,
+            content_hash = excluded.content_hash",
+        params![
+            podcast,
+            inner.attrs.guid(),
+            inner.attrs.title(),
+            inner
+                .attrs
+                .published()
+                .map(|published| published.as_secs() as i64),
+            episode.path().to_string_lossy(),
+            size as i64,
+            crate::utils::current_unix().as_secs() as i64,
+            episode.content_hash(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// A downloaded file that's identical to the one with the hash, other than the one of the given
+/// episode itself.
+#[cfg(feature = "sqlite")]
+pub fn find_duplicate(
+    path: &Path,
+    hash: &str,
+    podcast: &str,
+    guid: &str,
+) -> Result<Option<PathBuf>, String> {
+    let conn = open(path)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT path FROM episodes
+             WHERE content_hash = ?1 AND NOT (podcast = ?2 AND guid = ?3)",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let paths = stmt
+        .query_map(rusqlite::params![hash, podcast, guid], |row| {
+            row.get::<_, String>(0)
+        })
+        .map_err(|e| e.to_string())?;
+
+    // The file may have been deleted since, while its entry stays around.
+    for path in paths {
+        let path = PathBuf::from(path.map_err(|e| e.to_string())?);
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Adds up the indexed episodes of each podcast, by name, that are still on disk.
+#[cfg(feature = "sqlite")]
+pub fn stats(path: &Path) -> Result<HashMap<String, LibraryStats>, String> {
+    let conn = open(path)?;
+    let mut stmt = conn
+        .prepare("SELECT podcast, path, published FROM episodes")
+        .map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut stats: HashMap<String, LibraryStats> = HashMap::new();
+    for row in rows {
+        let (podcast, path, published) = row.map_err(|e| e.to_string())?;
+        // Pruned and deleted episodes keep their entries.
+        let Ok(meta) = std::fs::metadata(&path) else {
+            continue;
+        };
+        let published = published.map(|secs| crate::utils::Unix::from_secs(secs as u64));
+        stats.entry(podcast).or_default().add(meta.len(), published);
+    }
+
+    Ok(stats)
+}
+
+/// Hashes the file to compare it with the other downloads.
+///
+/// The quick hash only reads the size and the start and end of the file, which is enough to
+/// tell episodes apart in practice. The hashes are prefixed by their mode so they're never
+/// compared with the other kind.
+#[cfg(feature = "sqlite")]
+pub fn content_hash(path: &Path, mode: DedupeMode) -> Result<String, String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const CHUNK: u64 = 1 << 20;
+
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+
+    match mode {
+        DedupeMode::Quick => {
+            use std::hash::Hasher;
+
+            let size = file.metadata().map_err(|e| e.to_string())?.len();
+            let mut hasher = fnv::FnvHasher::default();
+            let mut buf = vec![];
+
+            (&mut file)
+                .take(CHUNK)
+                .read_to_end(&mut buf)
+                .map_err(|e| e.to_string())?;
+            if size > CHUNK {
+                file.seek(SeekFrom::Start(size.saturating_sub(CHUNK).max(CHUNK)))
+                    .and_then(|_| file.read_to_end(&mut buf))
+                    .map_err(|e| e.to_string())?;
+            }
+            hasher.write(&buf);
+
+            Ok(format!("quick:{}:{:x}", size, hasher.finish()))
+        }
+        DedupeMode::Full => {
+            use sha2::Digest;
+
+            let mut hasher = sha2::Sha256::new();
+            std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+            let hash: String = hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+
+            Ok(format!("sha256:{}", hash))
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open(path: &Path) -> Result<rusqlite::Connection, String> {
+    let conn = rusqlite::Connection::open(path).map_err(|e| e.to_string())?;
+    // Podcasts are synced concurrently, so the database may be locked by another one.
+    conn.busy_timeout(std::time::Duration::from_secs(5))
+        .map_err(|e| e.to_string())?;
+
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS episodes (
+            podcast TEXT NOT NULL,
+            guid TEXT NOT NULL,
+            title TEXT NOT NULL,
+            published INTEGER,
+            path TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            downloaded_at INTEGER NOT NULL,
+            content_hash TEXT,
+            PRIMARY KEY (podcast, guid)
+        );",
+    )
+    .map_err(|e| e.to_string())?;
+
+    // Indexes created before `dedupe` existed don't have the hashes yet.
+    if conn.prepare("SELECT content_hash FROM episodes").is_err() {
+        conn.execute_batch("ALTER TABLE episodes ADD COLUMN content_hash TEXT;")
+            .map_err(|e| e.to_string())?;
+    }
+
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS episodes_content_hash ON episodes (content_hash);",
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(conn)
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn upsert(_path: &Path, _podcast: &str, _episode: &DownloadedEpisode) -> Result<(), String> {
+    Err(missing_feature())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn find_duplicate(
+    _path: &Path,
+    _hash: &str,
+    _podcast: &str,
+    _guid: &str,
+) -> Result<Option<PathBuf>, String> {
+    Err(missing_feature())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn stats(_path: &Path) -> Result<HashMap<String, LibraryStats>, String> {
+    Err(missing_feature())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn content_hash(_path: &Path, _mode: DedupeMode) -> Result<String, String> {
+    Err(missing_feature())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn missing_feature() -> String {
+    "talecast was built without the 'sqlite' feature".to_string()
+}
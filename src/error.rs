@@ -0,0 +1,74 @@
+//! The ways syncing a podcast can fail.
+
+use serde::{Serialize, Serializer};
+use std::io;
+
+/// Why fetching a podcast or downloading one of its episodes failed.
+///
+/// The messages are meant for the progress bars, the underlying errors are kept as the source.
+#[derive(Debug, thiserror::Error)]
+pub enum CringeError {
+    /// The request couldn't be sent or its response couldn't be read.
+    #[error("{}", describe(.0))]
+    Fetch(#[from] reqwest::Error),
+    /// The server responded, but not with what was asked for.
+    #[error("server responded with {0}")]
+    Status(reqwest::StatusCode),
+    /// The feed couldn't be decoded or isn't valid XML.
+    #[error("failed to {0}")]
+    Parse(String),
+    /// Reading or writing the files of an episode failed.
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The download ended before the whole file was received.
+    #[error("download interrupted")]
+    Incomplete,
+    /// The sync was stopped with [`crate::utils::interrupt`].
+    #[error("sync interrupted")]
+    Interrupted,
+    /// The settings of the podcast are invalid.
+    #[error("{0}")]
+    Config(String),
+    /// Any other failure, e.g. while tagging or tracking a downloaded episode.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl CringeError {
+    /// Wraps an IO error in what was being done when it occurred, for `map_err`.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> Self {
+        let context = context.into();
+        |source| Self::Io { context, source }
+    }
+}
+
+impl From<String> for CringeError {
+    fn from(msg: String) -> Self {
+        Self::Other(msg)
+    }
+}
+
+/// Reports are serialized for scripts, which only get the message.
+impl Serialize for CringeError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+fn describe(e: &reqwest::Error) -> &'static str {
+    match e {
+        e if e.is_builder() => "Invalid URL",
+        // Connection timeouts are also connection errors, so this has to be checked first.
+        e if e.is_timeout() => "request timed out",
+        e if e.is_connect() => "failed to connect to url",
+        e if e.is_status() => "server error",
+        e if e.is_redirect() => "too many redirects while connecting",
+        e if e.is_decode() => "failed to decode response",
+        e if e.is_body() => "failed to load chunk",
+        _ => "unexpected connection error",
+    }
+}
@@ -0,0 +1,43 @@
+//! The sync engine of TaleCast, for embedding it in other programs.
+//!
+//! Podcasts are configured either from the config files, with [`config::GlobalConfig::load`]
+//! and [`config::PodcastConfigs::load`], or in code:
+//!
+//! ```no_run
+//! use std::collections::HashMap;
+//! use talecast::config::{GlobalConfig, PodcastConfig, PodcastConfigs};
+//!
+//! # async fn run() {
+//! let mut podcasts = HashMap::new();
+//! let url = "https://example.com/feed.xml".to_string();
+//! podcasts.insert("example".to_string(), PodcastConfig::new(url));
+//!
+//! let mp = talecast::MultiProgress::with_draw_target(talecast::ProgressDrawTarget::hidden());
+//! let reports = PodcastConfigs::new(podcasts)
+//!     .sync_with_progress(GlobalConfig::default(), &mp, None, false, None)
+//!     .await;
+//! # }
+//! ```
+
+pub mod config;
+pub mod error;
+pub mod lock;
+pub mod observer;
+pub mod opml;
+pub mod podcast;
+pub mod utils;
+
+mod cache;
+mod display;
+mod download_tracker;
+mod episode;
+mod episode_index;
+mod metrics;
+mod patterns;
+mod tags;
+mod tui;
+mod vorbis;
+
+pub use indicatif::{MultiProgress, ProgressDrawTarget};
+
+pub const APPNAME: &str = "talecast";
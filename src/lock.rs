@@ -0,0 +1,97 @@
+use crate::utils;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time;
+
+/// Locks that haven't been touched for this long are considered stale on platforms where it
+/// can't be checked whether the process holding them is still running.
+#[cfg(not(unix))]
+const STALE_AFTER: time::Duration = time::Duration::from_secs(60 * 60 * 24);
+
+/// An advisory lock that keeps multiple syncs from running at the same time, e.g. when a slow
+/// sync started by cron overlaps with the next one.
+///
+/// The lock file holds the PID of the process and when it was acquired. It's removed when the
+/// lock is dropped, and left behind by processes that crash, which is detected by the next one.
+pub struct SyncLock {
+    path: PathBuf,
+}
+
+impl SyncLock {
+    pub fn path() -> PathBuf {
+        utils::cache_dir().join("sync.lock")
+    }
+
+    /// Acquires the lock, waiting for the other process to finish if `wait` is set.
+    ///
+    /// Returns the PID of the process holding the lock if it couldn't be acquired.
+    pub async fn acquire(wait: bool) -> Result<Self, u32> {
+        let path = Self::path();
+
+        loop {
+            let file = fs::File::options().write(true).create_new(true).open(&path);
+
+            match file {
+                Ok(mut file) => {
+                    let contents =
+                        format!("{} {}", std::process::id(), utils::current_unix().as_secs());
+                    if let Err(e) = file.write_all(contents.as_bytes()) {
+                        log::warn!("failed to write lock file: {}", e);
+                    }
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                // Locking isn't worth failing the sync over, e.g. on a read-only cache dir.
+                Err(e) => {
+                    log::warn!("failed to create lock file: {}", e);
+                    return Ok(Self { path });
+                }
+            }
+
+            match Self::holder(&path) {
+                Some(pid) if wait => {
+                    log::info!("waiting for process {} to finish syncing", pid);
+                    tokio::time::sleep(time::Duration::from_secs(1)).await;
+                }
+                Some(pid) => return Err(pid),
+                None => {
+                    log::warn!("removing stale lock file {:?}", &path);
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+    }
+
+    /// The PID of the process holding the lock, if it's still running.
+    fn holder(path: &std::path::Path) -> Option<u32> {
+        // The lock may have been released in the meantime.
+        let contents = fs::read_to_string(path).ok()?;
+        let mut parts = contents.split_whitespace();
+        let pid: u32 = parts.next()?.parse().ok()?;
+        let acquired: u64 = parts.next()?.parse().ok()?;
+
+        Self::is_running(pid, acquired).then_some(pid)
+    }
+
+    #[cfg(unix)]
+    fn is_running(pid: u32, _acquired: u64) -> bool {
+        std::process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    #[cfg(not(unix))]
+    fn is_running(_pid: u32, acquired: u64) -> bool {
+        let age = utils::current_unix().saturating_sub(time::Duration::from_secs(acquired));
+        age < STALE_AFTER
+    }
+}
+
+impl Drop for SyncLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
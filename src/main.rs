@@ -1,21 +1,15 @@
-use crate::config::GlobalConfig;
-use crate::config::PodcastConfigs;
 use clap::Parser;
 use regex::Regex;
+use std::path::Path;
 use std::path::PathBuf;
-
-mod cache;
-mod config;
-mod display;
-mod download_tracker;
-mod episode;
-mod opml;
-mod patterns;
-mod podcast;
-mod tags;
-mod utils;
-
-pub const APPNAME: &'static str = "talecast";
+use talecast::config;
+use talecast::config::GlobalConfig;
+use talecast::config::PodcastConfigs;
+use talecast::lock::SyncLock;
+use talecast::opml;
+use talecast::podcast::ListOrder;
+use talecast::utils;
+use talecast::utils::Unix;
 
 #[derive(Parser)]
 #[command(
@@ -32,15 +26,61 @@ struct Args {
         help = "Import podcasts from an OPML file"
     )]
     import: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "import",
+        help = "Prefix the names of imported podcasts with their OPML folder"
+    )]
+    prefix_folders: bool,
     #[arg(
         short,
         long,
         value_name = "FILE",
-        help = "Export your podcasts to an OPML file"
+        num_args = 0..=1,
+        help = "Export your podcasts to an OPML file, or to stdout if no file is given"
     )]
-    export: Option<PathBuf>,
+    export: Option<Option<PathBuf>>,
     #[arg(short, long, help = "Print the downloaded paths to stdout")]
     print: bool,
+    #[arg(
+        long,
+        alias = "no-progress",
+        conflicts_with = "json",
+        help = "Print the progress as lines of status instead of progress bars, the default when stderr isn't a terminal"
+    )]
+    plain: bool,
+    #[arg(
+        long,
+        conflicts_with = "print",
+        help = "Print a JSON summary of the sync or the stats to stdout instead of showing progress bars"
+    )]
+    json: bool,
+    #[arg(
+        long,
+        conflicts_with = "json",
+        help = "List the episodes that would be downloaded without downloading them"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        value_name = "DATE|AGE",
+        value_parser = utils::parse_since,
+        conflicts_with_all = ["list", "prune", "catch_up"],
+        help = "Only download episodes published since a date or within an age like 7d, ignoring the configured mode"
+    )]
+    since: Option<Unix>,
+    #[arg(
+        long,
+        overrides_with = "no_wait",
+        help = "Wait for another running sync to finish instead of exiting"
+    )]
+    wait: bool,
+    #[arg(
+        long,
+        overrides_with = "wait",
+        help = "Exit if another sync is running, which is the default"
+    )]
+    no_wait: bool,
     #[arg(
         short,
         long,
@@ -57,10 +97,61 @@ struct Args {
     filter: Option<String>,
     #[arg(
         long,
-        value_name = "FILE",
-        help = "Override the path to the config file"
+        value_name = "NAME",
+        conflicts_with_all = ["filter", "list", "pending", "validate", "prune", "interactive"],
+        help = "Only sync the podcast with this name, even if it's disabled"
+    )]
+    only: Option<String>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["only", "list", "pending", "validate", "prune", "interactive"],
+        help = "Only sync the podcasts with these names, or matching globs like 'news*'. Can be repeated"
+    )]
+    podcast: Vec<String>,
+    #[arg(
+        long,
+        value_name = "GUID",
+        requires = "podcast",
+        conflicts_with_all = ["list", "pending", "validate", "prune", "interactive", "stats", "refresh_titles", "dry_run", "json", "since"],
+        help = "Download the episode with this guid of the --podcast again, overwriting its file. Can be repeated"
+    )]
+    redownload: Vec<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["filter", "only", "list", "pending", "validate", "prune", "interactive", "json"],
+        help = "Download the latest episode of a feed without adding it to your podcasts"
+    )]
+    feed_url: Option<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "feed_url",
+        conflicts_with = "since",
+        default_value_t = 1,
+        help = "How many of the latest episodes to download with --feed-url"
+    )]
+    latest: usize,
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "feed_url",
+        help = "Where to download the episodes of --feed-url, defaults to the current directory"
+    )]
+    out: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Override the path to the config file, or to the directory with both config files"
     )]
     config: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Keep the caches, sync lock and episode index in this directory"
+    )]
+    data_dir: Option<PathBuf>,
     #[arg(long, help = "Edit the config.toml file")]
     edit_config: bool,
     #[arg(long, help = "Edit the podcasts.toml file")]
@@ -69,6 +160,78 @@ struct Args {
     search: Option<Vec<String>>,
     #[arg(long, help = "Print your podcasts to stdout")]
     list: bool,
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        help = "Increase the log level, -v for info, -vv for debug and -vvv for trace"
+    )]
+    verbose: u8,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the log to this file instead of a temporary one"
+    )]
+    log_file: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "dry_run", "json"],
+        help = "List the pending episodes of each podcast without downloading them"
+    )]
+    pending: bool,
+    #[arg(
+        long,
+        value_name = "ORDER",
+        requires = "pending",
+        default_value = "date",
+        help = "How to order the pending episodes"
+    )]
+    sort: ListOrder,
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "pending",
+        help = "Only list the first N pending episodes of each podcast"
+    )]
+    limit: Option<usize>,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "pending", "prune", "dry_run", "json"],
+        help = "Check that the feed of every podcast can be fetched and parsed, without downloading"
+    )]
+    validate: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "pending", "validate", "prune", "dry_run", "json", "since"],
+        help = "Pick a podcast and which of its episodes to download"
+    )]
+    interactive: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "pending", "dry_run", "json"],
+        help = "Delete downloaded episodes older than their max_age"
+    )]
+    prune: bool,
+    #[arg(
+        long,
+        value_name = "AGE",
+        requires = "prune",
+        value_parser = utils::str_to_duration,
+        help = "Override max_age of all podcasts not marked with keep, e.g. 30d"
+    )]
+    max_age: Option<std::time::Duration>,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "pending", "validate", "prune", "interactive", "dry_run"],
+        help = "Show the downloaded episodes and size on disk of each podcast, without fetching feeds"
+    )]
+    stats: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["list", "pending", "validate", "prune", "interactive", "stats", "dry_run", "json"],
+        help = "Fetch every feed and record its title as the display_title of the podcast"
+    )]
+    refresh_titles: bool,
 }
 
 impl From<Args> for Action {
@@ -85,6 +248,64 @@ impl From<Args> for Action {
             return Self::List { filter };
         }
 
+        if let Some(url) = args.feed_url {
+            return Self::FeedUrl {
+                url,
+                latest: args.latest,
+                out: args.out,
+                print,
+                since: args.since,
+                dry_run: args.dry_run,
+            };
+        }
+
+        if args.pending {
+            return Self::Pending {
+                filter,
+                order: args.sort,
+                limit: args.limit,
+                since: args.since,
+            };
+        }
+
+        if args.validate {
+            return Self::Validate { filter };
+        }
+
+        if args.stats {
+            return Self::Stats {
+                filter,
+                json: args.json,
+            };
+        }
+
+        if args.refresh_titles {
+            return Self::RefreshTitles { filter };
+        }
+
+        if !args.redownload.is_empty() {
+            return Self::Redownload {
+                podcasts: args.podcast,
+                guids: args.redownload,
+                print,
+                wait: args.wait,
+            };
+        }
+
+        if args.interactive {
+            return Self::Interactive {
+                filter,
+                wait: args.wait,
+            };
+        }
+
+        if args.prune {
+            return Self::Prune {
+                filter,
+                max_age: args.max_age,
+            };
+        }
+
         if args.edit_config {
             let path = GlobalConfig::default_path();
             return Self::Edit { path };
@@ -101,7 +322,11 @@ impl From<Args> for Action {
         }
 
         if let Some(path) = args.import {
-            return Self::Import { path, catch_up };
+            return Self::Import {
+                path,
+                catch_up,
+                prefix_folders: args.prefix_folders,
+            };
         }
 
         if let Some(path) = args.export {
@@ -123,7 +348,16 @@ impl From<Args> for Action {
             return Self::CatchUp { filter };
         }
 
-        Self::Sync { filter, print }
+        Self::Sync {
+            filter,
+            print,
+            json: args.json,
+            dry_run: args.dry_run,
+            since: args.since,
+            wait: args.wait,
+            only: args.only,
+            podcasts: args.podcast,
+        }
     }
 }
 
@@ -131,6 +365,44 @@ enum Action {
     List {
         filter: Option<Regex>,
     },
+    Pending {
+        filter: Option<Regex>,
+        order: ListOrder,
+        limit: Option<usize>,
+        since: Option<Unix>,
+    },
+    Validate {
+        filter: Option<Regex>,
+    },
+    FeedUrl {
+        url: String,
+        latest: usize,
+        out: Option<PathBuf>,
+        print: bool,
+        since: Option<Unix>,
+        dry_run: bool,
+    },
+    Prune {
+        filter: Option<Regex>,
+        max_age: Option<std::time::Duration>,
+    },
+    Interactive {
+        filter: Option<Regex>,
+        wait: bool,
+    },
+    Stats {
+        filter: Option<Regex>,
+        json: bool,
+    },
+    RefreshTitles {
+        filter: Option<Regex>,
+    },
+    Redownload {
+        podcasts: Vec<String>,
+        guids: Vec<String>,
+        print: bool,
+        wait: bool,
+    },
     CatchUp {
         filter: Option<Regex>,
     },
@@ -140,9 +412,10 @@ enum Action {
     Import {
         path: PathBuf,
         catch_up: bool,
+        prefix_folders: bool,
     },
     Export {
-        path: PathBuf,
+        path: Option<PathBuf>,
         filter: Option<Regex>,
     },
     Add {
@@ -157,13 +430,33 @@ enum Action {
     Sync {
         filter: Option<Regex>,
         print: bool,
+        json: bool,
+        dry_run: bool,
+        since: Option<Unix>,
+        wait: bool,
+        only: Option<String>,
+        podcasts: Vec<String>,
     },
 }
 
 use chrono::Local;
 use fern::Dispatch;
 
-fn setup_logging(config: &config::LogConfig) -> Result<PathBuf, fern::InitError> {
+/// Logs to `log_file` if given, otherwise to a temporary file in `/tmp/talecast`.
+///
+/// The log level of the config is overridden if `verbose` is set.
+fn setup_logging(
+    config: &config::LogConfig,
+    log_file: Option<&Path>,
+    verbose: u8,
+) -> Result<PathBuf, fern::InitError> {
+    let level = match verbose {
+        0 => config.level(),
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+
     let base_config = Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
@@ -173,11 +466,24 @@ fn setup_logging(config: &config::LogConfig) -> Result<PathBuf, fern::InitError>
                 message
             ))
         })
-        .level(config.level());
-
-    let log_dir = PathBuf::from("/tmp/talecast");
-    utils::create_dir(&log_dir);
-    let log_path = log_dir.join(chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string());
+        .level(level);
+
+    let log_path = match log_file {
+        Some(path) => {
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                utils::create_dir(parent);
+            }
+            path.to_path_buf()
+        }
+        None => {
+            let log_dir = PathBuf::from("/tmp/talecast");
+            utils::create_dir(&log_dir);
+            log_dir.join(chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string())
+        }
+    };
 
     let file_config = base_config.chain(fern::log_file(&log_path)?);
 
@@ -185,19 +491,81 @@ fn setup_logging(config: &config::LogConfig) -> Result<PathBuf, fern::InitError>
     Ok(log_path)
 }
 
+/// On the first Ctrl-C, lets the downloads in progress finish without starting new ones. The
+/// second one exits right away, leaving the partial downloads to be resumed by the next sync.
+fn handle_interrupts() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        utils::interrupt();
+        eprintln!(
+            "\nInterrupted, finishing the downloads in progress. Press Ctrl-C again to exit now."
+        );
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+}
+
+/// Takes the lock that keeps syncs from running at the same time, or tells the user why it
+/// can't.
+async fn sync_lock(wait: bool) -> Option<SyncLock> {
+    match SyncLock::acquire(wait).await {
+        Ok(lock) => Some(lock),
+        Err(pid) => {
+            eprintln!(
+                "Another sync is already running (process {}), exiting.",
+                pid
+            );
+            eprintln!("Use --wait to wait for it to finish instead.");
+            None
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
 
-    let global_config = match args.config.as_ref() {
-        Some(path) => GlobalConfig::load_from_path(path),
+    if let Some(path) = args.data_dir.clone() {
+        utils::set_data_dir(path);
+    }
+
+    // A directory replaces the default config directory, so podcasts.toml is read from it too.
+    let mut global_config = match args.config.clone() {
+        Some(path) if path.is_dir() => {
+            utils::set_config_dir(path);
+            GlobalConfig::load()
+        }
+        Some(path) => GlobalConfig::load_from_path(&path),
         None => GlobalConfig::load(),
     };
 
-    let log_path = setup_logging(&global_config.log()).unwrap();
+    if let Err(e) = global_config.style().check_templates() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    if args.plain {
+        global_config.set_plain_progress();
+    }
+
+    let log_path = setup_logging(&global_config.log(), args.log_file.as_deref(), args.verbose)
+        .unwrap_or_else(|e| {
+            eprintln!("failed to set up logging: {}", e);
+            std::process::exit(1);
+        });
+    // A log file chosen by the user stays where it is.
+    let temp_log = args.log_file.is_none().then_some(log_path);
 
     match Action::from(args) {
-        Action::Import { path, catch_up } => opml::import(&path, catch_up),
+        Action::Import {
+            path,
+            catch_up,
+            prefix_folders,
+        } => opml::import(&path, catch_up, prefix_folders),
 
         Action::Edit { path } => utils::edit_file(&path),
 
@@ -209,17 +577,154 @@ async fn main() {
             }
         }
 
+        Action::Pending {
+            filter,
+            order,
+            limit,
+            since,
+        } => {
+            PodcastConfigs::load()
+                .assert_not_empty()
+                .filter(filter)
+                .list_pending(global_config, order, limit, since)
+                .await
+        }
+
+        Action::Validate { filter } => {
+            let valid = PodcastConfigs::load()
+                .assert_not_empty()
+                .filter(filter)
+                .validate(global_config)
+                .await;
+
+            if !valid {
+                std::process::exit(1);
+            }
+        }
+
+        Action::FeedUrl {
+            url,
+            latest,
+            out,
+            print,
+            since,
+            dry_run,
+        } => {
+            let out = match out {
+                Some(out) => out,
+                None => std::env::current_dir().unwrap_or_default(),
+            };
+
+            let url = match utils::discover_feed(&global_config, &url).await {
+                Ok(url) => url,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            handle_interrupts();
+            let paths =
+                config::download_feed(global_config, url, latest, &out, since, dry_run).await;
+
+            if dry_run {
+                eprintln!("{} episodes would be downloaded.", paths.len());
+            } else {
+                eprintln!("{} episodes downloaded.", paths.len());
+            }
+
+            if print {
+                for path in paths {
+                    println!("{}", path.to_str().unwrap());
+                }
+            }
+        }
+
+        Action::Prune { filter, max_age } => {
+            PodcastConfigs::load()
+                .assert_not_empty()
+                .filter(filter)
+                .prune(global_config, max_age)
+                .await
+        }
+
+        Action::Stats { filter, json } => {
+            PodcastConfigs::load()
+                .assert_not_empty()
+                .filter(filter)
+                .stats(global_config, json)
+                .await
+        }
+
+        Action::RefreshTitles { filter } => {
+            PodcastConfigs::load()
+                .assert_not_empty()
+                .filter(filter)
+                .refresh_titles(global_config)
+                .await
+        }
+
+        Action::Redownload {
+            podcasts,
+            guids,
+            print,
+            wait,
+        } => {
+            let Some(_lock) = sync_lock(wait).await else {
+                return;
+            };
+
+            handle_interrupts();
+
+            let paths = PodcastConfigs::load()
+                .assert_not_empty()
+                .select(&podcasts)
+                .redownload(global_config, &guids)
+                .await;
+
+            eprintln!("{} episodes downloaded.", paths.len());
+
+            if print {
+                for path in paths {
+                    println!("{}", path.to_str().unwrap());
+                }
+            }
+        }
+
+        Action::Interactive { filter, wait } => {
+            let Some(_lock) = sync_lock(wait).await else {
+                return;
+            };
+
+            let paths = PodcastConfigs::load()
+                .assert_not_empty()
+                .filter(filter)
+                .interactive(global_config)
+                .await;
+
+            eprintln!("{} episodes downloaded.", paths.len());
+        }
+
         Action::Search { query, catch_up } => {
             utils::search_podcasts(&global_config, query, catch_up).await
         }
 
-        Action::Export { path, filter } => opml::export(&path, filter).await,
+        Action::Export { path, filter } => opml::export(path.as_deref(), filter).await,
 
         Action::Add {
             name,
             url,
             catch_up,
         } => {
+            // Websites of podcasts are given as often as their feeds.
+            let url = match utils::discover_feed(&global_config, &url).await {
+                Ok(url) => url,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+
             let name = match name {
                 Some(name) => name,
                 None => match utils::get_input(Some("enter name of podcast: ")) {
@@ -242,15 +747,44 @@ async fn main() {
             }
         }
 
-        Action::Sync { filter, print } => {
+        Action::Sync {
+            filter,
+            print,
+            json,
+            dry_run,
+            since,
+            wait,
+            only,
+            podcasts,
+        } => {
+            // Dry runs don't download anything, so they can't get in the way of a sync.
+            let _lock = match dry_run {
+                true => None,
+                false => match sync_lock(wait).await {
+                    Some(lock) => Some(lock),
+                    None => return,
+                },
+            };
+
+            handle_interrupts();
+
             let paths = PodcastConfigs::load()
                 .assert_not_empty()
                 .filter(filter)
-                .sync(global_config, &log_path)
+                .only(only.as_deref())
+                .select(&podcasts)
+                .sync(global_config, temp_log.as_deref(), json, dry_run, since)
                 .await;
 
-            eprintln!("Syncing complete!");
-            eprintln!("{} episodes downloaded.", paths.len());
+            if dry_run {
+                eprintln!("{} episodes would be downloaded.", paths.len());
+            } else {
+                match utils::is_interrupted() {
+                    true => eprintln!("Sync interrupted."),
+                    false => eprintln!("Syncing complete!"),
+                }
+                eprintln!("{} episodes downloaded.", paths.len());
+            }
 
             if print {
                 for path in paths {
@@ -0,0 +1,165 @@
+//! Metrics of the syncs, written in the Prometheus text format for the textfile collector of
+//! the node exporter.
+
+use crate::podcast::SyncReport;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const EPISODES: &str = "talecast_episodes_downloaded_total";
+const BYTES: &str = "talecast_bytes_downloaded_total";
+const FAILURES: &str = "talecast_sync_failures_total";
+
+/// Counters of each podcast, keyed by metric and then podcast name.
+type Counters = BTreeMap<&'static str, BTreeMap<String, u64>>;
+
+/// Adds the results of the sync to the counters already in the metrics file, and replaces the
+/// stats of the last run.
+pub fn write(path: &Path, reports: &[SyncReport], duration: Duration) -> Result<(), String> {
+    let mut counters = fs::read_to_string(path)
+        .map(|s| parse_counters(&s))
+        .unwrap_or_default();
+
+    let mut downloaded = 0;
+    let mut failures = 0;
+    for report in reports {
+        let bytes: u64 = report
+            .downloaded
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|meta| meta.len())
+            .sum();
+        let failed = u64::from(report.error.is_some());
+
+        downloaded += report.downloaded.len();
+        failures += failed;
+
+        for (metric, value) in [
+            (EPISODES, report.downloaded.len() as u64),
+            (BYTES, bytes),
+            (FAILURES, failed),
+        ] {
+            *counters
+                .entry(metric)
+                .or_default()
+                .entry(report.name.clone())
+                .or_default() += value;
+        }
+    }
+
+    let mut out = String::new();
+    for (metric, help) in [
+        (EPISODES, "Episodes downloaded."),
+        (BYTES, "Bytes of episodes downloaded."),
+        (FAILURES, "Syncs of a podcast that failed."),
+    ] {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} counter\n",
+            metric, help, metric
+        ));
+        for (name, value) in counters.get(metric).into_iter().flatten() {
+            out.push_str(&format!(
+                "{}{{podcast=\"{}\"}} {}\n",
+                metric,
+                escape(name),
+                value
+            ));
+        }
+    }
+
+    let now = crate::utils::current_unix().as_secs();
+    for (metric, help, value) in [
+        (
+            "talecast_last_run_duration_seconds",
+            "How long the last sync took.",
+            format!("{:.3}", duration.as_secs_f64()),
+        ),
+        (
+            "talecast_last_run_timestamp_seconds",
+            "When the last sync finished.",
+            now.to_string(),
+        ),
+        (
+            "talecast_last_run_episodes_downloaded",
+            "Episodes downloaded in the last sync.",
+            downloaded.to_string(),
+        ),
+        (
+            "talecast_last_run_failures",
+            "Podcasts that failed in the last sync.",
+            failures.to_string(),
+        ),
+    ] {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+            metric, help, metric, metric, value
+        ));
+    }
+
+    // The collector may read the file at any time, so it's replaced in one go.
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, out)
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .map_err(|e| e.to_string())
+}
+
+/// Reads the counters of a previous metrics file, ignoring anything it doesn't recognize.
+fn parse_counters(s: &str) -> Counters {
+    let mut counters = Counters::new();
+
+    for line in s.lines() {
+        let Some((series, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let Some((metric, labels)) = series.split_once('{') else {
+            continue;
+        };
+        let Some(metric) = [EPISODES, BYTES, FAILURES]
+            .into_iter()
+            .find(|m| *m == metric)
+        else {
+            continue;
+        };
+        let Some(name) = labels
+            .strip_prefix("podcast=\"")
+            .and_then(|labels| labels.strip_suffix("\"}"))
+        else {
+            continue;
+        };
+        let Ok(value) = value.parse::<u64>() else {
+            continue;
+        };
+
+        counters
+            .entry(metric)
+            .or_default()
+            .insert(unescape(name), value);
+    }
+
+    counters
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn unescape(label: &str) -> String {
+    let mut out = String::with_capacity(label.len());
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
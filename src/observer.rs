@@ -0,0 +1,22 @@
+use std::path::Path;
+
+/// Receives the events of a sync, e.g. to show its progress in a GUI.
+///
+/// Podcasts sync concurrently, so the events of different podcasts are interleaved. All
+/// methods do nothing by default.
+pub trait SyncObserver: Send + Sync {
+    /// The feed of a podcast was downloaded and parsed.
+    fn feed_fetched(&self, _podcast: &str, _episodes: usize) {}
+
+    /// An episode started downloading.
+    fn episode_started(&self, _podcast: &str, _episode: &str) {}
+
+    /// More of an episode was downloaded. The total is unknown if the server doesn't say.
+    fn progress(&self, _podcast: &str, _episode: &str, _downloaded: u64, _total: Option<u64>) {}
+
+    /// An episode was downloaded to `path`.
+    fn episode_finished(&self, _podcast: &str, _episode: &str, _path: &Path) {}
+
+    /// Syncing the podcast failed, no further episodes of it are downloaded.
+    fn error(&self, _podcast: &str, _error: &str) {}
+}
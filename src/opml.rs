@@ -1,79 +1,46 @@
 use crate::config;
-use crate::config::PodcastConfig;
-use opml::OPML;
 use regex::Regex;
-use std::collections::HashMap;
 use std::fs;
 use std::io::Write as IoWrite;
 use std::path::Path;
 
-pub async fn export(p: &Path, filter: Option<Regex>) {
-    let podcasts = config::PodcastConfigs::load()
+pub async fn export(p: Option<&Path>, filter: Option<Regex>) {
+    // Urls with `${VAR}` placeholders are exported as they're written.
+    let xml_string = config::PodcastConfigs::load_unresolved()
         .assert_not_empty()
-        .filter(filter);
+        .filter(filter)
+        .to_opml();
 
-    let opml = OPML::from(podcasts);
-    let xml_string = opml.to_string().unwrap();
+    let Some(p) = p else {
+        println!("{}", xml_string);
+        return;
+    };
 
-    fs::OpenOptions::new()
+    let result = fs::OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
         .open(p)
-        .unwrap()
-        .write_all(xml_string.as_bytes())
-        .unwrap();
-}
-
-pub fn import(p: &Path, catch_up: bool) {
-    let opml_string = std::fs::read_to_string(p).unwrap();
-    let opml = opml::OPML::from_str(&opml_string).unwrap();
-
-    let mut podcasts = HashMap::default();
-
-    for podcast in opml.body.outlines.into_iter() {
-        let title = {
-            let title = podcast.title.unwrap_or(podcast.text);
+        .and_then(|mut file| file.write_all(xml_string.as_bytes()));
 
-            if title.is_empty() {
-                None
-            } else {
-                Some(title)
-            }
-        };
+    if let Err(e) = result {
+        eprintln!("failed to write opml file {:?}: {:?}", p, e);
+        std::process::exit(1);
+    }
+}
 
-        let (name, mut podcast) = match (title, podcast.xml_url) {
-            (None, None) => {
-                eprintln!("importing failed due to feed with missing title and url");
-                std::process::exit(1);
-            }
-            (Some(title), None) => {
-                eprintln!(
-                    "importing failed due to following podcast missing its' url: {}",
-                    title
-                );
-                std::process::exit(1);
-            }
-            (None, Some(url)) => {
-                eprintln!(
-                    "importing failed due to podcast with following url missing a title: {}",
-                    url
-                );
-                std::process::exit(1);
-            }
-            (Some(title), Some(url)) => (title, PodcastConfig::new(url)),
-        };
+pub fn import(p: &Path, catch_up: bool, prefix_folders: bool) {
+    let mut podcasts = config::PodcastConfigs::from_opml(p, prefix_folders);
 
-        if catch_up {
+    if catch_up {
+        for (_, podcast) in &mut podcasts {
             podcast.catch_up();
         }
-
-        podcasts.insert(name, podcast);
     }
 
     if podcasts.is_empty() {
         eprintln!("no podcasts found.");
     } else {
-        config::PodcastConfigs::extend(podcasts);
+        config::PodcastConfigs::extend(podcasts.into_inner());
     }
 }
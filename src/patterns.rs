@@ -50,18 +50,70 @@ impl FullPattern {
         Self::from_str(s).evaluate(data)
     }
 
-    pub fn direct_eval_file(s: &str, data: EvalData<'_>) -> PathBuf {
-        let p = PathBuf::from(Self::direct_eval(s, data));
-        if let Some(path) = p.parent() {
-            utils::create_dir(&path);
+    /// Evaluates the pattern into a relative path.
+    ///
+    /// Only the separators in the text of the pattern create directories, the evaluated values
+    /// are sanitized so that e.g. a slash in an episode title doesn't end up as a directory.
+    pub fn evaluate_path(&self, data: EvalData<'_>) -> PathBuf {
+        let mut output = String::new();
+
+        for segment in &self.0 {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Pattern(Pattern::Unit(pattern)) => {
+                    output.push_str(&sanitize_filename::sanitize(pattern.evaluate(data)))
+                }
+                Segment::Pattern(Pattern::Data(pattern)) => {
+                    output.push_str(&sanitize_filename::sanitize(pattern.evaluate(data)))
+                }
+            }
+        }
+
+        // Collapses repeated separators and drops components like ".." that escape the directory.
+        output
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+            .map(sanitize_filename::sanitize)
+            .collect()
+    }
+
+    /// Evaluates a template for text like tags, where unknown patterns and missing values are
+    /// left empty instead of being errors. Braces without a match are kept as they are.
+    pub fn eval_template(s: &str, data: EvalData<'_>) -> String {
+        let mut output = String::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            output.push_str(&rest[..start]);
+
+            let name = &rest[start + 1..start + len];
+            let value = if let Some(unit) = UnitPattern::from_str(name) {
+                unit.evaluate(data)
+            } else if let Some(pattern) = DataPattern::from_str(name) {
+                pattern.evaluate(data)
+            } else {
+                String::new()
+            };
+            if value != VALUE_NOT_FOUND {
+                output.push_str(&value);
+            }
+
+            rest = &rest[start + len + 1..];
         }
-        p
+
+        output.push_str(rest);
+        output
     }
 
-    pub fn direct_eval_dir(s: &str, data: EvalData<'_>) -> PathBuf {
-        let p = PathBuf::from(Self::direct_eval(s, data));
-        utils::create_dir(&p);
-        p
+    /// Evaluates the pattern into a path, without creating it.
+    ///
+    /// Directories are created once something is written to them, so that patterns specific to
+    /// an episode don't leave empty directories behind for episodes that aren't downloaded.
+    pub fn direct_eval_path(s: &str, data: EvalData<'_>) -> PathBuf {
+        PathBuf::from(Self::direct_eval(s, data))
     }
 }
 
@@ -112,11 +164,20 @@ impl DataPattern {
     }
 }
 
+/// Used in place of the values of data patterns that aren't in the feed.
+const VALUE_NOT_FOUND: &str = "<value not found>";
+
+/// Used in place of the publication date of episodes without a valid date.
+const UNKNOWN_DATE: &str = "unknown-date";
+
+/// Used in place of the author of podcasts that don't specify one.
+const UNKNOWN_AUTHOR: &str = "unknown-author";
+
 impl Evaluate for DataPattern {
     fn evaluate(&self, data: EvalData) -> String {
         use chrono::TimeZone;
         use DataPatternType as Ty;
-        let null = "<value not found>";
+        let null = VALUE_NOT_FOUND;
 
         match self.ty {
             Ty::CurrDate => {
@@ -133,25 +194,36 @@ impl Evaluate for DataPattern {
             Ty::PubDate => {
                 let formatting = &self.data;
 
+                let Some(published) = data.episode.published() else {
+                    return UNKNOWN_DATE.to_string();
+                };
+
                 let datetime = chrono::Utc
-                    .timestamp_opt(data.episode.published().as_secs() as i64, 0)
+                    .timestamp_opt(published.as_secs() as i64, 0)
                     .unwrap();
 
                 if formatting == "unix" {
-                    data.episode.published().as_secs().to_string()
+                    published.as_secs().to_string()
                 } else {
                     datetime.format(formatting).to_string()
                 }
             }
+            // Feeds often contain HTML in their text, which shouldn't end up in names.
             Ty::RssEpisode => {
                 let key = &self.data;
 
-                data.episode.get_str(&key).unwrap_or(null).to_string()
+                data.episode
+                    .get_text(key)
+                    .map(|text| utils::clean_html(&text, true))
+                    .unwrap_or_else(|| null.to_string())
             }
             Ty::RssChannel => {
                 let key = &self.data;
 
-                data.podcast.get_str(&key).unwrap_or(null).to_string()
+                data.podcast
+                    .get_str(&key)
+                    .map(|text| utils::clean_html(text, true))
+                    .unwrap_or_else(|| null.to_string())
             }
         }
     }
@@ -187,6 +259,13 @@ enum UnitPattern {
     PodName,
     AppName,
     Home,
+    Title,
+    PubDate,
+    Podcast,
+    EpisodeNum,
+    Season,
+    Author,
+    Year,
 }
 
 impl UnitPattern {
@@ -197,6 +276,13 @@ impl UnitPattern {
             "podname" => Self::PodName,
             "appname" => Self::AppName,
             "home" => Self::Home,
+            "title" => Self::Title,
+            "pubdate" => Self::PubDate,
+            "podcast" => Self::Podcast,
+            "episode_num" => Self::EpisodeNum,
+            "season" => Self::Season,
+            "author" => Self::Author,
+            "year" => Self::Year,
             _ => return None,
         }
         .into()
@@ -211,6 +297,43 @@ impl Evaluate for UnitPattern {
             Self::PodName => data.pod_name.to_string(),
             Self::AppName => crate::APPNAME.to_string(),
             Self::Home => home().unwrap_or("<missing home>".to_string()),
+            Self::Title => data.episode.title().to_string(),
+            Self::PubDate => {
+                use chrono::TimeZone;
+
+                match data.episode.published() {
+                    Some(published) => chrono::Utc
+                        .timestamp_opt(published.as_secs() as i64, 0)
+                        .unwrap()
+                        .format("%Y-%m-%d")
+                        .to_string(),
+                    None => UNKNOWN_DATE.to_string(),
+                }
+            }
+            Self::Podcast => data
+                .podcast
+                .get_str("title")
+                .unwrap_or(data.pod_name)
+                .to_string(),
+            Self::EpisodeNum => data.episode.get_text("itunes:episode").unwrap_or_default(),
+            Self::Season => data.episode.get_text("itunes:season").unwrap_or_default(),
+            Self::Author => data
+                .podcast
+                .author()
+                .map(|author| utils::clean_html(author, true))
+                .unwrap_or_else(|| UNKNOWN_AUTHOR.to_string()),
+            Self::Year => {
+                use chrono::TimeZone;
+
+                match data.episode.published() {
+                    Some(published) => chrono::Utc
+                        .timestamp_opt(published.as_secs() as i64, 0)
+                        .unwrap()
+                        .format("%Y")
+                        .to_string(),
+                    None => UNKNOWN_DATE.to_string(),
+                }
+            }
         }
     }
 }
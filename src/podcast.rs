@@ -1,78 +1,276 @@
+use crate::config::ConfigOption;
 use crate::config::DownloadMode;
 use crate::config::EvalData;
+use crate::config::OnError;
 use crate::config::PodcastConfig;
+use crate::config::RetryPolicy;
 use crate::config::{Config, GlobalConfig};
 use crate::display::DownloadBar;
 use crate::episode;
+use crate::episode::DownloadedEpisode;
 use crate::episode::Episode;
 use crate::episode::RawEpisode;
+use crate::error::CringeError;
 use crate::tags;
 use crate::utils;
+use crate::utils::Unix;
+use futures::stream::{self, StreamExt};
 use quickxml_to_serde::{xml_string_to_json, Config as XmlConfig};
+use regex::Regex;
+use serde::Serialize;
 use serde_json::Map;
 use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use tokio::sync::Semaphore;
 
-fn get_inner_channel(xml: String) -> Option<serde_json::Value> {
+/// How the episodes are ordered when listing the pending ones.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum ListOrder {
+    /// Newest first.
+    #[default]
+    Date,
+    /// Alphabetically.
+    Title,
+    /// Longest first.
+    Duration,
+}
+
+/// The kind of syndication format a feed document is written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FeedKind {
+    Rss,
+    Atom,
+}
+
+impl FeedKind {
+    /// The key under which the episodes are stored in the channel/feed object.
+    fn item_key(&self) -> &'static str {
+        match self {
+            Self::Rss => "item",
+            Self::Atom => "entry",
+        }
+    }
+}
+
+/// Finds the element holding the podcast info, `rss/channel` for RSS and `feed` for Atom.
+fn get_inner_channel(xml: String) -> Option<(FeedKind, serde_json::Value)> {
     let conf = XmlConfig::new_with_defaults();
-    std::mem::take(
-        xml_string_to_json(xml, &conf)
-            .ok()?
-            .get_mut("rss")?
-            .get_mut("channel")?,
-    )
-    .into()
+    let mut root = xml_string_to_json(xml, &conf).ok()?;
+
+    if let Some(channel) = root.get_mut("rss").and_then(|rss| rss.get_mut("channel")) {
+        return Some((FeedKind::Rss, std::mem::take(channel)));
+    }
+
+    let feed = root.get_mut("feed")?;
+    Some((FeedKind::Atom, std::mem::take(feed)))
+}
+
+/// Puts the `itunes:` prefix back in place of the placeholder, including in nested tags like
+/// `itunes:owner`.
+fn restore_prefix(val: Value, replacement: &str) -> Value {
+    match val {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, val)| {
+                    let key = key.replace(replacement, "itunes:");
+                    (key, restore_prefix(val, replacement))
+                })
+                .collect(),
+        ),
+        Value::Array(vals) => Value::Array(
+            vals.into_iter()
+                .map(|val| restore_prefix(val, replacement))
+                .collect(),
+        ),
+        // Only possible for tags within CDATA sections, which are text rather than tags.
+        Value::String(s) => Value::String(s.replace(replacement, "itunes:")),
+        val => val,
+    }
+}
+
+/// Fills in the RSS keys of an Atom feed that the rest of the program relies on.
+fn atom_feed_to_rss(feed: &mut Map<String, Value>) {
+    if !feed.contains_key("image") {
+        if let Some(logo) = feed.get("logo").or(feed.get("icon")).cloned() {
+            feed.insert("image".to_string(), logo);
+        }
+    }
+
+    if !feed.contains_key("description") {
+        if let Some(subtitle) = feed.get("subtitle").cloned() {
+            feed.insert("description".to_string(), subtitle);
+        }
+    }
+}
+
+/// Maps an Atom entry onto the RSS item keys, so that it can be parsed like any other episode.
+fn atom_entry_to_rss(entry: &mut Map<String, Value>) {
+    if !entry.contains_key("enclosure") {
+        let enclosure = entry
+            .get("link")
+            .map(utils::val_to_objects)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|link| link.get("@rel").and_then(Value::as_str) == Some("enclosure"))
+            .map(|link| {
+                let mut enclosure = Map::new();
+                for (atom_key, rss_key) in [
+                    ("@href", "@url"),
+                    ("@type", "@type"),
+                    ("@length", "@length"),
+                ] {
+                    if let Some(val) = link.get(atom_key) {
+                        enclosure.insert(rss_key.to_string(), val.clone());
+                    }
+                }
+                Value::Object(enclosure)
+            });
+
+        if let Some(enclosure) = enclosure {
+            entry.insert("enclosure".to_string(), enclosure);
+        }
+    }
+
+    if !entry.contains_key("pubDate") {
+        if let Some(date) = entry.get("published").or(entry.get("updated")).cloned() {
+            entry.insert("pubDate".to_string(), date);
+        }
+    }
+
+    if !entry.contains_key("guid") {
+        if let Some(id) = entry.get("id").cloned() {
+            entry.insert("guid".to_string(), id);
+        }
+    }
+
+    if !entry.contains_key("description") {
+        if let Some(summary) = entry.get("summary").or(entry.get("content")).cloned() {
+            entry.insert("description".to_string(), summary);
+        }
+    }
+
+    if let Some(name) = entry
+        .get("author")
+        .and_then(|author| author.get("name"))
+        .cloned()
+    {
+        entry.insert("author".to_string(), name);
+    }
+}
+
+type ParsedFeeds = std::collections::BTreeMap<String, (u64, RawPodcast, Vec<RawEpisode>)>;
+
+/// Feeds parsed during this run by url, along with a hash of the xml they were parsed from, so
+/// that a feed that's used more than once is only parsed once.
+static PARSED_FEEDS: std::sync::Mutex<ParsedFeeds> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+/// Like [`xml_to_value`], but reuses the result if the same xml was parsed for the url before.
+fn parse_feed(url: &str, xml: &str, ui: &DownloadBar) -> Option<(RawPodcast, Vec<RawEpisode>)> {
+    use std::hash::Hasher;
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(xml.as_bytes());
+    let hash = hasher.finish();
+
+    if let Some((parsed_hash, podcast, episodes)) = PARSED_FEEDS.lock().unwrap().get(url) {
+        if *parsed_hash == hash {
+            ui.log_debug("reusing parsed feed");
+            return Some((podcast.clone(), episodes.clone()));
+        }
+    }
+
+    let (podcast, episodes) = xml_to_value(xml, ui)?;
+    PARSED_FEEDS
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), (hash, podcast.clone(), episodes.clone()));
+    Some((podcast, episodes))
 }
 
 /// Converts the podcast's xml string to serde values of the channel and the episodes.
 ///
+/// Both RSS and Atom feeds are supported. Atom entries are mapped onto the RSS keys
+/// so that everything downstream can treat them the same.
+///
 /// The library will merge different namespaces together, which is why we manually change
 /// the itunes namespace, and then after converting it, we change it back. Preserving itunes:XXX as
 /// separate keys.
 fn xml_to_value(xml: &str, ui: &DownloadBar) -> Option<(RawPodcast, Vec<RawEpisode>)> {
     ui.log_info("converting xml to serde values");
-    let placeholder = "__placeholder__";
+    // The xml parser strips namespace prefixes, but `itunes:` tags often have a plain
+    // counterpart, e.g. `itunes:image` and `image`, so the prefix is hidden from it. The
+    // placeholder must not occur in the feed already or those keys would be mangled.
+    let mut placeholder = "__placeholder__".to_string();
+    while xml.contains(&placeholder) {
+        placeholder.push('_');
+    }
     let replacement = format!("itunes{}", placeholder);
-    let xml = xml.replace("itunes:", &replacement);
-    let mut val = match get_inner_channel(xml) {
+    // Only tag names are replaced, text mentioning `itunes:` is left alone.
+    let xml = Regex::new(r"<(/?)itunes:")
+        .unwrap()
+        .replace_all(xml, format!("<${{1}}{}", replacement))
+        .to_string();
+    let (kind, val) = match get_inner_channel(xml) {
         Some(val) => val,
         None => {
-            ui.log_error("failed to find rss/channel xml tags");
+            ui.log_error("failed to find rss/channel or feed xml tags");
             return None;
         }
     };
 
-    // Create a new map to store the transformed keys at the top level
-    let mut new_map: Map<String, Value> = Map::new();
+    if kind == FeedKind::Atom {
+        ui.log_debug("parsing feed as atom");
+    }
+
+    let Value::Object(mut channel) = restore_prefix(val, &replacement) else {
+        ui.log_error("unexpected structure of rss/channel or feed xml tags");
+        return None;
+    };
 
-    if let Some(obj) = val.as_object() {
-        for (key, value) in obj {
-            let new_key = key.replace(&replacement, "itunes:");
-            new_map.insert(new_key, value.clone());
+    // A feed with a single episode is parsed as an object rather than an array.
+    let items = match channel.remove(kind.item_key()) {
+        Some(Value::Array(items)) => items,
+        Some(item) => vec![item],
+        None => {
+            ui.log_warn("feed has no episodes");
+            vec![]
         }
+    };
+
+    if kind == FeedKind::Atom {
+        atom_feed_to_rss(&mut channel);
     }
 
-    let podcast = RawPodcast::new(new_map);
+    let podcast = RawPodcast::new(channel);
 
-    let items = std::mem::take(val.as_object_mut()?.get_mut("item")?.as_array_mut()?);
+    let mut skipped = 0;
+    let mut episodes = vec![];
 
-    let episodes = items
-        .iter()
-        .map(|item| {
-            let mut new_item_map: Map<String, Value> = Map::new();
-            for (key, val) in item.as_object().expect("unexpected serde type").iter() {
-                let new_key = key.replace(&replacement, "itunes:");
-                new_item_map.insert(new_key, val.clone());
-            }
-            RawEpisode::new(new_item_map)
-        })
-        .collect::<Vec<RawEpisode>>();
+    for item in items {
+        // Malformed items are skipped so that they don't prevent the other episodes from syncing.
+        let Value::Object(mut item) = item else {
+            skipped += 1;
+            continue;
+        };
+
+        if kind == FeedKind::Atom {
+            atom_entry_to_rss(&mut item);
+        }
+        episodes.push(RawEpisode::new(item));
+    }
+
+    if skipped > 0 {
+        ui.log_warn(format!("skipped {} malformed episodes", skipped));
+    }
 
     Some((podcast, episodes))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawPodcast(Map<String, serde_json::Value>);
 
 impl RawPodcast {
@@ -93,12 +291,42 @@ impl RawPodcast {
         self.get_str(&key)
     }
 
-    pub fn categories(&self) -> Vec<&str> {
-        let key = "itunes:category";
-        match self.0.get(key).and_then(|x| x.as_array()) {
-            Some(v) => v.iter().filter_map(utils::val_to_str).collect(),
-            None => vec![],
+    /// The iTunes categories of the podcast, the first one being the primary category.
+    ///
+    /// Subcategories are nested inside their parent category, and are flattened to
+    /// `Parent/Child`. A parent is only listed on its own if it has no subcategories.
+    pub fn categories(&self) -> Vec<String> {
+        fn flatten(val: &serde_json::Value, parent: Option<&str>, categories: &mut Vec<String>) {
+            // A single category isn't wrapped in an array.
+            let vals = match val {
+                serde_json::Value::Array(vals) => vals.iter().collect(),
+                val => vec![val],
+            };
+
+            for val in vals {
+                let Some(name) = utils::val_to_str(val).map(str::trim) else {
+                    continue;
+                };
+                let name = match parent {
+                    Some(parent) => format!("{}/{}", parent, name),
+                    None => name.to_string(),
+                };
+
+                let before = categories.len();
+                if let Some(children) = val.get("itunes:category") {
+                    flatten(children, Some(&name), categories);
+                }
+                if categories.len() == before {
+                    categories.push(name);
+                }
+            }
         }
+
+        let mut categories = vec![];
+        if let Some(val) = self.0.get("itunes:category") {
+            flatten(val, None, &mut categories);
+        }
+        categories
     }
 
     pub fn copyright(&self) -> Option<&str> {
@@ -106,21 +334,271 @@ impl RawPodcast {
         utils::val_to_str(&inner)
     }
 
+    pub fn explicit(&self) -> Option<bool> {
+        utils::parse_explicit(self.get_str("itunes:explicit")?)
+    }
+
     pub fn language(&self) -> Option<&str> {
         self.get_str("language")
     }
 
+    /// The links where the show can be supported, from `<podcast:funding>` tags.
+    pub fn funding(&self) -> Vec<Funding> {
+        let Some(funding) = self.0.get("funding") else {
+            return vec![];
+        };
+
+        utils::val_to_objects(funding)
+            .into_iter()
+            .filter_map(|obj| {
+                let url = obj.get("@url")?.as_str()?.trim().to_string();
+                let label = obj
+                    .get("#text")
+                    .and_then(utils::val_to_str)
+                    .map(|label| label.trim().to_string())
+                    .filter(|label| !label.is_empty());
+                Some(Funding { url, label })
+            })
+            .collect()
+    }
+
+    /// Whether the whole feed asks not to be distributed with `<itunes:block>`.
+    pub fn blocked(&self) -> bool {
+        self.get_str("itunes:block").is_some_and(utils::parse_block)
+    }
+
+    /// The artwork of the podcast, preferring `<itunes:image>` since it's usually larger than
+    /// the RSS `<image>`.
     pub fn image(&self) -> Option<&str> {
-        let inner = self.0.get("image")?;
-        utils::val_to_url(inner)
+        ["itunes:image", "image"]
+            .into_iter()
+            .find_map(|key| utils::val_to_url(self.0.get(key)?))
+    }
+
+    /// The url the feed moved to, from `<itunes:new-feed-url>`.
+    pub fn new_feed_url(&self) -> Option<&str> {
+        self.get_str("itunes:new-feed-url")
+            .map(str::trim)
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+    }
+
+    /// Link to the next page of a paginated feed (RFC 5005), which holds older episodes.
+    pub fn next_page(&self) -> Option<&str> {
+        utils::val_to_objects(self.0.get("link")?)
+            .into_iter()
+            .find(|link| {
+                let rel = link.get("@rel").and_then(Value::as_str);
+                matches!(rel, Some("next" | "prev-archive"))
+            })?
+            .get("@href")?
+            .as_str()
+    }
+}
+
+/// The publication date, duration and title of an episode, as they're shown in listings.
+fn listing_line(episode: &Episode) -> String {
+    let date = episode
+        .attrs
+        .published()
+        .and_then(|published| chrono::DateTime::from_timestamp(published.as_secs() as i64, 0))
+        .map_or("unknown date".to_string(), |date| {
+            date.format("%Y-%m-%d").to_string()
+        });
+
+    let duration = episode
+        .attrs
+        .duration()
+        .map_or("--:--".to_string(), utils::format_duration);
+
+    format!("{}  {:>8}  {}", date, duration, episode.attrs.title())
+}
+
+/// Sorts the episodes from oldest to newest.
+///
+/// Episodes without a date stay next to the episode that precedes them in the feed,
+/// on the side that matches the order of the feed.
+fn sort_chronologically(attrs: &mut Vec<episode::Attributes>) {
+    let dates: Vec<_> = attrs.iter().filter_map(|attr| attr.published()).collect();
+    let newest_first = dates.first() > dates.last();
+
+    let mut last_date = dates.first().copied().unwrap_or_default();
+    let mut keys = Vec::with_capacity(attrs.len());
+    for (position, attr) in attrs.iter().enumerate() {
+        let date = attr.published().unwrap_or(last_date);
+        last_date = date;
+
+        // Within the same date, the feed order decides.
+        let position = if newest_first {
+            usize::MAX - position
+        } else {
+            position
+        };
+        keys.push((date, position));
+    }
+
+    let mut keyed: Vec<_> = keys.into_iter().zip(attrs.drain(..)).collect();
+    keyed.sort_by_key(|(key, _)| *key);
+    attrs.extend(keyed.into_iter().map(|(_, attr)| attr));
+}
+
+/// Follows the links to the older pages of a paginated feed and returns their episodes.
+///
+/// Stops after `max_pages`, when a page links back to one that was already fetched, or when a
+/// page fails to download, in which case the episodes found so far are still used.
+async fn fetch_next_pages(
+    config: &PodcastConfig,
+    client: &reqwest::Client,
+    retry: &RetryPolicy,
+    ttl: Option<std::time::Duration>,
+    mut next: Option<String>,
+    max_pages: usize,
+    ui: &DownloadBar,
+) -> Vec<RawEpisode> {
+    let mut episodes = vec![];
+    let mut current = config.url.clone();
+    let mut visited = HashSet::from([current.clone()]);
+
+    while let Some(link) = next.take() {
+        // Links may be relative to the page they're on.
+        let url = match reqwest::Url::parse(&current).and_then(|base| base.join(&link)) {
+            Ok(url) => url.to_string(),
+            Err(_) => link,
+        };
+
+        if visited.len() > max_pages {
+            ui.log_warn(format!(
+                "stopped following feed pages after {} pages",
+                max_pages
+            ));
+            break;
+        }
+
+        if !visited.insert(url.clone()) {
+            ui.log_debug(format!("feed pages loop back to {}", &url));
+            break;
+        }
+
+        ui.log_debug(format!("fetching next feed page: {}", &url));
+        let request = config.request_settings().apply(client.get(&url));
+        let xml = match utils::download_text(&url, request, retry, ttl, ui).await {
+            Ok(xml) => xml,
+            Err(e) => {
+                ui.log_warn(format!("failed to download feed page {}: {}", &url, e));
+                break;
+            }
+        };
+
+        let Some((page, page_episodes)) = parse_feed(&url, &xml, ui) else {
+            ui.log_warn(format!("failed to parse feed page {}", &url));
+            break;
+        };
+
+        episodes.extend(page_episodes);
+        next = page.next_page().map(String::from);
+        current = url;
+    }
+
+    episodes
+}
+
+/// A link where a show can be supported, from the `<podcast:funding>` tag.
+#[derive(Debug, Clone, Serialize)]
+pub struct Funding {
+    pub url: String,
+    pub label: Option<String>,
+}
+
+/// An episode that failed to download, while the others of the podcast were still synced.
+#[derive(Debug, Serialize)]
+pub struct FailedEpisode {
+    pub title: String,
+    pub error: CringeError,
+}
+
+/// The outcome of syncing a single podcast.
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    pub name: String,
+    /// The title recorded with `--refresh-titles`, shown instead of the name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_title: Option<String>,
+    pub url: String,
+    pub downloaded: Vec<PathBuf>,
+    /// Episodes that weren't pending, e.g. because they were downloaded previously.
+    pub skipped: usize,
+    pub error: Option<CringeError>,
+    /// Episodes that failed and were skipped, as configured with `on_error`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<FailedEpisode>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub funding: Vec<Funding>,
+    /// Where the feed permanently redirected to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
+    /// The url the feed declares it moved to with `<itunes:new-feed-url>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_feed_url: Option<String>,
+}
+
+impl SyncReport {
+    pub fn display_name(&self) -> &str {
+        self.display_title.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// What's downloaded of a podcast and still on disk, as shown by `--stats`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LibraryStats {
+    pub episodes: usize,
+    /// Total size of the episode files in bytes.
+    pub size: u64,
+    /// Publication date of the oldest episode.
+    pub oldest: Option<Unix>,
+    /// Publication date of the newest episode.
+    pub newest: Option<Unix>,
+}
+
+impl LibraryStats {
+    pub fn add(&mut self, size: u64, published: Option<Unix>) {
+        self.merge(&Self {
+            episodes: 1,
+            size,
+            oldest: published,
+            newest: published,
+        });
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.episodes += other.episodes;
+        self.size += other.size;
+        self.oldest = self.oldest.into_iter().chain(other.oldest).min();
+        self.newest = self.newest.into_iter().chain(other.newest).max();
     }
 }
 
 #[derive(Debug)]
 pub struct Podcast {
+    name: String,
+    display_title: Option<String>,
+    url: String,
     episodes: Vec<Episode>,
     client: Arc<reqwest::Client>,
     mode: DownloadMode,
+    concurrent_downloads: usize,
+    processing_concurrency: usize,
+    generate_playlist: bool,
+    latest_symlink: bool,
+    prune_old: bool,
+    keep_recent: Option<usize>,
+    ignore_block: bool,
+    funding: Vec<Funding>,
+    moved_to: Option<String>,
+    new_feed_url: Option<String>,
+    /// The subscription date to remember after the first sync.
+    new_subscription: Option<Unix>,
+    sort_by_episode_number: bool,
+    max_downloads: Option<usize>,
+    on_error: OnError,
 }
 
 impl Podcast {
@@ -129,81 +607,377 @@ impl Podcast {
         config: PodcastConfig,
         global_config: &GlobalConfig,
         client: Arc<reqwest::Client>,
+        rate_limiter: Option<Arc<utils::RateLimiter>>,
+        ui: &DownloadBar,
+    ) -> Result<Podcast, CringeError> {
+        let xml_string = Self::fetch_feed(&config, global_config, &client, ui).await?;
+
+        let moved_to = utils::permanent_redirect(&config.url);
+        if let Some(new_url) = &moved_to {
+            ui.log_warn(format!("feed permanently moved to {}", new_url));
+        }
+
+        let mut podcast = Self::from_feed(
+            name,
+            &xml_string,
+            config,
+            global_config,
+            client,
+            rate_limiter,
+            ui,
+        )
+        .await?;
+        podcast.moved_to = moved_to;
+        Ok(podcast)
+    }
+
+    /// Downloads the feed of the podcast.
+    pub async fn fetch_feed(
+        config: &PodcastConfig,
+        global_config: &GlobalConfig,
+        client: &reqwest::Client,
         ui: &DownloadBar,
-    ) -> Result<Podcast, String> {
+    ) -> Result<String, CringeError> {
         ui.fetching();
         ui.log_info("downloading podcast info...");
-        let Some(xml_string) = utils::download_text(&client, &config.url, ui).await else {
-            return Err("failed to download xml-file".into());
-        };
+        let retry = RetryPolicy::new(global_config);
+        let request = config.request_settings().apply(client.get(&config.url));
+        let ttl = global_config.feed_cache_ttl();
+        utils::download_text(&config.url, request, &retry, ttl, ui).await
+    }
+
+    /// The title of the podcast in a downloaded feed.
+    pub fn feed_title(url: &str, xml_string: &str, ui: &DownloadBar) -> Option<String> {
+        let (raw_podcast, _) = parse_feed(url, xml_string, ui)?;
+        raw_podcast
+            .get_str("title")
+            .map(|title| title.trim().to_string())
+    }
 
-        let Some((raw_podcast, raw_episodes)) = xml_to_value(&xml_string, ui) else {
-            return Err("failed to parse xml".into());
+    /// Builds the podcast from its downloaded feed.
+    pub async fn from_feed(
+        name: String,
+        xml_string: &str,
+        config: PodcastConfig,
+        global_config: &GlobalConfig,
+        client: Arc<reqwest::Client>,
+        rate_limiter: Option<Arc<utils::RateLimiter>>,
+        ui: &DownloadBar,
+    ) -> Result<Podcast, CringeError> {
+        let retry = RetryPolicy::new(global_config);
+        let Some((raw_podcast, mut raw_episodes)) = parse_feed(&config.url, xml_string, ui) else {
+            return Err(CringeError::Parse("parse xml".to_string()));
         };
+        let raw_podcast = Arc::new(raw_podcast);
+
+        let max_pages = config.max_feed_pages(global_config);
+        let next_page = raw_podcast.next_page().map(String::from);
+        if max_pages > 0 && next_page.is_some() {
+            let ttl = global_config.feed_cache_ttl();
+            let pages =
+                fetch_next_pages(&config, &client, &retry, ttl, next_page, max_pages, ui).await;
+            raw_episodes.extend(pages);
+        }
+
+        if let Some(title) = raw_podcast.get_str("title") {
+            if crate::cache::TitleMap::update(&config.url, title).is_none() {
+                ui.log_warn("failed to cache podcast title");
+            }
+        }
+
+        let all_enclosures = config.all_enclosures(global_config);
+
+        // Filtered before indexing so the download modes only consider the matching episodes.
+        let filter = config.episode_filter().map_err(CringeError::Config)?;
 
         let episode_attrs = {
             let mut attrs = vec![];
+            // Paginated feeds may repeat episodes across pages, and some feeds simply list an
+            // episode twice. The first occurrence is kept.
+            let mut guids = HashSet::new();
+            let mut urls = HashSet::new();
+            let mut duplicates = 0;
 
             for episode in raw_episodes {
                 ui.log_trace("parsing attributes from raw episode");
-                match episode::Attributes::new(episode) {
-                    Ok(attr) => attrs.push(attr),
+                let episode = episode::Attributes::new(episode).map(|attr| {
+                    let new_url = urls.insert(attr.url.clone());
+                    // The guid falls back to the enclosure url when the feed doesn't have one, in
+                    // which case a copy that does have a guid would otherwise slip through.
+                    let duplicate =
+                        !guids.insert(attr.guid.clone()) || (attr.guid == attr.url && !new_url);
+                    (attr, duplicate)
+                });
+
+                match episode {
+                    Ok((attr, true)) => {
+                        ui.log_trace(format!("duplicate episode: {}", attr.title()));
+                        duplicates += 1;
+                    }
+                    Ok((attr, false)) if filter.matches(&attr) => attrs.push(attr),
+                    Ok((attr, false)) => {
+                        ui.log_trace(format!("episode filtered out: {}", attr.title()));
+                    }
                     Err(e) => {
                         ui.log_debug(e);
                     }
                 }
             }
 
-            attrs.sort_by_key(|attr| attr.published());
+            if duplicates > 0 {
+                ui.log_debug(format!("collapsed {} duplicate episodes", duplicates));
+            }
+
+            sort_chronologically(&mut attrs);
+
+            if all_enclosures {
+                attrs = attrs
+                    .into_iter()
+                    .flat_map(|attr| {
+                        let extra = attr.extra_enclosures();
+                        std::iter::once(attr).chain(extra)
+                    })
+                    .collect();
+            }
+
             attrs
         };
 
+        let tag_templates = config.tag_templates(global_config);
         let mut episodes = vec![];
-        for (index, attr) in episode_attrs.into_iter().enumerate() {
-            let tags = tags::extract_tags_from_raw(&raw_podcast, &attr, ui).await;
+        for (index, mut attr) in episode_attrs.into_iter().enumerate() {
+            let data = EvalData::new(&name, &raw_podcast, &attr);
+            let tags = tags::extract_tags_from_raw(data, &tag_templates, ui).await;
             let config = {
                 let data = EvalData::new(&name, &raw_podcast, &attr);
-                Config::new(global_config, &config, data)
+                let mut config = Config::new(global_config, &config, data);
+                config.rate_limiter = rate_limiter.clone();
+                config
             };
 
+            // Picking one enclosure makes no sense when all of them are downloaded.
+            if !all_enclosures {
+                attr.select_enclosure(config.preferred_format.as_deref(), config.preferred_bitrate);
+            }
+
             let url = attr
                 .image()
                 .ok()
                 .or(raw_podcast.image())
                 .map(ToString::to_string);
 
-            let episode = Episode::new(attr, index, config, tags, url);
+            let podcast = Arc::clone(&raw_podcast);
+            let episode = Episode::new(attr, index, config, tags, url, podcast);
             episodes.push(episode);
         }
 
-        let mode = DownloadMode::new(global_config, &config);
+        let mut mode = DownloadMode::new(global_config, &config);
+        let mut new_subscription = None;
+        if let DownloadMode::Standard { subscribed_at, .. } = &mut mode {
+            *subscribed_at = match config.subscribed_at()? {
+                ConfigOption::Enabled(date) => Some(date),
+                ConfigOption::Disabled => None,
+                ConfigOption::UseGlobal => match crate::cache::Subscriptions::get(&config.url) {
+                    Some(date) => Some(date),
+                    None => {
+                        new_subscription = Some(subscription_start(&episodes));
+                        new_subscription
+                    }
+                },
+            };
+        }
+        if let DownloadMode::Backlog {
+            earliest, skipped, ..
+        } = &mut mode
+        {
+            *skipped = episodes
+                .iter()
+                .filter(|episode| episode.before_backlog(*earliest))
+                .count();
+        }
+        ui.feed_fetched(episodes.len());
+
+        let new_feed_url = raw_podcast
+            .new_feed_url()
+            .filter(|url| *url != config.url)
+            .map(String::from);
+        if let Some(new_url) = &new_feed_url {
+            ui.log_warn(format!(
+                "feed declares a new url: {} -> {}",
+                &config.url, new_url
+            ));
+        }
+
+        if raw_podcast.blocked() {
+            match config.ignore_block(global_config) {
+                true => {
+                    ui.log_warn("feed is blocked, downloading anyway since ignore_block is set")
+                }
+                false => ui.log_warn("feed is blocked, none of its episodes will be downloaded"),
+            }
+        }
 
         Ok(Podcast {
+            url: config.url.clone(),
+            display_title: config.display_title().map(String::from),
+            name,
             episodes,
             client,
             mode,
+            concurrent_downloads: global_config.concurrent_downloads(),
+            processing_concurrency: global_config.processing_concurrency(),
+            generate_playlist: config.generate_playlist(global_config),
+            latest_symlink: config.latest_symlink(global_config),
+            prune_old: config.prune_old(),
+            keep_recent: config.keep_recent(global_config),
+            ignore_block: config.ignore_block(global_config),
+            funding: raw_podcast.funding(),
+            moved_to: None,
+            new_feed_url,
+            new_subscription,
+            sort_by_episode_number: config.sort_by_episode_number(global_config),
+            max_downloads: config.max_downloads_per_run(global_config),
+            on_error: config.on_error(global_config),
         })
     }
 
-    pub async fn sync(self, ui: &mut DownloadBar) -> Vec<PathBuf> {
+    /// Replaces the configured download mode with the episodes published since the given time,
+    /// for this run only.
+    pub fn since(mut self, since: Option<Unix>) -> Self {
+        if let Some(since) = since {
+            self.mode = DownloadMode::DateRange {
+                after: Some(since),
+                before: None,
+            };
+        }
+        self
+    }
+
+    /// Downloads the pending episodes.
+    ///
+    /// With `dry_run` enabled nothing is downloaded, the episodes are listed on stdout instead.
+    pub async fn sync(self, ui: &mut DownloadBar, dry_run: bool) -> SyncReport {
         ui.init();
         ui.log_info("syncing...");
 
-        let episodes = self.pending_episodes();
-        let mut downloaded = vec![];
+        let (episodes, deferred) = self.pending_episodes(ui);
+        if dry_run {
+            return self.dry_run(&episodes, deferred, ui);
+        }
 
-        for (index, episode) in episodes.iter().enumerate() {
-            ui.begin_download(&episode, index, episodes.len());
+        let report = self.download(&episodes, deferred, ui).await;
+        if report.error.is_none()
+            && crate::cache::LastSync::save(&self.url, utils::current_unix()).is_none()
+        {
+            ui.log_warn("failed to remember when the podcast was synced");
+        }
 
-            match episode.download(&self.client, ui).await {
-                Ok(downloaded_episode) => downloaded.push(downloaded_episode),
-                Err(e) => {
-                    ui.error(&e);
-                    break;
-                }
+        report
+    }
+
+    /// Adds up the downloaded episodes whose files are still around.
+    pub fn library_stats(&self) -> LibraryStats {
+        let mut stats = LibraryStats::default();
+
+        for episode in &self.episodes {
+            let Some(path) = episode.downloaded_path() else {
+                continue;
             };
+            let size = fs::metadata(&path).map_or(0, |meta| meta.len());
+            stats.add(size, episode.attrs.published());
         }
 
+        stats
+    }
+
+    /// Lists every episode, newest first, marking the ones that are downloaded or pending.
+    ///
+    /// This is the menu that [`Self::download_selected`] picks from.
+    pub fn menu(&self, ui: &DownloadBar) -> Vec<String> {
+        let pending: HashSet<usize> = self
+            .filter_pending(ui)
+            .iter()
+            .map(|episode| episode.index)
+            .collect();
+
+        self.episodes
+            .iter()
+            .rev()
+            .map(|episode| {
+                let status = if pending.contains(&episode.index) {
+                    "pending"
+                } else if episode.is_downloaded() {
+                    "downloaded"
+                } else {
+                    ""
+                };
+                format!("{:<10}  {}", status, listing_line(episode))
+            })
+            .collect()
+    }
+
+    /// Downloads the episodes at the given positions of [`Self::menu`], regardless of the
+    /// download mode and whether they're downloaded already.
+    pub async fn download_selected(self, selected: &[usize], ui: &mut DownloadBar) -> SyncReport {
+        ui.init();
+        ui.log_info("downloading selected episodes...");
+
+        let newest_first: Vec<&Episode> = self.episodes.iter().rev().collect();
+        let episodes: Vec<&Episode> = selected
+            .iter()
+            .filter_map(|&position| newest_first.get(position).copied())
+            .collect();
+
+        self.download(&episodes, 0, ui).await
+    }
+
+    /// Whether the feed has an episode with the guid.
+    pub fn has_guid(&self, guid: &str) -> bool {
+        self.episodes
+            .iter()
+            .any(|episode| episode.attrs.guid == guid)
+    }
+
+    /// Downloads the episodes with the guids again, however they're tracked, replacing their
+    /// files.
+    pub async fn redownload(self, guids: &[String], ui: &mut DownloadBar) -> SyncReport {
+        ui.init();
+        ui.log_info("downloading episodes again...");
+
+        let episodes: Vec<&Episode> = self
+            .episodes
+            .iter()
+            .filter(|episode| guids.contains(&episode.attrs.guid))
+            .collect();
+
+        for episode in &episodes {
+            episode.discard_partial();
+        }
+
+        self.download(&episodes, 0, ui).await
+    }
+
+    /// Downloads the episodes and runs everything that comes after, like the download hooks and
+    /// the playlist.
+    async fn download(
+        &self,
+        episodes: &[&Episode],
+        deferred: usize,
+        ui: &mut DownloadBar,
+    ) -> SyncReport {
+        if let Some(date) = self.new_subscription {
+            if crate::cache::Subscriptions::save(&self.url, date).is_none() {
+                ui.log_warn("failed to remember when the podcast was subscribed to");
+            }
+        }
+
+        let mut failed = vec![];
+        let (downloaded, error) = if self.concurrent_downloads > 1 {
+            self.download_concurrently(episodes, &mut failed, ui).await
+        } else {
+            self.download_sequentially(episodes, &mut failed, ui).await
+        };
+
         let mut paths = vec![];
 
         ui.hook_status();
@@ -212,31 +986,900 @@ impl Podcast {
             paths.push(episode.into_path());
         }
 
+        if self.prune_old {
+            self.prune_old(ui);
+        }
+
+        if let Some(count) = self.keep_recent {
+            self.keep_recent(count, ui);
+        }
+
+        if self.generate_playlist {
+            if let Err(e) = self.write_playlist(ui) {
+                ui.log_error(format!("failed to write playlist: {}", e));
+            }
+        }
+
+        if self.latest_symlink && !paths.is_empty() {
+            if let Err(e) = self.link_latest(ui) {
+                ui.log_error(format!("failed to link latest episode: {}", e));
+            }
+        }
+
+        if !failed.is_empty() {
+            ui.error(&format!(
+                "{} of {} episodes failed",
+                failed.len(),
+                episodes.len()
+            ));
+        } else if deferred > 0 {
+            ui.log_info(format!("{} episodes deferred to the next sync", deferred));
+            ui.complete_deferred(paths.len(), episodes.len() + deferred);
+        } else {
+            ui.complete();
+        }
+
+        SyncReport {
+            skipped: self.episodes.len() - episodes.len(),
+            name: self.name.clone(),
+            display_title: self.display_title.clone(),
+            url: self.url.clone(),
+            downloaded: paths,
+            error,
+            failed,
+            funding: self.funding.clone(),
+            moved_to: self.moved_to.clone(),
+            new_feed_url: self.new_feed_url.clone(),
+        }
+    }
+
+    /// Lists what a sync would download, without writing any files or tracking state.
+    fn dry_run(&self, episodes: &[&Episode], deferred: usize, ui: &mut DownloadBar) -> SyncReport {
+        let mut paths = vec![];
+        let mut listing = format!("{}:", self.name);
+
+        for episode in episodes {
+            let path = match episode.planned_path() {
+                Ok(path) => path,
+                Err(e) => {
+                    ui.log_warn(format!("{}: {}", episode.attrs.title(), e));
+                    continue;
+                }
+            };
+
+            let size = episode
+                .attrs
+                .enclosure_length()
+                .map_or("unknown size".to_string(), |len| {
+                    indicatif::HumanBytes(len).to_string()
+                });
+
+            listing.push_str(&format!(
+                "\n  {} ({}) -> {}",
+                episode.attrs.title(),
+                size,
+                path.display()
+            ));
+            paths.push(path);
+        }
+
+        if deferred > 0 {
+            listing.push_str(&format!("\n  {} more deferred to a later sync", deferred));
+        }
+
+        // Printed at once so the listings of podcasts synced at the same time don't interleave.
+        if !episodes.is_empty() {
+            println!("{}", listing);
+        }
+
         ui.complete();
-        paths
+
+        SyncReport {
+            skipped: self.episodes.len() - episodes.len(),
+            name: self.name.clone(),
+            display_title: self.display_title.clone(),
+            url: self.url.clone(),
+            downloaded: paths,
+            error: None,
+            failed: vec![],
+            funding: self.funding.clone(),
+            moved_to: self.moved_to.clone(),
+            new_feed_url: self.new_feed_url.clone(),
+        }
+    }
+
+    /// Lists the pending episodes with their publication date and duration.
+    ///
+    /// Unlike a dry run this ignores `max_downloads_per_run`, since the point is to see everything
+    /// the filters let through.
+    pub fn list_pending(
+        &self,
+        order: ListOrder,
+        limit: Option<usize>,
+        ui: &DownloadBar,
+    ) -> Option<String> {
+        let mut pending = self.filter_pending(ui);
+
+        if pending.is_empty() {
+            return None;
+        }
+
+        match order {
+            ListOrder::Date => pending.sort_by_key(|ep| std::cmp::Reverse(ep.index)),
+            ListOrder::Title => pending.sort_by_key(|ep| ep.attrs.title().to_lowercase()),
+            ListOrder::Duration => pending.sort_by_key(|ep| std::cmp::Reverse(ep.attrs.duration())),
+        }
+
+        let total = pending.len();
+        if let Some(limit) = limit {
+            pending.truncate(limit);
+        }
+
+        let mut listing = format!("{}:", self.name);
+        for episode in &pending {
+            listing.push_str(&format!("\n  {}", listing_line(episode)));
+        }
+
+        if total > pending.len() {
+            listing.push_str(&format!("\n  ... and {} more", total - pending.len()));
+        }
+
+        Some(listing)
     }
 
-    fn pending_episodes(&self) -> Vec<&Episode> {
+    pub fn episode_qty(&self) -> usize {
+        self.episodes.len()
+    }
+
+    /// Deletes the episodes that were downloaded longer than `max_age` ago, returning how many
+    /// files were deleted and how many bytes were freed.
+    ///
+    /// They're kept in the download tracker so that they aren't downloaded again.
+    pub fn prune_older_than(&self, max_age: std::time::Duration, ui: &DownloadBar) -> (usize, u64) {
+        let mut files = 0;
+        let mut freed = 0;
+
+        for episode in &self.episodes {
+            if episode.download_age().is_none_or(|age| age <= max_age) {
+                continue;
+            }
+
+            match episode.remove_files(ui) {
+                Ok(0) => {}
+                Ok(bytes) => {
+                    files += 1;
+                    freed += bytes;
+                }
+                Err(e) => {
+                    ui.log_error(format!("failed to prune {}: {}", episode.attrs.title(), e));
+                }
+            }
+        }
+
+        (files, freed)
+    }
+
+    /// Deletes the downloaded episodes that are no longer among the latest ones.
+    fn prune_old(&self, ui: &DownloadBar) {
+        let DownloadMode::LatestN { count } = self.mode else {
+            return;
+        };
+
         let qty = self.episodes.len();
+        for episode in &self.episodes {
+            if episode.outside_window(count, qty) && episode.is_downloaded() {
+                if let Err(e) = episode.prune(ui) {
+                    ui.log_error(format!("failed to prune {}: {}", episode.attrs.title(), e));
+                }
+            }
+        }
+    }
 
-        let mut pending: Vec<&Episode> = self
+    /// Deletes the files of the downloaded episodes beyond the `count` most recently published
+    /// ones. They stay in the download tracker, so they aren't downloaded again.
+    fn keep_recent(&self, count: usize, ui: &DownloadBar) {
+        let mut downloaded: Vec<&Episode> = self
             .episodes
             .iter()
-            .filter(|episode| episode.should_download(&self.mode, qty))
+            .filter(|episode| episode.downloaded_path().is_some())
             .collect();
+        downloaded
+            .sort_by_key(|episode| std::cmp::Reverse((episode.attrs.published(), episode.index)));
+
+        for episode in downloaded.into_iter().skip(count) {
+            match episode.remove_files(ui) {
+                Ok(0) => {}
+                Ok(bytes) => ui.log_info(format!(
+                    "removed {} to keep the {} most recent episodes, freeing {}",
+                    episode.attrs.title(),
+                    count,
+                    indicatif::HumanBytes(bytes)
+                )),
+                Err(e) => {
+                    ui.log_error(format!("failed to remove {}: {}", episode.attrs.title(), e));
+                }
+            }
+        }
+    }
+
+    /// Writes an m3u8 playlist of all the downloaded episodes to the download directory.
+    ///
+    /// The paths are relative to the playlist, so it keeps working if the directory is moved.
+    fn write_playlist(&self, ui: &DownloadBar) -> Result<(), String> {
+        let Some(first) = self.episodes.first() else {
+            return Ok(());
+        };
+
+        let dir = &first.config.download_path;
+        let mut playlist = String::from("#EXTM3U\n");
+
+        // Episodes are sorted chronologically, which is also the play order.
+        for episode in &self.episodes {
+            let Some(path) = episode.downloaded_path() else {
+                continue;
+            };
 
-        // In backlog mode it makes more sense to download earliest episode first.
-        // in standard mode, the most recent episodes are more relevant.
-        match self.mode {
-            DownloadMode::Backlog { .. } => {
-                pending.sort_by_key(|ep| ep.index);
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+
+            let duration = episode
+                .attrs
+                .duration()
+                .map_or(-1, |duration| duration.as_secs() as i64);
+            let title = episode.attrs.title().replace('\n', " ");
+
+            playlist.push_str(&format!("#EXTINF:{},{}\n", duration, title));
+            playlist.push_str(&format!("{}\n", relative.to_string_lossy()));
+        }
+
+        let path = dir.join("playlist.m3u8");
+        ui.log_debug(format!("writing playlist to {:?}", &path));
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        fs::write(&path, playlist).map_err(|e| e.to_string())
+    }
+
+    /// Points `latest.<ext>` in the download directory to the most recently published episode.
+    ///
+    /// It's a symlink where supported, and a copy elsewhere.
+    fn link_latest(&self, ui: &DownloadBar) -> Result<(), String> {
+        let Some(first) = self.episodes.first() else {
+            return Ok(());
+        };
+
+        // Episodes are sorted chronologically.
+        let Some(latest) = self
+            .episodes
+            .iter()
+            .rev()
+            .find_map(|episode| episode.downloaded_path())
+        else {
+            return Ok(());
+        };
+
+        let dir = &first.config.download_path;
+        let link = match latest.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => dir.join(format!("latest.{}", ext)),
+            None => dir.join("latest"),
+        };
+
+        if fs::read_link(&link).is_ok_and(|target| target == latest) {
+            return Ok(());
+        }
+
+        // Also removes links with another extension, in case the format changed.
+        for entry in fs::read_dir(dir).map_err(|e| e.to_string())?.flatten() {
+            let path = entry.path();
+            if path.file_stem().is_some_and(|stem| stem == "latest") {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+        }
+
+        ui.log_debug(format!("linking {:?} to {:?}", &link, &latest));
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&latest, &link).map_err(|e| e.to_string())?;
+
+        #[cfg(not(unix))]
+        fs::copy(&latest, &link).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    async fn download_sequentially<'a>(
+        &'a self,
+        episodes: &[&'a Episode],
+        failed: &mut Vec<FailedEpisode>,
+        ui: &mut DownloadBar,
+    ) -> (Vec<DownloadedEpisode<'a>>, Option<CringeError>) {
+        if self.processing_concurrency > 0 {
+            return self.download_pipelined(episodes, failed, ui).await;
+        }
+
+        let mut downloaded = vec![];
+
+        for (index, episode) in episodes.iter().enumerate() {
+            if utils::is_interrupted() {
+                return (downloaded, Some(interrupted(ui)));
             }
-            DownloadMode::Standard { .. } => {
-                pending.sort_by_key(|ep| ep.index);
-                pending.reverse();
+
+            ui.begin_download(episode, index, episodes.len());
+
+            let download = || episode.download(&self.client, ui);
+            match self.with_retry(episode, ui, download).await {
+                Ok(downloaded_episode) => {
+                    ui.finish_download(downloaded_episode.path());
+                    downloaded.push(downloaded_episode);
+                }
+                Err(e) if self.on_error.skips() => failed.push(skip_failed(episode, e, ui)),
+                Err(e) => {
+                    ui.error(&e);
+                    return (downloaded, Some(e));
+                }
+            };
+        }
+
+        (downloaded, None)
+    }
+
+    /// Downloads the episodes one by one like [`Self::download_sequentially`], but processes
+    /// them in the background, up to `processing_concurrency` at a time, so the next download
+    /// doesn't have to wait for the tags and transcoding of the previous one.
+    ///
+    /// Unless the episodes that fail are skipped, no new downloads are started after an error
+    /// occurs, but the episodes that are already being processed are finished.
+    async fn download_pipelined<'a>(
+        &'a self,
+        episodes: &[&'a Episode],
+        failed: &mut Vec<FailedEpisode>,
+        ui: &mut DownloadBar,
+    ) -> (Vec<DownloadedEpisode<'a>>, Option<CringeError>) {
+        let semaphore = Semaphore::new(self.processing_concurrency);
+        let mut processing = stream::FuturesUnordered::new();
+        let mut results = vec![];
+        let mut error = None;
+
+        for (index, episode) in episodes.iter().enumerate() {
+            if !self.on_error.skips()
+                && results
+                    .iter()
+                    .any(|(_, res): &(usize, Result<_, _>)| res.is_err())
+            {
+                break;
+            }
+
+            if utils::is_interrupted() {
+                error = Some(interrupted(ui));
+                break;
             }
+
+            ui.begin_download(episode, index, episodes.len());
+
+            // The processing continues while the next episode downloads.
+            let fetched = {
+                let fetch = self.with_retry(episode, ui, || episode.fetch(&self.client, ui));
+                tokio::pin!(fetch);
+                loop {
+                    tokio::select! {
+                        res = &mut fetch => break res,
+                        Some(res) = processing.next() => results.push(res),
+                    }
+                }
+            };
+
+            let mut downloaded_episode = match fetched {
+                Ok(downloaded_episode) => downloaded_episode,
+                Err(e) if self.on_error.skips() => {
+                    failed.push(skip_failed(episode, e, ui));
+                    continue;
+                }
+                Err(e) => {
+                    ui.error(&e);
+                    error = Some(e);
+                    break;
+                }
+            };
+
+            let mut bar = ui.child();
+            bar.processing(episode);
+            let semaphore = &semaphore;
+            processing.push(async move {
+                let _permit = semaphore.acquire().await;
+                let res = downloaded_episode.finish(&self.client, &bar).await;
+                if res.is_ok() {
+                    bar.finish_download(downloaded_episode.path());
+                }
+                bar.clear();
+                (index, res.map(|_| downloaded_episode))
+            });
         }
 
-        pending
+        ui.hook_status();
+        results.extend(processing.collect::<Vec<_>>().await);
+        results.sort_by_key(|(index, _)| *index);
+
+        let mut downloaded = vec![];
+        for (index, res) in results {
+            match res {
+                Ok(downloaded_episode) => downloaded.push(downloaded_episode),
+                Err(e) if self.on_error.skips() => {
+                    failed.push(skip_failed(episodes[index], e, ui));
+                }
+                Err(e) => {
+                    ui.error(&e);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+
+        (downloaded, error)
+    }
+
+    /// Downloads multiple episodes at once, each with their own progress bar.
+    ///
+    /// Like the sequential downloads, no new downloads are started after an error occurs unless
+    /// the episodes that fail are skipped. The downloaded episodes are returned in the same order
+    /// as they were passed in.
+    async fn download_concurrently<'a>(
+        &'a self,
+        episodes: &[&'a Episode],
+        failed: &mut Vec<FailedEpisode>,
+        ui: &mut DownloadBar,
+    ) -> (Vec<DownloadedEpisode<'a>>, Option<CringeError>) {
+        let qty = episodes.len();
+        let error_occured = AtomicBool::new(false);
+        let finished = AtomicUsize::new(0);
+        let semaphore = Semaphore::new(self.processing_concurrency);
+        let processing = (self.processing_concurrency > 0).then_some(&semaphore);
+
+        let mut results: Vec<_> = {
+            let ui: &DownloadBar = ui;
+            ui.concurrent_status(0, qty);
+
+            let mut futures = vec![];
+            for (index, episode) in episodes.iter().enumerate() {
+                let error_occured = &error_occured;
+                let finished = &finished;
+
+                futures.push(async move {
+                    if error_occured.load(Ordering::SeqCst) || utils::is_interrupted() {
+                        return None;
+                    }
+
+                    let mut bar = ui.child();
+                    bar.init();
+                    bar.begin_download(episode, index, qty);
+                    let res = match processing {
+                        // At most `processing_concurrency` of the downloaded episodes are
+                        // processed at the same time, the others wait for their turn.
+                        Some(semaphore) => match self
+                            .with_retry(episode, &bar, || episode.fetch(&self.client, &bar))
+                            .await
+                        {
+                            Ok(mut downloaded_episode) => {
+                                let _permit = semaphore.acquire().await;
+                                bar.processing(episode);
+                                let res = downloaded_episode.finish(&self.client, &bar).await;
+                                res.map(|_| downloaded_episode)
+                            }
+                            Err(e) => Err(e),
+                        },
+                        None => {
+                            let download = || episode.download(&self.client, &bar);
+                            self.with_retry(episode, &bar, download).await
+                        }
+                    };
+                    if let Ok(downloaded_episode) = &res {
+                        bar.finish_download(downloaded_episode.path());
+                    }
+                    bar.clear();
+
+                    if res.is_err() && !self.on_error.skips() {
+                        error_occured.store(true, Ordering::SeqCst);
+                    }
+
+                    ui.concurrent_status(finished.fetch_add(1, Ordering::SeqCst) + 1, qty);
+                    Some((index, res))
+                });
+            }
+
+            stream::iter(futures)
+                .buffer_unordered(self.concurrent_downloads)
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .flatten()
+                .collect()
+        };
+
+        results.sort_by_key(|(index, _)| *index);
+
+        let started = results.len();
+        let mut downloaded = vec![];
+        let mut error = None;
+        for (index, res) in results {
+            match res {
+                Ok(downloaded_episode) => downloaded.push(downloaded_episode),
+                Err(e) if self.on_error.skips() => {
+                    failed.push(skip_failed(episodes[index], e, ui));
+                }
+                Err(e) => {
+                    ui.error(&e);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+
+        if error.is_none() && started < qty && utils::is_interrupted() {
+            error = Some(interrupted(ui));
+        }
+
+        (downloaded, error)
+    }
+
+    /// Downloads the episode again after it fails, if the `on_error` policy says so.
+    async fn with_retry<'a, F, Fut>(
+        &self,
+        episode: &Episode,
+        ui: &DownloadBar,
+        download: F,
+    ) -> Result<DownloadedEpisode<'a>, CringeError>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<DownloadedEpisode<'a>, CringeError>>,
+    {
+        match download().await {
+            Err(e) if self.on_error == OnError::RetryThenSkip && !utils::is_interrupted() => {
+                episode.log_warn(ui, format!("download failed, trying again: {}", e));
+                download().await
+            }
+            res => res,
+        }
+    }
+
+    /// The episodes to download in this sync, along with how many were deferred to a later one.
+    fn pending_episodes(&self, ui: &DownloadBar) -> (Vec<&Episode>, usize) {
+        let mut pending = self.filter_pending(ui);
+
+        // The numbering is only used if every episode has one, otherwise they can't be compared.
+        let numbered = self.sort_by_episode_number
+            && pending
+                .iter()
+                .all(|ep| ep.attrs.season().is_some() && ep.attrs.episode_number().is_some());
+
+        if numbered {
+            pending.sort_by_key(|ep| (ep.attrs.season(), ep.attrs.episode_number(), ep.index));
+        } else {
+            pending.sort_by_key(|ep| ep.index);
+        }
+
+        // In backlog and date range mode it makes more sense to download earliest episode first.
+        // in standard and latest mode, the most recent episodes are more relevant.
+        if let DownloadMode::Standard { .. } | DownloadMode::LatestN { .. } = self.mode {
+            pending.reverse();
+        }
+
+        let mut deferred = 0;
+        if let Some(max) = self.max_downloads {
+            deferred = pending.len().saturating_sub(max);
+            pending.truncate(max);
+        }
+
+        ui.log_debug(format!(
+            "{} of {} episodes pending, {} deferred",
+            pending.len(),
+            self.episodes.len(),
+            deferred
+        ));
+
+        (pending, deferred)
+    }
+
+    /// The episodes that should be downloaded, in chronological order.
+    fn filter_pending(&self, ui: &DownloadBar) -> Vec<&Episode> {
+        let qty = self.episodes.len();
+
+        self.episodes
+            .iter()
+            .filter(|episode| {
+                if !self.ignore_block && episode.blocked() {
+                    episode.log_trace(ui, "skipping: blocked by the feed");
+                    return false;
+                }
+
+                let download = episode.should_download(&self.mode, qty);
+                if !download {
+                    let reason = match episode.is_downloaded() {
+                        true => "already downloaded",
+                        false => "excluded by download mode",
+                    };
+                    episode.log_trace(ui, format!("skipping: {}", reason));
+                }
+                download
+            })
+            .collect()
+    }
+}
+
+/// When a podcast that hasn't been synced before counts as subscribed to.
+///
+/// That's now, unless episodes were downloaded before the subscription was remembered, in which
+/// case it's the oldest of those so the episodes after it are still downloaded.
+fn subscription_start(episodes: &[Episode]) -> Unix {
+    episodes
+        .iter()
+        .filter(|episode| episode.is_downloaded())
+        .filter_map(|episode| episode.attrs.published())
+        .min()
+        .unwrap_or_else(utils::current_unix)
+}
+
+/// Records an episode that failed to download, so the sync can go on with the next one.
+fn skip_failed(episode: &Episode, error: CringeError, ui: &DownloadBar) -> FailedEpisode {
+    let title = episode.attrs.title().to_string();
+    ui.log_error(format!("skipping {}: {}", &title, &error));
+    FailedEpisode { title, error }
+}
+
+/// Shows that the sync stopped before all the episodes were downloaded, after an
+/// [`utils::interrupt`].
+fn interrupted(ui: &mut DownloadBar) -> CringeError {
+    let e = CringeError::Interrupted;
+    ui.error(&e);
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ui() -> DownloadBar {
+        let hidden =
+            indicatif::MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        DownloadBar::new("test".to_string(), Default::default(), &hidden, 4)
+    }
+
+    fn attributes(xml: &str) -> (RawPodcast, Vec<Result<episode::Attributes, String>>) {
+        let (podcast, episodes) = xml_to_value(xml, &ui()).unwrap();
+        let attrs = episodes.into_iter().map(episode::Attributes::new).collect();
+        (podcast, attrs)
+    }
+
+    /// Builds the podcast from the feed, with the podcast settings given as toml.
+    async fn podcast(xml: &str, settings: &str) -> Podcast {
+        // Parsing the feed updates the caches.
+        utils::set_data_dir(
+            std::env::temp_dir().join(format!("talecast-test-{}", std::process::id())),
+        );
+
+        let url = "https://example.com/feed.xml";
+        let config: PodcastConfig =
+            toml::from_str(&format!("url = \"{}\"\n{}", url, settings)).unwrap();
+        let global_config = GlobalConfig::default();
+        let client = crate::config::init_reqwest_client(&global_config);
+        Podcast::from_feed(
+            "test".to_string(),
+            xml,
+            config,
+            &global_config,
+            client,
+            None,
+            &ui(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn titles(podcast: &Podcast) -> Vec<&str> {
+        podcast
+            .episodes
+            .iter()
+            .map(|episode| episode.attrs.title())
+            .collect()
+    }
+
+    #[test]
+    fn atom_entries_map_onto_rss_items() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Atom Cast</title>
+  <subtitle>A podcast in Atom</subtitle>
+  <logo>https://example.com/logo.png</logo>
+  <entry>
+    <title>First</title>
+    <id>urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a</id>
+    <published>2024-03-01T10:00:00Z</published>
+    <updated>2024-03-02T10:00:00Z</updated>
+    <summary>The first one</summary>
+    <author><name>Jane</name></author>
+    <link rel="alternate" href="https://example.com/1"/>
+    <link rel="enclosure" href="https://example.com/1.mp3" type="audio/mpeg" length="1234"/>
+  </entry>
+  <entry>
+    <title>Second</title>
+    <id>tag:example.com,2024:2</id>
+    <updated>2024-03-08T10:00:00Z</updated>
+    <link rel="enclosure" href="https://example.com/2.m4a" type="audio/mp4"/>
+  </entry>
+</feed>"#;
+
+        let (podcast, attrs) = attributes(xml);
+        assert_eq!(podcast.title(), "Atom Cast");
+        assert_eq!(podcast.image(), Some("https://example.com/logo.png"));
+
+        let first = attrs[0].as_ref().unwrap();
+        assert_eq!(first.url, "https://example.com/1.mp3");
+        assert_eq!(first.mime.as_deref(), Some("audio/mpeg"));
+        assert_eq!(first.length, Some(1234));
+        assert_eq!(first.guid, "urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a");
+        // `published` is preferred over `updated`.
+        assert_eq!(first.published, Some(Unix::from_secs(1709287200)));
+        assert_eq!(first.author().ok(), Some("Jane"));
+
+        let second = attrs[1].as_ref().unwrap();
+        assert_eq!(second.url, "https://example.com/2.m4a");
+        assert_eq!(second.guid, "tag:example.com,2024:2");
+        assert_eq!(second.published, Some(Unix::from_secs(1709892000)));
+    }
+
+    #[test]
+    fn itunes_text_survives_the_prefix_placeholder() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Placeholder Cast</title>
+    <description>Find us in itunes: search, not __placeholder__ or itunes__placeholder__.</description>
+    <author>Plain author</author>
+    <itunes:author>Itunes author</itunes:author>
+    <item>
+      <title>First</title>
+      <guid>1</guid>
+      <description><![CDATA[<p>Rate us at <itunes:review> __placeholder__</p>]]></description>
+      <itunes:duration>01:02:03</itunes:duration>
+      <enclosure url="https://example.com/1.mp3" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#;
+
+        let (podcast, attrs) = attributes(xml);
+        assert_eq!(
+            podcast.get_str("description"),
+            Some("Find us in itunes: search, not __placeholder__ or itunes__placeholder__.")
+        );
+        assert_eq!(podcast.get_str("author"), Some("Plain author"));
+        assert_eq!(podcast.get_str("itunes:author"), Some("Itunes author"));
+
+        let first = attrs[0].as_ref().unwrap();
+        assert_eq!(
+            first.get_str("description").ok(),
+            Some("<p>Rate us at <itunes:review> __placeholder__</p>")
+        );
+        assert_eq!(first.get_str("itunes:duration").ok(), Some("01:02:03"));
+    }
+
+    #[tokio::test]
+    async fn items_without_enclosure_are_skipped() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Announcements Cast</title>
+    <item>
+      <title>Third</title>
+      <guid>3</guid>
+      <pubDate>Mon, 15 Jan 2024 10:00:00 GMT</pubDate>
+      <enclosure url="https://example.com/3.mp3" type="audio/mpeg"/>
+    </item>
+    <item>
+      <title>We're on a break</title>
+      <guid>announcement</guid>
+      <pubDate>Mon, 08 Jan 2024 10:00:00 GMT</pubDate>
+      <description>No episode this week.</description>
+    </item>
+    <item>
+      <title>First</title>
+      <guid>1</guid>
+      <pubDate>Mon, 01 Jan 2024 10:00:00 GMT</pubDate>
+      <enclosure url="https://example.com/1.mp3" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#;
+
+        let (_, attrs) = attributes(xml);
+        assert!(attrs[0].is_ok());
+        assert!(attrs[1].is_err());
+        assert!(attrs[2].is_ok());
+
+        let podcast = podcast(xml, "").await;
+        assert_eq!(titles(&podcast), ["First", "Third"]);
+    }
+
+    const DUAL_ENCLOSURES: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:podcast="https://podcastindex.org/namespace/1.0">
+  <channel>
+    <title>Dual Cast</title>
+    <item>
+      <title>First</title>
+      <guid>1</guid>
+      <enclosure url="https://example.com/1.mp3" type="audio/mpeg" length="1000"/>
+      <enclosure url="https://example.com/1.opus" type="audio/opus" length="500"/>
+      <enclosure url="https://example.com/1.jpg" type="image/jpeg"/>
+      <podcast:alternateEnclosure type="audio/mpeg" bitrate="64000" length="480">
+        <podcast:source uri="https://example.com/1-64.mp3"/>
+      </podcast:alternateEnclosure>
+      <podcast:alternateEnclosure type="audio/mpeg" bitrate="128000" length="960">
+        <podcast:source uri="ipfs://1-128"/>
+        <podcast:source uri="https://example.com/1-128.mp3"/>
+      </podcast:alternateEnclosure>
+    </item>
+  </channel>
+</rss>"#;
+
+    fn urls(podcast: &Podcast) -> Vec<&str> {
+        podcast
+            .episodes
+            .iter()
+            .map(|episode| episode.attrs.url.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn first_audio_enclosure_is_the_default() {
+        let podcast = podcast(DUAL_ENCLOSURES, "").await;
+        assert_eq!(urls(&podcast), ["https://example.com/1.mp3"]);
+        assert_eq!(podcast.episodes[0].attrs.length, Some(1000));
+    }
+
+    #[tokio::test]
+    async fn preferred_format_picks_another_enclosure() {
+        let podcast = podcast(DUAL_ENCLOSURES, "preferred_format = \"opus\"").await;
+        assert_eq!(urls(&podcast), ["https://example.com/1.opus"]);
+        assert_eq!(
+            podcast.episodes[0].attrs.mime.as_deref(),
+            Some("audio/opus")
+        );
+        assert_eq!(podcast.episodes[0].attrs.guid, "1");
+    }
+
+    #[tokio::test]
+    async fn preferred_bitrate_picks_an_alternate_enclosure() {
+        // The highest bitrate that doesn't exceed the preferred one.
+        let below = podcast(DUAL_ENCLOSURES, "preferred_bitrate = 100").await;
+        assert_eq!(urls(&below), ["https://example.com/1-64.mp3"]);
+        assert_eq!(below.episodes[0].attrs.length, Some(480));
+
+        let above = podcast(DUAL_ENCLOSURES, "preferred_bitrate = 320").await;
+        assert_eq!(urls(&above), ["https://example.com/1-128.mp3"]);
+
+        // Or the lowest one if they all exceed it.
+        let lowest = podcast(DUAL_ENCLOSURES, "preferred_bitrate = 32").await;
+        assert_eq!(urls(&lowest), ["https://example.com/1-64.mp3"]);
+
+        let settings = "preferred_format = \"mp3\"\npreferred_bitrate = 100";
+        let both = podcast(DUAL_ENCLOSURES, settings).await;
+        assert_eq!(urls(&both), ["https://example.com/1-64.mp3"]);
+    }
+
+    #[tokio::test]
+    async fn all_enclosures_downloads_every_one() {
+        // The preferences don't apply when every enclosure is downloaded.
+        let settings = "all_enclosures = true\npreferred_format = \"opus\"";
+        let podcast = podcast(DUAL_ENCLOSURES, settings).await;
+        assert_eq!(
+            urls(&podcast),
+            ["https://example.com/1.mp3", "https://example.com/1.opus"]
+        );
+
+        let guids: Vec<&str> = podcast
+            .episodes
+            .iter()
+            .map(|episode| episode.attrs.guid.as_str())
+            .collect();
+        assert_eq!(guids, ["1", "1#2"]);
+        let indices: Vec<usize> = podcast
+            .episodes
+            .iter()
+            .map(|episode| episode.index)
+            .collect();
+        assert_eq!(indices, [0, 1]);
     }
 }
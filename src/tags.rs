@@ -1,99 +1,334 @@
+use crate::config::EvalData;
 use crate::display::DownloadBar;
-use crate::episode;
-use crate::podcast::RawPodcast;
+use crate::patterns::FullPattern;
+use crate::vorbis::VorbisComments;
 use chrono::Datelike;
 use id3::TagLike;
+use std::io::Read;
+use std::path::Path;
+
+/// The container format of a downloaded file, which decides how it's tagged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+    /// Tagged with ID3v2.
+    Mp3,
+    /// Tagged with MP4 atoms, e.g. m4a and m4b files.
+    Mp4,
+    /// Tagged with Vorbis comments, e.g. opus and ogg files.
+    Ogg,
+}
+
+impl Container {
+    /// Detects the container from the magic bytes of the file, falling back to its extension.
+    ///
+    /// The extension alone isn't reliable, since it's taken from the url which may not match
+    /// what the server actually sends.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let mut header = [0u8; 12];
+        let read = std::fs::File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .is_ok();
+
+        if read {
+            if &header[4..8] == b"ftyp" {
+                return Some(Self::Mp4);
+            }
+
+            if &header[..4] == b"OggS" {
+                return Some(Self::Ogg);
+            }
+
+            // An ID3 tag, or the frame sync of an MPEG audio frame with its layer set,
+            // which tells it apart from ADTS encoded AAC.
+            if &header[..3] == b"ID3"
+                || (header[0] == 0xFF && header[1] & 0xE0 == 0xE0 && header[1] & 0x06 != 0)
+            {
+                return Some(Self::Mp3);
+            }
+        }
+
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "mp3" => Some(Self::Mp3),
+            "m4a" | "m4b" | "mp4" => Some(Self::Mp4),
+            "ogg" | "oga" | "opus" => Some(Self::Ogg),
+            _ => None,
+        }
+    }
+}
+
+/// Tags of a file that the tags extracted from the feed can be merged into.
+pub trait TagWriter {
+    /// Adds the tags from the feed, keeping the ones the file already has.
+    fn merge_feed_tags(&mut self, feed: &id3::Tag);
+}
+
+impl TagWriter for id3::Tag {
+    fn merge_feed_tags(&mut self, feed: &id3::Tag) {
+        for frame in feed.frames() {
+            // There can be multiple user defined text frames, told apart by description.
+            let exists = match frame.content().extended_text() {
+                Some(ext) => self
+                    .extended_texts()
+                    .any(|existing| existing.description == ext.description),
+                None => self.get(frame.id()).is_some(),
+            };
+
+            if !exists {
+                self.add_frame(frame.to_owned());
+            }
+        }
+    }
+}
+
+impl TagWriter for mp4ameta::Tag {
+    fn merge_feed_tags(&mut self, feed: &id3::Tag) {
+        if let Some(title) = feed.title().filter(|_| self.title().is_none()) {
+            self.set_title(title);
+        }
+
+        // MP4 has no publisher atom, so the podcast's author stands in for missing artists.
+        let artist = feed.artist().or_else(|| {
+            feed.get(Id3Tag::PUBLISHER)
+                .and_then(|frame| frame.content().text())
+        });
+        if let Some(artist) = artist.filter(|_| self.artist().is_none()) {
+            self.set_artist(artist);
+        }
+
+        if let Some(album) = feed.album().filter(|_| self.album().is_none()) {
+            self.set_album(album);
+        }
+
+        let album_artist = feed.album_artist();
+        if let Some(album_artist) = album_artist.filter(|_| self.album_artist().is_none()) {
+            self.set_album_artist(album_artist);
+        }
+
+        if let Some(genre) = feed.genre().filter(|_| self.genre().is_none()) {
+            self.set_genre(genre);
+        }
+
+        // The `©day` atom holds either just the year or a full date.
+        if self.year().is_none() {
+            if let Some(date) = feed.date_released() {
+                self.set_year(date.to_string());
+            } else if let Some(year) = feed.year() {
+                self.set_year(year.to_string());
+            }
+        }
+
+        if let Some(track) = feed.track().filter(|_| self.track_number().is_none()) {
+            if let Ok(track) = u16::try_from(track) {
+                self.set_track_number(track);
+            }
+        }
+
+        let description = feed
+            .get(Id3Tag::DESCRIPTION)
+            .and_then(|frame| frame.content().text());
+        if let Some(description) = description.filter(|_| self.description().is_none()) {
+            // The `desc` atom is limited to 255 characters, so the full description goes into
+            // the comment atom.
+            self.set_description(truncate_text(description, MP4_DESCRIPTION_LIMIT));
+            if self.comment().is_none() {
+                self.set_comment(truncate_text(description, TEXT_LIMIT));
+            }
+        }
+
+        let copyright = feed
+            .get(Id3Tag::COPYRIGHT)
+            .and_then(|frame| frame.content().text());
+        if let Some(copyright) = copyright.filter(|_| self.copyright().is_none()) {
+            self.set_copyright(copyright);
+        }
+
+        let advisory = feed
+            .extended_texts()
+            .find(|ext| ext.description == Id3Tag::ADVISORY)
+            .map(|ext| match ext.value.as_str() {
+                "1" => mp4ameta::AdvisoryRating::Explicit,
+                _ => mp4ameta::AdvisoryRating::Clean,
+            });
+        if let Some(advisory) = advisory.filter(|_| self.advisory_rating().is_none()) {
+            self.set_advisory_rating(advisory);
+        }
+    }
+}
+
+impl TagWriter for VorbisComments {
+    fn merge_feed_tags(&mut self, feed: &id3::Tag) {
+        let artist = feed.artist().or_else(|| {
+            feed.get(Id3Tag::PUBLISHER)
+                .and_then(|frame| frame.content().text())
+        });
+        let date = feed
+            .date_released()
+            .map(|date| date.to_string())
+            .or_else(|| feed.year().map(|year| year.to_string()));
+        let track = feed.track().map(|track| track.to_string());
+        let text = |id: &str| feed.get(id).and_then(|frame| frame.content().text());
+
+        for (key, value) in [
+            ("TITLE", feed.title()),
+            ("ARTIST", artist),
+            ("ALBUM", feed.album()),
+            ("ALBUMARTIST", feed.album_artist()),
+            ("GENRE", feed.genre()),
+            ("DATE", date.as_deref()),
+            ("TRACKNUMBER", track.as_deref()),
+            ("DESCRIPTION", text(Id3Tag::DESCRIPTION)),
+            ("COPYRIGHT", text(Id3Tag::COPYRIGHT)),
+            ("LANGUAGE", text(Id3Tag::LANGUAGE)),
+        ] {
+            if let Some(value) = value.filter(|_| self.get(key).is_none()) {
+                self.set(key, value);
+            }
+        }
+    }
+}
 
 pub async fn extract_tags_from_raw(
-    podcast: &RawPodcast,
-    episode: &episode::Attributes,
+    data: EvalData<'_>,
+    templates: &(Option<String>, Option<String>),
     ui: &DownloadBar,
 ) -> Option<id3::Tag> {
+    let (podcast, episode) = (data.podcast, data.episode);
     let mut tags = id3::Tag::new();
 
-    tags.set_title(episode.title());
+    match &templates.0 {
+        Some(template) => tags.set_title(FullPattern::eval_template(template, data)),
+        None => tags.set_title(episode.title()),
+    }
 
     if let Ok(author) = episode.author() {
         ui.log_trace("extracting author tag");
         tags.set_artist(author);
     }
 
-    tags.set_album(podcast.title());
+    match &templates.1 {
+        Some(template) => tags.set_album(FullPattern::eval_template(template, data)),
+        None => tags.set_album(podcast.title()),
+    }
 
-    tags.set_genre("podcast");
+    let categories = podcast.categories();
 
-    if let Ok(episode) = episode.itunes_episode() {
-        if let Ok(episode) = episode.parse::<u32>() {
-            ui.log_trace("extracting itunes track number");
-            tags.set_track(episode);
+    // The primary category makes for the most specific genre.
+    match categories.first() {
+        Some(category) => {
+            ui.log_trace("extracting genre tag");
+            tags.set_genre(category);
         }
+        None => tags.set_genre("podcast"),
+    }
+
+    if let Some(episode) = episode.episode_number() {
+        ui.log_trace("extracting itunes track number");
+        tags.set_track(episode);
     }
 
-    let year = chrono::DateTime::from_timestamp(episode.published().as_secs() as i64, 0)
-        .unwrap()
-        .year();
-    tags.set_year(year);
+    let published = episode
+        .published()
+        .and_then(|published| chrono::DateTime::from_timestamp(published.as_secs() as i64, 0));
+
+    if let Some(published) = published {
+        tags.set_year(published.year());
+    }
 
     if let Some(copyright) = podcast.copyright() {
         ui.log_trace("extracting copyright tag");
         tags.set_text(Id3Tag::COPYRIGHT, copyright);
     }
 
-    if let Ok(desc) = episode.description() {
+    if let Ok(desc) = episode
+        .description()
+        .map(|desc| truncate_text(&desc, TEXT_LIMIT))
+    {
         ui.log_trace("extracting description tag");
+        // Most players show the comment frame rather than the description.
+        tags.add_frame(id3::frame::Comment {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: desc.clone(),
+        });
         tags.set_text(Id3Tag::DESCRIPTION, desc);
     }
 
-    let mut strs = vec![];
-    for cat in podcast.categories() {
-        strs.push(cat);
-    }
-
-    if !strs.is_empty() {
+    if !categories.is_empty() {
         ui.log_trace("extracting podcast categories tag");
-        tags.set_text_values(Id3Tag::PODCASTCATEGORY, strs);
+        tags.set_text_values(Id3Tag::PODCASTCATEGORY, &categories);
     }
 
-    use chrono::TimeZone;
     use chrono::Timelike;
-    let datetime = chrono::Utc
-        .timestamp_opt(episode.published().as_secs() as i64, 0)
-        .unwrap();
-
-    let ts = id3::frame::Timestamp {
-        year: datetime.year(),
-        month: Some(datetime.month() as u8),
-        day: Some(datetime.day() as u8),
-        hour: Some(datetime.hour() as u8),
-        minute: Some(datetime.minute() as u8),
-        second: Some(datetime.second() as u8),
-    };
+    if let Some(datetime) = published {
+        let ts = id3::frame::Timestamp {
+            year: datetime.year(),
+            month: Some(datetime.month() as u8),
+            day: Some(datetime.day() as u8),
+            hour: Some(datetime.hour() as u8),
+            minute: Some(datetime.minute() as u8),
+            second: Some(datetime.second() as u8),
+        };
 
-    tags.set_date_released(ts);
+        tags.set_date_released(ts);
+    }
 
     if let Some(language) = podcast.language() {
         ui.log_trace("extracting language tag");
         tags.set_text(Id3Tag::LANGUAGE, language);
     }
 
-    if let Ok(dur) = episode.itunes_duration() {
-        if let Ok(secs) = dur.parse::<u32>() {
-            ui.log_trace("extracting itunes duration tag");
-            let millis = secs * 1000;
-            tags.set_text(Id3Tag::DURATION, millis.to_string());
-        }
+    if let Some(dur) = episode.duration() {
+        ui.log_trace("extracting itunes duration tag");
+        tags.set_text(Id3Tag::DURATION, dur.as_millis().to_string());
     }
 
     if let Some(author) = podcast.author() {
-        ui.log_trace("extracting publisher tag");
+        ui.log_trace("extracting publisher and album artist tags");
         tags.set_text(Id3Tag::PUBLISHER, author);
+        // Keeps the episodes of a show together in players that group albums by album artist,
+        // even when the episodes credit different artists.
+        tags.set_album_artist(author);
     }
 
     tags.set_text(Id3Tag::PODCAST_ID, episode.guid());
 
+    if let Some(explicit) = episode.explicit().or_else(|| podcast.explicit()) {
+        ui.log_trace("extracting explicit tag");
+        // The advisory rating iTunes reads from mp3 files, 1 is explicit and 2 clean, like the
+        // `AdvisoryRating` of mp4 files.
+        tags.add_frame(id3::frame::ExtendedText {
+            description: Id3Tag::ADVISORY.to_string(),
+            value: if explicit { "1" } else { "2" }.to_string(),
+        });
+    }
+
     Some(tags)
 }
 
+/// Text longer than this is cut short, which is far beyond any show notes but keeps a broken
+/// feed from bloating every file.
+const TEXT_LIMIT: usize = 64 * 1024;
+
+/// How many characters iTunes reads from the `desc` atom.
+const MP4_DESCRIPTION_LIMIT: usize = 255;
+
+/// Shortens the text to at most `max_chars` characters, cutting at a word boundary where
+/// possible and marking the cut with an ellipsis.
+fn truncate_text(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(idx) if idx > cut.len() / 2 => &cut[..idx],
+        _ => cut.as_str(),
+    };
+
+    format!("{}…", cut.trim_end())
+}
+
 struct Id3Tag;
 
 impl Id3Tag {
@@ -104,4 +339,158 @@ impl Id3Tag {
     const DURATION: &'static str = "TLEN";
     const PUBLISHER: &'static str = "TPUB";
     const PODCAST_ID: &'static str = "TGID";
+    const ADVISORY: &'static str = "ITUNESADVISORY";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("talecast-test-{}-{}", std::process::id(), name))
+    }
+
+    /// The tags that would be extracted from a feed.
+    fn feed_tags() -> id3::Tag {
+        let mut tags = id3::Tag::new();
+        tags.set_title("Feed title");
+        tags.set_artist("Feed artist");
+        tags.set_album("Feed album");
+        tags.set_genre("podcast");
+        tags.set_track(7);
+        tags.set_text(Id3Tag::DESCRIPTION, "Feed description");
+        tags.set_text(Id3Tag::COPYRIGHT, "Feed copyright");
+        tags.add_frame(id3::frame::ExtendedText {
+            description: Id3Tag::ADVISORY.to_string(),
+            value: "1".to_string(),
+        });
+        tags
+    }
+
+    fn atom(fourcc: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut atom = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend(fourcc);
+        atom.extend(content);
+        atom
+    }
+
+    /// An m4a file with just the atoms needed to be tagged, and no tracks.
+    fn empty_m4a() -> Vec<u8> {
+        let mut ftyp = b"M4A ".to_vec();
+        ftyp.extend(0u32.to_be_bytes());
+        ftyp.extend(b"M4A isomiso2");
+
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[20..24].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        mvhd[24..26].copy_from_slice(&0x0100u16.to_be_bytes());
+        mvhd[96..100].copy_from_slice(&1u32.to_be_bytes());
+
+        let mut file = atom(b"ftyp", &ftyp);
+        file.extend(atom(b"moov", &atom(b"mvhd", &mvhd)));
+        file.extend(atom(b"mdat", &[0; 16]));
+        file
+    }
+
+    #[test]
+    fn mp3_tags_round_trip() {
+        let path = temp_path("tags.mp3");
+        std::fs::write(&path, [0xFF, 0xFB, 0x90, 0x00]).unwrap();
+
+        let mut tags = id3::Tag::read_from_path(&path).unwrap_or_default();
+        tags.merge_feed_tags(&feed_tags());
+        tags.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        let tags = id3::Tag::read_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tags.title(), Some("Feed title"));
+        assert_eq!(tags.artist(), Some("Feed artist"));
+        assert_eq!(tags.album(), Some("Feed album"));
+        assert_eq!(tags.track(), Some(7));
+        assert_eq!(
+            tags.get(Id3Tag::DESCRIPTION)
+                .and_then(|frame| frame.content().text()),
+            Some("Feed description")
+        );
+        let advisory = tags
+            .extended_texts()
+            .find(|ext| ext.description == Id3Tag::ADVISORY);
+        assert_eq!(advisory.map(|ext| ext.value.as_str()), Some("1"));
+    }
+
+    #[test]
+    fn mp3_keeps_existing_tags() {
+        let path = temp_path("existing.mp3");
+        std::fs::write(&path, [0xFF, 0xFB, 0x90, 0x00]).unwrap();
+
+        let mut existing = id3::Tag::new();
+        existing.set_title("File title");
+        existing.add_frame(id3::frame::ExtendedText {
+            description: Id3Tag::ADVISORY.to_string(),
+            value: "2".to_string(),
+        });
+        existing.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        let mut tags = id3::Tag::read_from_path(&path).unwrap();
+        tags.merge_feed_tags(&feed_tags());
+        tags.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        let tags = id3::Tag::read_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tags.title(), Some("File title"));
+        assert_eq!(tags.artist(), Some("Feed artist"));
+        let advisories: Vec<_> = tags
+            .extended_texts()
+            .filter(|ext| ext.description == Id3Tag::ADVISORY)
+            .map(|ext| ext.value.as_str())
+            .collect();
+        assert_eq!(advisories, ["2"]);
+    }
+
+    #[test]
+    fn m4a_tags_round_trip() {
+        let path = temp_path("tags.m4a");
+        std::fs::write(&path, empty_m4a()).unwrap();
+
+        let mut tag = mp4ameta::Tag::read_from_path(&path).unwrap();
+        tag.merge_feed_tags(&feed_tags());
+        tag.write_to_path(&path).unwrap();
+
+        let tag = mp4ameta::Tag::read_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tag.title(), Some("Feed title"));
+        assert_eq!(tag.artist(), Some("Feed artist"));
+        assert_eq!(tag.album(), Some("Feed album"));
+        assert_eq!(tag.genre(), Some("podcast"));
+        assert_eq!(tag.track_number(), Some(7));
+        assert_eq!(tag.description(), Some("Feed description"));
+        assert_eq!(tag.copyright(), Some("Feed copyright"));
+        assert_eq!(
+            tag.advisory_rating(),
+            Some(mp4ameta::AdvisoryRating::Explicit)
+        );
+    }
+
+    #[test]
+    fn m4a_keeps_existing_tags() {
+        let path = temp_path("existing.m4a");
+        std::fs::write(&path, empty_m4a()).unwrap();
+
+        let mut existing = mp4ameta::Tag::read_from_path(&path).unwrap();
+        existing.set_title("File title");
+        existing.set_album("File album");
+        existing.set_advisory_rating(mp4ameta::AdvisoryRating::Clean);
+        existing.write_to_path(&path).unwrap();
+
+        let mut tag = mp4ameta::Tag::read_from_path(&path).unwrap();
+        tag.merge_feed_tags(&feed_tags());
+        tag.write_to_path(&path).unwrap();
+
+        let tag = mp4ameta::Tag::read_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tag.title(), Some("File title"));
+        assert_eq!(tag.album(), Some("File album"));
+        assert_eq!(tag.artist(), Some("Feed artist"));
+        assert_eq!(tag.advisory_rating(), Some(mp4ameta::AdvisoryRating::Clean));
+    }
 }
@@ -0,0 +1,162 @@
+//! The terminal interface of `--interactive`, for picking a podcast and its episodes.
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeSet;
+use std::io;
+use std::io::IsTerminal;
+
+/// How far page up and page down move.
+const PAGE: u16 = 10;
+
+/// Lets the user pick one of the podcasts. `None` if they quit instead.
+pub fn pick_podcast(names: &[String]) -> io::Result<Option<usize>> {
+    let picked = Picker::new("Podcasts", names, false).run()?;
+    Ok(picked.and_then(|picked| picked.first().copied()))
+}
+
+/// Lets the user mark the episodes to download, returning their positions in `episodes`. If
+/// none are marked, the highlighted one is picked. `None` if they quit instead.
+pub fn pick_episodes(title: &str, episodes: &[String]) -> io::Result<Option<Vec<usize>>> {
+    Picker::new(title, episodes, true).run()
+}
+
+/// A list that's moved through with the arrow keys, where several entries can be marked if
+/// `multi` is set.
+struct Picker<'a> {
+    title: &'a str,
+    items: &'a [String],
+    multi: bool,
+    state: ListState,
+    marked: BTreeSet<usize>,
+}
+
+impl<'a> Picker<'a> {
+    fn new(title: &'a str, items: &'a [String], multi: bool) -> Self {
+        Self {
+            title,
+            items,
+            multi,
+            state: ListState::default().with_selected(Some(0)),
+            marked: BTreeSet::new(),
+        }
+    }
+
+    /// Takes over the terminal until the user is done, then restores it.
+    fn run(mut self) -> io::Result<Option<Vec<usize>>> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "interactive mode needs a terminal",
+            ));
+        }
+
+        let mut terminal = ratatui::try_init()?;
+        let picked = self.event_loop(&mut terminal);
+        ratatui::restore();
+        picked
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<Option<Vec<usize>>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+                // Raw mode keeps Ctrl-C from interrupting, so it quits like `q`.
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(None)
+                }
+                KeyCode::Up | KeyCode::Char('k') => self.state.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.state.select_next(),
+                KeyCode::PageUp => self.state.scroll_up_by(PAGE),
+                KeyCode::PageDown => self.state.scroll_down_by(PAGE),
+                KeyCode::Home | KeyCode::Char('g') => self.state.select_first(),
+                KeyCode::End | KeyCode::Char('G') => self.state.select_last(),
+                KeyCode::Char(' ') if self.multi => {
+                    if let Some(position) = self.highlighted() {
+                        if !self.marked.remove(&position) {
+                            self.marked.insert(position);
+                        }
+                        self.state.select_next();
+                    }
+                }
+                KeyCode::Char('a') if self.multi => {
+                    if self.marked.len() == self.items.len() {
+                        self.marked.clear();
+                    } else {
+                        self.marked = (0..self.items.len()).collect();
+                    }
+                }
+                KeyCode::Enter => {
+                    if !self.marked.is_empty() {
+                        return Ok(Some(self.marked.iter().copied().collect()));
+                    }
+                    if let Some(position) = self.highlighted() {
+                        return Ok(Some(vec![position]));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The entry under the cursor. The list keeps the selection within bounds once it's drawn,
+    /// which happens before every key press.
+    fn highlighted(&self) -> Option<usize> {
+        let last = self.items.len().checked_sub(1)?;
+        self.state.selected().map(|position| position.min(last))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, help_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+        let items: Vec<ListItem> = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(position, item)| {
+                if !self.multi {
+                    return ListItem::new(item.as_str());
+                }
+                let mark = if self.marked.contains(&position) {
+                    "[x]"
+                } else {
+                    "[ ]"
+                };
+                ListItem::new(format!("{} {}", mark, item))
+            })
+            .collect();
+
+        let mut block = Block::bordered().title(format!(" {} ", self.title));
+        if self.multi {
+            block = block.title_bottom(format!(" {} selected ", self.marked.len()));
+        }
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.state);
+
+        let help = if self.multi {
+            "↑/↓ move  space select  a select all  enter download  q quit"
+        } else {
+            "↑/↓ move  enter open  q quit"
+        };
+        let help = Line::from(help).style(Style::default().add_modifier(Modifier::DIM));
+        frame.render_widget(Paragraph::new(help), help_area);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::cache;
 use crate::config;
 use crate::episode::Episode;
 use crate::utils;
@@ -26,7 +27,47 @@ pub fn log<S: AsRef<str>>(message: S) {
     writeln!(file, "{}", message.as_ref()).unwrap();
 }
 
+static CONFIG_DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+static DATA_DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+
+/// Reads the config files from this directory instead of the default one, for the rest of the
+/// process. Only the first call has an effect.
+pub fn set_config_dir(path: PathBuf) {
+    let _ = CONFIG_DIR.set(path);
+}
+
+/// Keeps the caches, the sync lock and the episode index in this directory instead of the
+/// default ones, for the rest of the process. Only the first call has an effect.
+pub fn set_data_dir(path: PathBuf) {
+    let _ = DATA_DIR.set(path);
+}
+
+/// The directory set with [`set_data_dir`], if any.
+pub fn data_dir() -> Option<PathBuf> {
+    let path = DATA_DIR.get()?.clone();
+    utils::create_dir(&path);
+    Some(path)
+}
+
+static INTERRUPTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Asks the running syncs to stop once the downloads in progress are finished, e.g. after the
+/// user pressed Ctrl-C.
+pub fn interrupt() {
+    INTERRUPTED.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Whether [`interrupt`] was called, in which case no new downloads are started.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(std::sync::atomic::Ordering::SeqCst)
+}
+
 pub fn config_dir() -> PathBuf {
+    if let Some(path) = CONFIG_DIR.get() {
+        utils::create_dir(path);
+        return path.clone();
+    }
+
     let path = match std::env::var("XDG_CONFIG_HOME") {
         Ok(path) => PathBuf::from(path),
         Err(_) => dirs::home_dir()
@@ -41,6 +82,10 @@ pub fn config_dir() -> PathBuf {
 }
 
 pub fn cache_dir() -> PathBuf {
+    if let Some(path) = data_dir() {
+        return path;
+    }
+
     let path = match std::env::var("XDG_CACHE_HOME") {
         Ok(path) => PathBuf::from(path),
         Err(_) => dirs::cache_dir()
@@ -94,24 +139,67 @@ pub fn truncate_string(s: &str, max_width: usize, append_dots: bool) -> String {
     truncated
 }
 
+/// Puts a file name in NFC form, so the same title always ends up as the same bytes, and
+/// optionally transliterates it to ASCII for filesystems that are strict about file names.
+///
+/// Accents are dropped in the transliteration, and the characters without an ASCII counterpart
+/// are replaced with underscores.
+pub fn normalize_file_name(name: &str, ascii: bool) -> String {
+    use unicode_normalization::char::is_combining_mark;
+    use unicode_normalization::UnicodeNormalization;
+
+    if !ascii {
+        return name.nfc().collect();
+    }
+
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            c if c.is_ascii() => normalized.push(c),
+            'ß' => normalized.push_str("ss"),
+            'æ' => normalized.push_str("ae"),
+            'Æ' => normalized.push_str("AE"),
+            'œ' => normalized.push_str("oe"),
+            'Œ' => normalized.push_str("OE"),
+            'ø' => normalized.push('o'),
+            'Ø' => normalized.push('O'),
+            'đ' | 'ð' => normalized.push('d'),
+            'Đ' | 'Ð' => normalized.push('D'),
+            'ł' => normalized.push('l'),
+            'Ł' => normalized.push('L'),
+            'þ' => normalized.push_str("th"),
+            'Þ' => normalized.push_str("TH"),
+            '‘' | '’' => normalized.push('\''),
+            '“' | '”' => normalized.push('"'),
+            '–' | '—' => normalized.push('-'),
+            _ => normalized.push('_'),
+        }
+    }
+
+    // The replacements can bring back characters that aren't allowed in file names.
+    sanitize_filename::sanitize(normalized)
+}
+
+/// Cuts the string down to at most `max_len` bytes without splitting a grapheme, so accented
+/// letters and emoji aren't left half.
+pub fn truncate_graphemes(s: &str, max_len: usize) -> &str {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    let mut end = 0;
+    for (idx, grapheme) in s.grapheme_indices(true) {
+        if idx + grapheme.len() > max_len {
+            break;
+        }
+        end = idx + grapheme.len();
+    }
+
+    &s[..end]
+}
+
 pub fn short_handle_response(
     response: Result<reqwest::Response, reqwest::Error>,
 ) -> Result<reqwest::Response, String> {
-    match response {
-        Ok(res) => Ok(res),
-        Err(e) => {
-            let error_message = match e {
-                e if e.is_builder() => format!("Invalid URL"),
-                e if e.is_connect() => format!("failed to connect to url",),
-                e if e.is_timeout() => format!("request timed out"),
-                e if e.is_status() => format!("server error"),
-                e if e.is_redirect() => format!("too many redirects while connecting"),
-                e if e.is_decode() => format!("failed to decode response"),
-                _ => format!("unexpected connection error"),
-            };
-            Err(error_message)
-        }
-    }
+    response.map_err(|e| CringeError::from(e).to_string())
 }
 
 pub fn _handle_response(response: Result<reqwest::Response, reqwest::Error>) -> reqwest::Response {
@@ -138,22 +226,203 @@ pub fn _handle_response(response: Result<reqwest::Response, reqwest::Error>) ->
     }
 }
 
+use crate::config::RetryPolicy;
 use crate::display::DownloadBar;
+use crate::error::CringeError;
 use futures_util::StreamExt;
 
+/// Whether a failed request is worth trying again.
+///
+/// Connection errors, rate-limiting and server errors are likely temporary,
+/// other client errors are not.
+fn is_retryable(response: &Result<reqwest::Response, reqwest::Error>) -> bool {
+    match response {
+        Ok(res) => {
+            let status = res.status();
+            status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+        }
+        Err(e) => !e.is_builder() && !e.is_redirect(),
+    }
+}
+
+/// How long the `Retry-After` header of the response asks to wait, given either in seconds or
+/// as an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<std::time::Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(secs));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let secs = date.timestamp() - chrono::Utc::now().timestamp();
+    Some(std::time::Duration::from_secs(secs.max(0) as u64))
+}
+
+/// Sends the request, retrying with exponential backoff on temporary failures.
+///
+/// Rate-limited responses are retried after the delay from their `Retry-After` header instead,
+/// up to `max_retry_after`.
+pub async fn send_with_retries(
+    request: reqwest::RequestBuilder,
+    retry: &RetryPolicy,
+    ui: &DownloadBar,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let mut attempt = 0;
+
+    loop {
+        let Some(req) = request.try_clone() else {
+            return request.send().await;
+        };
+
+        let response = req.send().await;
+
+        if attempt >= retry.max_retries || !is_retryable(&response) {
+            if attempt > 0 {
+                ui.retry_done();
+            }
+            return response;
+        }
+
+        attempt += 1;
+
+        // Rate-limited responses say how long to wait, which beats guessing with a backoff.
+        let retry_after = response
+            .as_ref()
+            .ok()
+            .filter(|res| res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS)
+            .and_then(retry_after)
+            .map(|delay| delay.min(retry.max_retry_after));
+
+        if let Some(delay) = retry_after {
+            ui.rate_limited(delay);
+            tokio::time::sleep(delay).await;
+            continue;
+        }
+
+        match &response {
+            Ok(res) => ui.log_warn(format!("request failed with status: {}", res.status())),
+            Err(e) => ui.log_warn(format!("request failed: {:?}", e)),
+        }
+        ui.retrying(attempt, retry.max_retries);
+        tokio::time::sleep(retry.delay(attempt)).await;
+    }
+}
+
+/// Where the requested urls were permanently redirected to during this run, and whether a
+/// temporary redirect came after, in which case the location stops being updated.
+static PERMANENT_REDIRECTS: std::sync::Mutex<std::collections::BTreeMap<String, (String, bool)>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+/// Follows up to `max` redirects like `Policy::limited`, remembering where the permanent ones
+/// lead so a moved feed can be noticed.
+pub fn redirect_policy(max: usize) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= max {
+            return attempt.error("too many redirects");
+        }
+
+        let permanent = matches!(
+            attempt.status(),
+            reqwest::StatusCode::MOVED_PERMANENTLY | reqwest::StatusCode::PERMANENT_REDIRECT
+        );
+        if let Some(requested) = attempt.previous().first() {
+            let mut redirects = PERMANENT_REDIRECTS.lock().unwrap();
+            let location = attempt.url().to_string();
+            if attempt.previous().len() == 1 {
+                match permanent {
+                    true => redirects.insert(requested.to_string(), (location, false)),
+                    false => redirects.remove(requested.as_str()),
+                };
+            } else if let Some((to, frozen)) = redirects.get_mut(requested.as_str()) {
+                match permanent && !*frozen {
+                    true => *to = location,
+                    false => *frozen = true,
+                }
+            }
+        }
+
+        attempt.follow()
+    })
+}
+
+/// Where the url has permanently moved to, if a request to it was permanently redirected.
+pub fn permanent_redirect(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    PERMANENT_REDIRECTS
+        .lock()
+        .unwrap()
+        .get(url.as_str())
+        .map(|(to, _)| to.clone())
+}
+
+/// Downloads the feed at the url.
+///
+/// If the feed was cached with an `ETag` or `Last-Modified` header, the request is made
+/// conditional and the cached feed is used if the server responds that it hasn't changed.
+/// A feed that was fetched less than `ttl` ago is used without making a request at all.
 pub async fn download_text(
-    client: &reqwest::Client,
     url: &str,
+    mut request: reqwest::RequestBuilder,
+    retry: &RetryPolicy,
+    ttl: Option<time::Duration>,
     ui: &DownloadBar,
-) -> Option<String> {
+) -> Result<String, CringeError> {
+    use reqwest::header;
+
+    if let Some(cached) = ttl.and_then(|ttl| cache::FeedCache::fresh(url, ttl)) {
+        ui.log_info("podcast xml was fetched recently, using cached version");
+        return Ok(cached.xml);
+    }
+
+    let cached = cache::FeedCache::get(url);
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
     ui.log_info("downloading podcast xml");
-    let response = match client.get(url).send().await {
-        Ok(res) => res,
-        Err(e) => {
-            ui.log_error(&format!("connection failure: {:?}", e));
-            return None;
+    let response = send_with_retries(request, retry, ui).await;
+    if let Err(e) = &response {
+        ui.log_error(format!("connection failure: {:?}", e));
+    }
+    let response = response?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        if let Some(cached) = cached {
+            ui.log_info("podcast xml is up to date, using cached version");
+            // Resaved so the time to live starts over.
+            if cache::FeedCache::save(url, &cached).is_none() {
+                ui.log_warn("failed to cache podcast xml");
+            }
+            return Ok(cached.xml);
         }
+    }
+
+    if !response.status().is_success() {
+        ui.log_error(format!("server responded with: {}", response.status()));
+        return Err(CringeError::Status(response.status()));
+    }
+
+    let header_val = |key: header::HeaderName| {
+        response
+            .headers()
+            .get(key)
+            .and_then(|val| val.to_str().ok())
+            .map(ToString::to_string)
     };
+    let etag = header_val(header::ETAG);
+    let last_modified = header_val(header::LAST_MODIFIED);
+    let content_type = header_val(header::CONTENT_TYPE);
 
     let total_size = response.content_length().unwrap_or(0);
 
@@ -163,19 +432,84 @@ pub async fn download_text(
     ui.init_download_bar(downloaded, total_size);
     let mut buffer: Vec<u8> = vec![];
     while let Some(item) = stream.next().await {
-        let chunk = item.ok()?;
+        let chunk = item?;
         buffer.extend(&chunk);
         downloaded = std::cmp::min(downloaded + (chunk.len() as u64), total_size);
         ui.set_progress(downloaded);
     }
 
-    match String::from_utf8(buffer) {
-        Ok(s) => Some(s),
+    let xml = match decode_xml(buffer, content_type.as_deref()) {
+        Ok(s) => s,
         Err(e) => {
-            ui.log_error(&format!("failed to decode xml: {:?}", e));
-            None
+            ui.log_error(format!("failed to decode xml: {}", e));
+            return Err(CringeError::Parse("decode xml".to_string()));
         }
+    };
+
+    let feed = cache::CachedFeed {
+        xml,
+        etag,
+        last_modified,
+        fetched_at: None,
+    };
+
+    if cache::FeedCache::save(url, &feed).is_none() {
+        ui.log_warn("failed to cache podcast xml");
+    }
+
+    Ok(feed.xml)
+}
+
+/// Decodes a feed to UTF-8.
+///
+/// The encoding is taken from the byte order mark, the charset of the `Content-Type` header or
+/// the XML declaration, in that order, and defaults to UTF-8.
+fn decode_xml(bytes: Vec<u8>, content_type: Option<&str>) -> Result<String, String> {
+    let from_label = |label: &str| encoding_rs::Encoding::for_label(label.trim().as_bytes());
+
+    let header_charset = content_type.and_then(|content_type| {
+        content_type.split(';').skip(1).find_map(|param| {
+            let (key, val) = param.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| trim_quotes(val))
+        })
+    });
+
+    let declared_charset = || {
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_string();
+        let decl = head.strip_prefix('\u{feff}').unwrap_or(&head);
+        let decl = &decl[..decl.find("?>")?];
+        let decl = decl.trim_start().strip_prefix("<?xml")?;
+        let (_, after) = decl.split_once("encoding")?;
+        let after = after.trim_start().strip_prefix('=')?.trim_start();
+        let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let val = &after[1..];
+        Some(val[..val.find(quote)?].to_string())
+    };
+
+    let encoding = encoding_rs::Encoding::for_bom(&bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| header_charset.as_deref().and_then(from_label))
+        .or_else(|| declared_charset().as_deref().and_then(from_label))
+        .unwrap_or(encoding_rs::UTF_8);
+
+    if encoding == encoding_rs::UTF_8 {
+        let bytes = bytes
+            .strip_prefix(b"\xef\xbb\xbf")
+            .unwrap_or(&bytes)
+            .to_vec();
+        return String::from_utf8(bytes).map_err(|e| e.to_string());
+    }
+
+    let (xml, _, had_errors) = encoding.decode(&bytes);
+    if had_errors {
+        return Err(format!("invalid {} in feed", encoding.name()));
     }
+
+    // The declaration would otherwise still claim the original encoding.
+    let declaration = Regex::new(r#"^(\s*<\?xml[^>]*?encoding\s*=\s*)["'][^"']*["']"#).unwrap();
+    Ok(declaration.replace(&xml, "${1}\"UTF-8\"").to_string())
 }
 
 pub fn edit_file(path: &Path) {
@@ -254,8 +588,119 @@ pub fn get_input(prompt: Option<&str>) -> Option<String> {
     }
 }
 
+/// The feed url of a podcast, given either the feed itself or a webpage that links to it.
+///
+/// Webpages are scanned for `<link rel="alternate">` tags with an RSS or Atom type, and the user
+/// picks one when there are several.
+pub async fn discover_feed(config: &config::GlobalConfig, url: &str) -> Result<String, String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Ok(url.to_string());
+    }
+
+    let client = config::init_reqwest_client(config);
+    let retry = RetryPolicy::new(config);
+    let hidden =
+        indicatif::MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let ui = DownloadBar::new(url.to_string(), config.style(), &hidden, url.len());
+
+    // The url is kept as it is if it can't be fetched now, e.g. when adding podcasts offline.
+    let text = match download_text(url, client.get(url), &retry, None, &ui).await {
+        Ok(text) => text,
+        Err(e) => {
+            log::warn!("failed to fetch {} to discover its feed: {}", url, e);
+            return Ok(url.to_string());
+        }
+    };
+
+    if !is_html(&text) {
+        return Ok(url.to_string());
+    }
+
+    let feeds = feed_links(&text, url);
+    match feeds.len() {
+        0 => Err(format!("no feed found at {}", url)),
+        1 => Ok(feeds[0].0.clone()),
+        _ => {
+            eprintln!("Enter index of feed to use");
+            for (idx, (url, title)) in feeds.iter().enumerate() {
+                match title {
+                    Some(title) => println!("{}: {} ({})", idx + 1, title, url),
+                    None => println!("{}: {}", idx + 1, url),
+                }
+            }
+
+            let input = get_input(None).ok_or("no feed picked")?;
+            let index = match parse_indices(&input, feeds.len())?.as_slice() {
+                [index] => *index,
+                _ => return Err("pick a single feed".to_string()),
+            };
+            Ok(feeds[index].0.clone())
+        }
+    }
+}
+
+/// Whether a downloaded page is a webpage rather than a feed.
+fn is_html(text: &str) -> bool {
+    let head: String = text.chars().take(1024).collect::<String>().to_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    head.starts_with("<!doctype html") || (head.contains("<html") && !head.contains("<rss"))
+}
+
+/// The urls and titles of the feeds a webpage links to, resolved against the url of the page.
+fn feed_links(html: &str, page_url: &str) -> Vec<(String, Option<String>)> {
+    let tag = Regex::new(r"(?is)<link\b[^>]*>").unwrap();
+    let attr = Regex::new(r#"(?s)([a-zA-Z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap();
+    let base = reqwest::Url::parse(page_url).ok();
+
+    let mut feeds: Vec<(String, Option<String>)> = vec![];
+    for tag in tag.find_iter(html) {
+        let attrs: std::collections::HashMap<String, String> = attr
+            .captures_iter(tag.as_str())
+            .map(|caps| {
+                let val = caps
+                    .get(2)
+                    .or(caps.get(3))
+                    .or(caps.get(4))
+                    .map_or("", |val| val.as_str());
+                (
+                    caps[1].to_lowercase(),
+                    html_escape::decode_html_entities(val).to_string(),
+                )
+            })
+            .collect();
+
+        let is_alternate = attrs.get("rel").is_some_and(|rel| {
+            rel.split_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+        });
+        let is_feed = attrs.get("type").is_some_and(|ty| {
+            let ty = ty.trim().to_lowercase();
+            ty == "application/rss+xml" || ty == "application/atom+xml"
+        });
+        let Some(href) = attrs.get("href").filter(|_| is_alternate && is_feed) else {
+            continue;
+        };
+
+        let url = match &base {
+            Some(base) => match base.join(href.trim()) {
+                Ok(url) => url.to_string(),
+                Err(_) => continue,
+            },
+            None => href.trim().to_string(),
+        };
+
+        if !feeds.iter().any(|(existing, _)| existing == &url) {
+            let title = attrs.get("title").filter(|title| !title.trim().is_empty());
+            feeds.push((url, title.map(|title| title.trim().to_string())));
+        }
+    }
+
+    feeds
+}
+
 pub async fn search_podcasts(config: &config::GlobalConfig, query: String, catch_up: bool) {
-    let response = search(&query).await;
+    let client = config::init_reqwest_client(config);
+    let response = search(&client, &query).await;
     let mut results = vec![];
 
     let mut idx = 0;
@@ -284,23 +729,13 @@ pub async fn search_podcasts(config: &config::GlobalConfig, query: String, catch
         return;
     };
 
-    let mut indices = vec![];
-    for input in input.split(" ") {
-        let Ok(num) = input.parse::<usize>() else {
-            eprintln!(
-                "invalid input: {}. You must enter the index of a podcast",
-                input
-            );
-            return;
-        };
-
-        if num > results.len() || num == 0 {
-            eprintln!("index {} is out of bounds", num);
+    let indices = match parse_indices(&input, results.len()) {
+        Ok(indices) => indices,
+        Err(e) => {
+            eprintln!("{}", e);
             return;
         }
-
-        indices.push(num - 1);
-    }
+    };
 
     let mut regex_parts = vec![];
     for index in indices {
@@ -334,12 +769,124 @@ pub async fn search_podcasts(config: &config::GlobalConfig, query: String, catch
     }
 }
 
+/// Parses the space-separated, one-based indices of a numbered menu with `len` entries into
+/// zero-based ones. Ranges like `3-5` select every entry in between, and repeated entries are
+/// only included once.
+pub fn parse_indices(input: &str, len: usize) -> Result<Vec<usize>, String> {
+    let parse = |num: &str| -> Result<usize, String> {
+        let Ok(num) = num.parse::<usize>() else {
+            return Err(format!(
+                "invalid input: {}. You must enter the index of an entry",
+                num
+            ));
+        };
+
+        if num > len || num == 0 {
+            return Err(format!("index {} is out of bounds", num));
+        }
+
+        Ok(num - 1)
+    };
+
+    let mut indices: Vec<usize> = vec![];
+    for part in input.split_whitespace() {
+        let range = match part.split_once('-') {
+            Some((start, end)) => parse(start)?..=parse(end)?,
+            None => parse(part)?..=parse(part)?,
+        };
+
+        for index in range {
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+    }
+
+    Ok(indices)
+}
+
+/// Replaces the `${VAR}` placeholders in the string with the values of the environment
+/// variables, failing if one of them isn't set.
+pub fn expand_env(s: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("unclosed placeholder in {:?}", s));
+        };
+
+        let name = &rest[start + 2..start + end];
+        match std::env::var(name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => return Err(format!("environment variable {} is not set", name)),
+        }
+        rest = &rest[start + end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
 pub fn trim_quotes(s: &str) -> String {
     let s = s.trim_end_matches("\"");
     let s = s.trim_start_matches("\"");
     s.to_string()
 }
 
+/// Parses the publication date of an episode.
+///
+/// Feeds are supposed to use RFC 2822, but in practice dates come in all kinds of formats, and
+/// often with the wrong weekday. Returns a unix timestamp.
+pub fn parse_pubdate(date: &str) -> Result<time::Duration, String> {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+    let date = date.trim();
+    let to_unix = |secs: i64| {
+        u64::try_from(secs)
+            .map(time::Duration::from_secs)
+            .map_err(|_| format!("date before 1970: {}", date))
+    };
+
+    // A wrong weekday makes the date invalid according to the RFC, so it's parsed without it.
+    let without_weekday = date
+        .split_once(',')
+        .filter(|(weekday, _)| weekday.chars().all(char::is_alphabetic))
+        .map_or(date, |(_, rest)| rest.trim());
+
+    for date in [date, without_weekday] {
+        if let Ok(datetime) = DateTime::parse_from_rfc2822(date) {
+            return to_unix(datetime.timestamp());
+        }
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return to_unix(datetime.timestamp());
+    }
+
+    // Dates without a timezone are assumed to be UTC.
+    for format in [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(date, format) {
+            return to_unix(datetime.and_utc().timestamp());
+        }
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return to_unix(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp());
+    }
+
+    match dateparser::parse(without_weekday) {
+        Ok(datetime) => to_unix(datetime.timestamp()),
+        Err(_) => Err(format!("failed to parse date: {}", date)),
+    }
+}
+
 pub fn date_str_to_unix(date: &str) -> Result<time::Duration, String> {
     let secs = match dateparser::parse(date) {
         Ok(val) => val.timestamp(),
@@ -349,32 +896,232 @@ pub fn date_str_to_unix(date: &str) -> Result<time::Duration, String> {
     Ok(time::Duration::from_secs(secs as u64))
 }
 
+/// Decodes HTML entities like `&amp;` and `&#8217;`, and optionally strips the markup.
+///
+/// Line breaks and paragraphs become newlines when stripping so that descriptions stay readable.
+pub fn clean_html(s: &str, strip_tags: bool) -> String {
+    if !strip_tags {
+        return html_escape::decode_html_entities(s).trim().to_string();
+    }
+
+    let mut text = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            text.push(c);
+            continue;
+        }
+
+        let tag: String = chars.by_ref().take_while(|c| *c != '>').collect();
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if ["br", "p", "div", "li"].contains(&name.as_str()) && !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+
+    html_escape::decode_html_entities(&text).trim().to_string()
+}
+
+/// Parses the value of an `itunes:block` tag, only "yes" blocks.
+pub fn parse_block(s: &str) -> bool {
+    s.trim().eq_ignore_ascii_case("yes")
+}
+
+/// Parses the value of an `itunes:explicit` tag, "clean" is the same as not explicit.
+pub fn parse_explicit(s: &str) -> Option<bool> {
+    match s.trim().to_lowercase().as_str() {
+        "true" | "yes" | "explicit" => Some(true),
+        "false" | "no" | "clean" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses a size like "500KB", "2MB" or "1.5 GB". A plain number is in bytes.
+///
+/// Units are binary, so "1KB" is 1024 bytes. A trailing "/s" is ignored for rates.
+pub fn str_to_bytes(s: &str) -> Result<u64, String> {
+    let trimmed = s.trim();
+    let trimmed = trimmed.strip_suffix("/s").unwrap_or(trimmed).trim_end();
+
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (num, unit) = trimmed.split_at(split);
+
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("invalid size: {}", s)),
+    };
+
+    let Ok(val) = num.parse::<f64>() else {
+        return Err(format!("invalid size: {}", s));
+    };
+
+    // Checked after rounding, so fractions of a byte don't make a size of 0.
+    let bytes = (val * multiplier as f64) as u64;
+    if bytes == 0 {
+        return Err(format!("invalid size: {}", s));
+    }
+
+    Ok(bytes)
+}
+
+/// Token bucket that limits how many bytes per second are downloaded.
+///
+/// A single instance is shared between all downloads so that the limit applies to their combined
+/// rate. Taking more bytes than are available puts the bucket in debt, which the caller waits out.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    state: std::sync::Mutex<(f64, tokio::time::Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            state: std::sync::Mutex::new((bytes_per_sec as f64, tokio::time::Instant::now())),
+        }
+    }
+
+    /// Waits until `bytes` may be consumed without exceeding the rate.
+    pub async fn consume(&self, bytes: usize) {
+        let rate = self.bytes_per_sec as f64;
+        let wait = {
+            let mut guard = self.state.lock().unwrap();
+            let (tokens, last) = &mut *guard;
+            let now = tokio::time::Instant::now();
+            // At most one second worth of bytes can build up while idle.
+            *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * rate).min(rate);
+            *tokens -= bytes as f64;
+            *last = now;
+            (*tokens < 0.)
+                .then(|| time::Duration::try_from_secs_f64(-*tokens / rate).ok())
+                .flatten()
+        };
+
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Formats a duration as `H:MM:SS`, or `MM:SS` if it's shorter than an hour.
+pub fn format_duration(duration: time::Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs % 3600 / 60, secs % 60);
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{:02}:{:02}", minutes, secs)
+    }
+}
+
+/// Parses a duration like "90s", "10m", "2h" or "1h30m". A plain number is in seconds.
+pub fn str_to_duration(s: &str) -> Result<time::Duration, String> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(time::Duration::from_secs(secs));
+    }
+
+    let mut secs = 0;
+    let mut num = String::new();
+
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+
+        let multiplier = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 60 * 60 * 24,
+            'w' => 60 * 60 * 24 * 7,
+            'y' => 60 * 60 * 24 * 365,
+            _ => return Err(format!("invalid duration: {}", s)),
+        };
+
+        let Ok(val) = num.parse::<u64>() else {
+            return Err(format!("invalid duration: {}", s));
+        };
+
+        secs += val * multiplier;
+        num.clear();
+    }
+
+    if !num.is_empty() || s.is_empty() {
+        return Err(format!("invalid duration: {}", s));
+    }
+
+    Ok(time::Duration::from_secs(secs))
+}
+
+/// Parses either a date or an age like `7d`, which is counted back from now.
+pub fn parse_since(s: &str) -> Result<Unix, String> {
+    if let Ok(age) = str_to_duration(s) {
+        return Ok(current_unix().saturating_sub(age));
+    }
+
+    date_str_to_unix(s).map_err(|_| format!("invalid date or age: {}", s))
+}
+
+/// Picks the extension of the downloaded file.
+///
+/// A common mime type of the enclosure is trusted the most. Otherwise the url the response
+/// ended up at is preferred over the enclosure url, since tracking redirectors may point to a
+/// file of another type than the enclosure url suggests. For the same reason, the content type
+/// wins over the enclosure url when the request was redirected.
 pub fn get_extension_from_response(response: &reqwest::Response, episode: &Episode) -> String {
-    let url = &episode.attrs.url();
-    let ext = match PathBuf::from(url)
-        .extension()
-        .and_then(|ext| ext.to_str().map(String::from))
-    {
-        Some(ext) => ext.to_string(),
-        None => {
-            let content_type = response
-                .headers()
-                .get(reqwest::header::CONTENT_TYPE)
-                .and_then(|ct| ct.to_str().ok())
-                .unwrap_or("application/octet-stream");
-
-            let extensions = mime_guess::get_mime_extensions_str(&content_type).unwrap();
-
-            match extensions.contains(&"mp3") {
-                true => "mp3".to_owned(),
-                false => extensions
-                    .first()
-                    .expect("extension not found.")
-                    .to_string(),
-            }
+    let url_ext = |path: &str| {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str().map(String::from))
+    };
+
+    let mime_ext = |content_type: &str| {
+        let extensions = mime_guess::get_mime_extensions_str(content_type)?;
+
+        match extensions.contains(&"mp3") {
+            true => Some("mp3".to_owned()),
+            false => extensions.first().map(ToString::to_string),
         }
     };
 
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok());
+
+    let url = episode.attrs.url();
+    let redirected = response.url().as_str() != url;
+
+    let ext = episode
+        .attrs
+        .enclosure_extension()
+        .map(String::from)
+        .or_else(|| url_ext(response.url().path()))
+        .or_else(|| {
+            content_type
+                .filter(|ct| redirected && !ct.starts_with("application/octet-stream"))
+                .and_then(mime_ext)
+        })
+        .or_else(|| url_ext(url))
+        .or_else(|| mime_ext(content_type.unwrap_or("application/octet-stream")))
+        .expect("extension not found.");
+
     // Some urls have these arguments after the extension.
     // feels a bit hacky.
     // todo: find a cleaner way to extract extensions.
@@ -387,13 +1134,13 @@ pub fn get_extension_from_response(response: &reqwest::Response, episode: &Episo
 
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
-pub async fn search(terms: &str) -> Vec<Value> {
+pub async fn search(client: &reqwest::Client, terms: &str) -> Vec<Value> {
     let encoded: String = utf8_percent_encode(terms, NON_ALPHANUMERIC).to_string();
     let url = format!(
         "https://itunes.apple.com/search?media=podcast&entity=podcast&term={}",
         encoded
     );
-    let resp = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    let resp = client.get(&url).send().await.unwrap().text().await.unwrap();
 
     serde_json::from_str::<serde_json::Value>(&resp)
         .unwrap()
@@ -404,6 +1151,15 @@ pub async fn search(terms: &str) -> Vec<Value> {
         .clone()
 }
 
+/// Attributes like lengths are parsed as numbers, unless they contain something else.
+pub fn val_to_number(val: &serde_json::Value) -> Option<f64> {
+    match val {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
 pub fn val_to_str<'a>(val: &'a serde_json::Value) -> Option<&'a str> {
     if let Some(val) = val.as_str() {
         return Some(val);
@@ -417,26 +1173,34 @@ pub fn val_to_str<'a>(val: &'a serde_json::Value) -> Option<&'a str> {
     obj.get("#text")?.as_str()
 }
 
+/// The url of an element, either its text or one of the attributes urls are usually kept in.
+///
+/// Only http and https urls are returned, as nothing else can be downloaded.
 pub fn val_to_url<'a>(val: &'a serde_json::Value) -> Option<&'a str> {
-    if let Some(val) = val.as_str() {
-        return Some(val);
-    }
-
-    let obj = val.as_object()?;
+    let url = match val.as_object() {
+        Some(obj) => ["@url", "url", "@href", "src", "uri"]
+            .iter()
+            .find_map(|key| obj.get(*key))?
+            .as_str()?,
+        None => val.as_str()?,
+    };
 
-    if let Some(url) = obj.get("url") {
-        return url.as_str();
-    }
+    Some(url.trim()).filter(|url| is_http_url(url))
+}
 
-    if let Some(url) = obj.get("@href") {
-        return url.as_str();
-    }
+pub fn is_http_url(url: &str) -> bool {
+    let url = url.to_lowercase();
+    url.starts_with("http://") || url.starts_with("https://")
+}
 
-    if let Some(url) = obj.get("src") {
-        return url.as_str();
+/// Elements that may occur multiple times are parsed as an array, but as a single object
+/// when they only occur once.
+pub fn val_to_objects(val: &serde_json::Value) -> Vec<&serde_json::Map<String, serde_json::Value>> {
+    match val {
+        serde_json::Value::Array(vals) => vals.iter().filter_map(|val| val.as_object()).collect(),
+        serde_json::Value::Object(obj) => vec![obj],
+        _ => vec![],
     }
-
-    obj.get("uri")?.as_str()
 }
 
 pub fn parse_quoted_words(line: &str) -> Option<(String, String)> {
@@ -496,3 +1260,184 @@ pub fn _log_warn<E: std::fmt::Debug>(ui: &DownloadBar, msg: &str, error: E) -> E
     ui.log_warn(&msg);
     error
 }
+
+/// Shows a desktop notification, or only logs it when built without the `notifications` feature.
+pub async fn notify(body: String) {
+    #[cfg(feature = "notifications")]
+    {
+        let result = tokio::task::spawn_blocking(move || {
+            notify_rust::Notification::new()
+                .appname(crate::APPNAME)
+                .summary("TaleCast")
+                .body(&body)
+                .show()
+                .map(|_| ())
+        })
+        .await;
+
+        // Headless systems have no notification server to talk to.
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::warn!("failed to show notification: {}", e),
+            Err(e) => log::warn!("failed to show notification: {}", e),
+        }
+    }
+
+    #[cfg(not(feature = "notifications"))]
+    log::info!("built without notification support: {}", body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn ui() -> DownloadBar {
+        let hidden =
+            indicatif::MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        DownloadBar::new("test".to_string(), Default::default(), &hidden, 4)
+    }
+
+    #[test]
+    fn str_to_bytes_rejects_sizes_under_a_byte() {
+        assert_eq!(str_to_bytes("500"), Ok(500));
+        assert_eq!(str_to_bytes("1KB"), Ok(1024));
+        assert_eq!(str_to_bytes("1.5 MB/s"), Ok(3 << 19));
+        assert_eq!(str_to_bytes("0.5KB"), Ok(512));
+
+        for invalid in ["0", "0.5", "0.0001KB", "-1", "abc", "1 TB"] {
+            assert!(str_to_bytes(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    /// Serves a single response with the given headers and body on a local port.
+    async fn serve_once(headers: &'static str, body: Vec<u8>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await.unwrap();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                headers,
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&body).await.unwrap();
+        });
+        format!("http://{}/feed.xml", addr)
+    }
+
+    const LATIN1_FEED: &str = r#"<rss version="2.0">
+  <channel>
+    <title>Café Crème</title>
+    <item>
+      <title>Épisode numéro un</title>
+      <guid>1</guid>
+      <enclosure url="https://example.com/1.mp3" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    /// Encodes text that only has characters from ISO-8859-1, which map one to one onto bytes.
+    fn latin1(text: &str) -> Vec<u8> {
+        text.chars().map(|c| u8::try_from(c).unwrap()).collect()
+    }
+
+    fn assert_latin1_titles(xml: &str) {
+        assert!(xml.contains("<title>Café Crème</title>"));
+        assert!(xml.contains("<title>Épisode numéro un</title>"));
+    }
+
+    #[test]
+    fn decode_xml_uses_header_charset() {
+        let feed = format!("<?xml version=\"1.0\"?>\n{}", LATIN1_FEED);
+        let xml = decode_xml(
+            latin1(&feed),
+            Some("application/rss+xml; charset=\"ISO-8859-1\""),
+        )
+        .unwrap();
+        assert_latin1_titles(&xml);
+        assert_eq!(
+            crate::podcast::Podcast::feed_title("https://example.com/header", &xml, &ui())
+                .as_deref(),
+            Some("Café Crème")
+        );
+    }
+
+    #[test]
+    fn decode_xml_uses_declared_encoding() {
+        let feed = format!(
+            "<?xml version=\"1.0\" encoding='iso-8859-1'?>\n{}",
+            LATIN1_FEED
+        );
+        let xml = decode_xml(latin1(&feed), Some("application/rss+xml")).unwrap();
+        assert_latin1_titles(&xml);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert_eq!(
+            crate::podcast::Podcast::feed_title("https://example.com/declared", &xml, &ui())
+                .as_deref(),
+            Some("Café Crème")
+        );
+    }
+
+    #[test]
+    fn decode_xml_prefers_bom() {
+        // The byte order mark wins over both the header and the declaration.
+        let feed = format!(
+            "\u{feff}<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\n{}",
+            LATIN1_FEED
+        );
+        let xml = decode_xml(
+            feed.into_bytes(),
+            Some("application/rss+xml; charset=ISO-8859-1"),
+        )
+        .unwrap();
+        assert_latin1_titles(&xml);
+        assert_eq!(
+            crate::podcast::Podcast::feed_title("https://example.com/bom", &xml, &ui()).as_deref(),
+            Some("Café Crème")
+        );
+    }
+
+    #[tokio::test]
+    async fn download_text_decompresses_gzip() {
+        utils::set_data_dir(std::env::temp_dir().join(format!("talecast-test-{}", process::id())));
+
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Gzipped Cast</title>
+    <item>
+      <title>First</title>
+      <guid>1</guid>
+      <enclosure url="https://example.com/1.mp3" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#;
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let url = serve_once(
+            "Content-Type: application/rss+xml\r\nContent-Encoding: gzip\r\n",
+            body,
+        )
+        .await;
+
+        let global_config = config::GlobalConfig::default();
+        let client = config::init_reqwest_client(&global_config);
+        let retry = RetryPolicy::new(&global_config);
+        let ui = ui();
+        let text = download_text(&url, client.get(&url), &retry, None, &ui)
+            .await
+            .unwrap();
+
+        assert_eq!(text, xml);
+        assert_eq!(
+            crate::podcast::Podcast::feed_title(&url, &text, &ui).as_deref(),
+            Some("Gzipped Cast")
+        );
+    }
+}
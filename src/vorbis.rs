@@ -0,0 +1,576 @@
+//! Vorbis comments, the tags of Ogg files with Opus or Vorbis audio.
+//!
+//! The comments are one of the header packets at the start of the stream, so writing them means
+//! repaginating the headers and renumbering the pages of audio after them.
+
+use base64::Engine;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Holds the cover art, as a base64 encoded FLAC picture block.
+const PICTURE: &str = "METADATA_BLOCK_PICTURE";
+
+/// The page header flag of a packet continued from the previous page.
+const CONTINUED: u8 = 0x01;
+/// The page header flag of the first page of a stream.
+const FIRST_PAGE: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Codec {
+    Opus,
+    Vorbis,
+}
+
+impl Codec {
+    fn from_id_header(packet: &[u8]) -> Option<Self> {
+        if packet.starts_with(b"OpusHead") {
+            Some(Self::Opus)
+        } else if packet.starts_with(b"\x01vorbis") {
+            Some(Self::Vorbis)
+        } else {
+            None
+        }
+    }
+
+    fn comment_magic(self) -> &'static [u8] {
+        match self {
+            Self::Opus => b"OpusTags",
+            Self::Vorbis => b"\x03vorbis",
+        }
+    }
+
+    /// How many packets come before the audio, the comments being the second one.
+    fn header_packets(self) -> usize {
+        match self {
+            Self::Opus => 2,
+            Self::Vorbis => 3,
+        }
+    }
+}
+
+/// The comments of the first stream in an Ogg file.
+#[derive(Debug, Clone)]
+pub struct VorbisComments {
+    vendor: String,
+    comments: Vec<(String, String)>,
+    /// Binary data Opus allows after the comments, which is kept as it is.
+    trailing: Vec<u8>,
+}
+
+impl VorbisComments {
+    pub fn read_from_path(path: &Path) -> Result<Self, String> {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let headers = Headers::read(&mut BufReader::new(file))?;
+        Self::parse(headers.codec, &headers.packets[1])
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Replaces the values of the key, keys being case insensitive.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        self.comments.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+        self.comments.push((key.to_uppercase(), value.into()));
+    }
+
+    pub fn has_picture(&self) -> bool {
+        self.get(PICTURE).is_some()
+    }
+
+    /// Sets the front cover, in the `METADATA_BLOCK_PICTURE` format of FLAC.
+    pub fn set_picture(&mut self, data: &[u8], mime: &str) {
+        const FRONT_COVER: u32 = 3;
+
+        let mut block = vec![];
+        block.extend(FRONT_COVER.to_be_bytes());
+        block.extend((mime.len() as u32).to_be_bytes());
+        block.extend(mime.as_bytes());
+        // The description, followed by the width, height, color depth and number of colors,
+        // which are all allowed to be left unknown.
+        block.extend([0u8; 20]);
+        block.extend((data.len() as u32).to_be_bytes());
+        block.extend(data);
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(block);
+        self.set(PICTURE, encoded);
+    }
+
+    /// Writes the comments into the file, replacing the ones it has.
+    pub fn write_to_path(&self, path: &Path) -> Result<(), String> {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut reader = BufReader::new(file);
+        let headers = Headers::read(&mut reader)?;
+
+        let mut packets = headers.packets;
+        packets[1] = self.serialize(headers.codec);
+
+        let tmp_path = path.with_extension("tagging");
+        let written = fs::File::create(&tmp_path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            let mut seq = headers.first_seq;
+            // The identification header has a page of its own, the other headers may share.
+            write_packets(
+                &mut writer,
+                headers.serial,
+                &mut seq,
+                &packets[..1],
+                FIRST_PAGE,
+            )?;
+            write_packets(&mut writer, headers.serial, &mut seq, &packets[1..], 0)?;
+
+            let shift = seq.wrapping_sub(headers.audio_seq);
+            if shift == 0 {
+                io::copy(&mut reader, &mut writer)?;
+            } else {
+                while let Some(mut page) = Page::read(&mut reader)? {
+                    if page.serial == headers.serial {
+                        page.seq = page.seq.wrapping_add(shift);
+                    }
+                    page.write(&mut writer)?;
+                }
+            }
+
+            writer.flush()
+        });
+
+        match written.and_then(|_| fs::rename(&tmp_path, path)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                Err(e.to_string())
+            }
+        }
+    }
+
+    fn parse(codec: Codec, packet: &[u8]) -> Result<Self, String> {
+        let invalid = || "invalid comment header".to_string();
+
+        let mut rest = packet
+            .strip_prefix(codec.comment_magic())
+            .ok_or_else(invalid)?;
+
+        // Each string is prefixed with its length.
+        fn take_string(rest: &mut &[u8]) -> Option<String> {
+            let (len, after) = rest.split_at_checked(4)?;
+            let len = u32::from_le_bytes(len.try_into().ok()?) as usize;
+            let (string, after) = after.split_at_checked(len)?;
+            *rest = after;
+            Some(String::from_utf8_lossy(string).to_string())
+        }
+
+        let vendor = take_string(&mut rest).ok_or_else(invalid)?;
+        let (count, after) = rest.split_at_checked(4).ok_or_else(invalid)?;
+        let count = u32::from_le_bytes(count.try_into().unwrap());
+        rest = after;
+
+        let mut comments = vec![];
+        for _ in 0..count {
+            let comment = take_string(&mut rest).ok_or_else(invalid)?;
+            if let Some((key, value)) = comment.split_once('=') {
+                comments.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        // Vorbis ends the header with a framing bit, which is added back when it's written.
+        let trailing = match codec {
+            Codec::Opus => rest.to_vec(),
+            Codec::Vorbis => vec![],
+        };
+
+        Ok(Self {
+            vendor,
+            comments,
+            trailing,
+        })
+    }
+
+    fn serialize(&self, codec: Codec) -> Vec<u8> {
+        let mut packet = codec.comment_magic().to_vec();
+        packet.extend((self.vendor.len() as u32).to_le_bytes());
+        packet.extend(self.vendor.as_bytes());
+        packet.extend((self.comments.len() as u32).to_le_bytes());
+        for (key, value) in &self.comments {
+            let comment = format!("{}={}", key, value);
+            packet.extend((comment.len() as u32).to_le_bytes());
+            packet.extend(comment.as_bytes());
+        }
+
+        match codec {
+            Codec::Opus => packet.extend(&self.trailing),
+            Codec::Vorbis => packet.push(1),
+        }
+
+        packet
+    }
+}
+
+/// The header packets at the start of a file, read up to the first page of audio.
+struct Headers {
+    codec: Codec,
+    serial: u32,
+    packets: Vec<Vec<u8>>,
+    /// The sequence number of the first page.
+    first_seq: u32,
+    /// The sequence number of the first page after the headers.
+    audio_seq: u32,
+}
+
+impl Headers {
+    fn read(reader: &mut impl Read) -> Result<Self, String> {
+        let mut codec = None;
+        let mut packets = vec![];
+        let mut packet = vec![];
+        let mut stream = None;
+
+        loop {
+            let page = Page::read(reader)
+                .map_err(|e| e.to_string())?
+                .ok_or("ogg file ended before its headers")?;
+
+            let (serial, first_seq) = *stream.get_or_insert((page.serial, page.seq));
+            if page.serial != serial {
+                return Err("multiplexed ogg streams aren't supported".to_string());
+            }
+
+            let mut offset = 0;
+            for &lacing in &page.lacing {
+                if codec.is_some_and(|codec: Codec| packets.len() == codec.header_packets()) {
+                    return Err("ogg headers don't end on a page boundary".to_string());
+                }
+
+                let len = lacing as usize;
+                packet.extend(&page.data[offset..offset + len]);
+                offset += len;
+                if lacing < 255 {
+                    if packets.is_empty() {
+                        codec =
+                            Some(Codec::from_id_header(&packet).ok_or("unsupported ogg codec")?);
+                    }
+                    packets.push(std::mem::take(&mut packet));
+                }
+            }
+
+            if let Some(codec) = codec.filter(|codec| packets.len() == codec.header_packets()) {
+                return Ok(Self {
+                    codec,
+                    serial,
+                    packets,
+                    first_seq,
+                    audio_seq: page.seq.wrapping_add(1),
+                });
+            }
+        }
+    }
+}
+
+struct Page {
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    seq: u32,
+    lacing: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Page {
+    /// Reads the next page, or nothing at the end of the file.
+    fn read(reader: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut header = [0u8; 27];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        if &header[..4] != b"OggS" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing ogg page header",
+            ));
+        }
+
+        let mut lacing = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut lacing)?;
+        let mut data = vec![0u8; lacing.iter().map(|&len| len as usize).sum()];
+        reader.read_exact(&mut data)?;
+
+        Ok(Some(Self {
+            header_type: header[5],
+            granule: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+            serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+            seq: u32::from_le_bytes(header[18..22].try_into().unwrap()),
+            lacing,
+            data,
+        }))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(self.header_type);
+        page.extend(self.granule.to_le_bytes());
+        page.extend(self.serial.to_le_bytes());
+        page.extend(self.seq.to_le_bytes());
+        page.extend([0u8; 4]);
+        page.push(self.lacing.len() as u8);
+        page.extend(&self.lacing);
+        page.extend(&self.data);
+
+        let crc = crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        writer.write_all(&page)
+    }
+}
+
+/// Splits the packets into as few pages as they fit in, numbering them from `seq`.
+fn write_packets(
+    writer: &mut impl Write,
+    serial: u32,
+    seq: &mut u32,
+    packets: &[Vec<u8>],
+    header_type: u8,
+) -> io::Result<()> {
+    // A packet is laced into segments of 255 bytes, ending with a shorter one.
+    let mut segments: Vec<(&[u8], bool)> = vec![];
+    for packet in packets {
+        let mut chunks = packet.chunks(255).peekable();
+        while let Some(chunk) = chunks.next() {
+            let last = chunks.peek().is_none();
+            segments.push((chunk, last && chunk.len() < 255));
+        }
+        if packet.len() % 255 == 0 {
+            segments.push((&[], true));
+        }
+    }
+
+    let mut continued = false;
+    for (index, page_segments) in segments.chunks(255).enumerate() {
+        let ends_packet = page_segments.iter().any(|(_, last)| *last);
+        let mut page = Page {
+            header_type: if continued { CONTINUED } else { 0 },
+            // Pages where no packet ends have no granule position.
+            granule: if ends_packet { 0 } else { u64::MAX },
+            serial,
+            seq: *seq,
+            lacing: page_segments
+                .iter()
+                .map(|(chunk, _)| chunk.len() as u8)
+                .collect(),
+            data: page_segments
+                .iter()
+                .flat_map(|(chunk, _)| *chunk)
+                .copied()
+                .collect(),
+        };
+        if index == 0 {
+            page.header_type |= header_type;
+        }
+
+        page.write(writer)?;
+        *seq = seq.wrapping_add(1);
+        continued = page_segments.last().is_some_and(|(_, last)| !last);
+    }
+
+    Ok(())
+}
+
+/// The CRC-32 of Ogg pages, which unlike the common one isn't reflected.
+fn crc(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = (i as u32) << 24;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = match crc & 0x8000_0000 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x04c1_1db7,
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    data.iter().fold(0, |crc, &byte| {
+        (crc << 8) ^ TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The flag of the last page of a stream.
+    const LAST_PAGE: u8 = 0x04;
+
+    const SERIAL: u32 = 0x1234_5678;
+
+    fn page(header_type: u8, granule: u64, seq: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut lacing = vec![];
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        let page = Page {
+            header_type,
+            granule,
+            serial: SERIAL,
+            seq,
+            lacing,
+            data: packets.concat(),
+        };
+        let mut bytes = vec![];
+        page.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Three pages of audio, numbered from `seq`.
+    fn audio_pages(seq: u32) -> Vec<u8> {
+        let mut bytes = page(0, 960, seq, &[&[0xAA; 40]]);
+        bytes.extend(page(0, 1920, seq + 1, &[&[0xBB; 300]]));
+        bytes.extend(page(LAST_PAGE, 2880, seq + 2, &[&[0xCC; 12]]));
+        bytes
+    }
+
+    fn opus_file() -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.extend([1, 2]);
+        head.extend(312u16.to_le_bytes());
+        head.extend(48000u32.to_le_bytes());
+        head.extend([0, 0, 0]);
+
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend(4u32.to_le_bytes());
+        tags.extend(b"test");
+        tags.extend(0u32.to_le_bytes());
+
+        let mut bytes = page(FIRST_PAGE, 0, 0, &[&head]);
+        bytes.extend(page(0, 0, 1, &[&tags]));
+        bytes.extend(audio_pages(2));
+        bytes
+    }
+
+    fn vorbis_file() -> Vec<u8> {
+        let mut id = b"\x01vorbis".to_vec();
+        id.extend(0u32.to_le_bytes());
+        id.push(2);
+        id.extend(44100u32.to_le_bytes());
+        id.extend([0; 12]);
+        id.extend([0xB8, 1]);
+
+        let mut comments = b"\x03vorbis".to_vec();
+        comments.extend(4u32.to_le_bytes());
+        comments.extend(b"test");
+        comments.extend(0u32.to_le_bytes());
+        comments.push(1);
+
+        let mut setup = b"\x05vorbis".to_vec();
+        setup.extend([0x42; 600]);
+
+        let mut bytes = page(FIRST_PAGE, 0, 0, &[&id]);
+        bytes.extend(page(0, 0, 1, &[&comments, &setup]));
+        bytes.extend(audio_pages(2));
+        bytes
+    }
+
+    /// Parses every page of the file, checking its CRC.
+    fn checked_pages(bytes: &[u8]) -> Vec<Page> {
+        let mut pages = vec![];
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let segments = rest[26] as usize;
+            let len = 27
+                + segments
+                + rest[27..27 + segments]
+                    .iter()
+                    .map(|&l| l as usize)
+                    .sum::<usize>();
+            let (raw, after) = rest.split_at(len);
+
+            let stored = u32::from_le_bytes(raw[22..26].try_into().unwrap());
+            let mut zeroed = raw.to_vec();
+            zeroed[22..26].fill(0);
+            assert_eq!(crc(&zeroed), stored, "invalid crc of page {}", pages.len());
+
+            pages.push(Page::read(&mut &*raw).unwrap().unwrap());
+            rest = after;
+        }
+        pages
+    }
+
+    fn round_trip(original: Vec<u8>, name: &str) -> Vec<Page> {
+        let path =
+            std::env::temp_dir().join(format!("talecast-test-{}-{}", std::process::id(), name));
+        fs::write(&path, &original).unwrap();
+        let headers_before = Headers::read(&mut &original[..]).unwrap();
+
+        let cover: Vec<u8> = (0..200_000u32).map(|n| (n % 251) as u8).collect();
+        let mut comments = VorbisComments::read_from_path(&path).unwrap();
+        comments.set("title", "Épisode one");
+        comments.set_picture(&cover, "image/png");
+        comments.write_to_path(&path).unwrap();
+
+        let comments = VorbisComments::read_from_path(&path).unwrap();
+        let written = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(comments.get("TITLE"), Some("Épisode one"));
+        assert!(comments.has_picture());
+        let mut expected =
+            VorbisComments::parse(headers_before.codec, &headers_before.packets[1]).unwrap();
+        expected.set_picture(&cover, "image/png");
+        assert_eq!(comments.get(PICTURE), expected.get(PICTURE));
+
+        // The other headers are kept as they were.
+        let headers = Headers::read(&mut &written[..]).unwrap();
+        assert_eq!(headers.codec, headers_before.codec);
+        assert_eq!(headers.packets.len(), headers_before.packets.len());
+        assert_eq!(headers.packets[0], headers_before.packets[0]);
+        assert_eq!(headers.packets[2..], headers_before.packets[2..]);
+
+        let pages = checked_pages(&written);
+        // The comments no longer fit in one page, which moves the audio pages back.
+        assert!(headers.audio_seq > 4);
+        for (seq, page) in pages.iter().enumerate() {
+            assert_eq!(page.seq, seq as u32);
+            assert_eq!(page.serial, SERIAL);
+        }
+        assert_eq!(pages[0].header_type, FIRST_PAGE);
+        assert_eq!(pages[2].header_type, CONTINUED);
+
+        let audio_before = checked_pages(&original).split_off(2);
+        let audio = &pages[pages.len() - 3..];
+        for (page, before) in audio.iter().zip(&audio_before) {
+            assert_eq!(page.header_type, before.header_type);
+            assert_eq!(page.granule, before.granule);
+            assert_eq!(page.data, before.data);
+        }
+        pages
+    }
+
+    #[test]
+    fn crc_matches_the_ogg_polynomial() {
+        // The CRC-32/CKSUM check value, which is the same CRC with its output inverted.
+        assert_eq!(crc(b"123456789") ^ 0xFFFF_FFFF, 0x765E_7680);
+    }
+
+    #[test]
+    fn opus_comments_round_trip() {
+        let pages = round_trip(opus_file(), "tags.opus");
+        assert_eq!(pages.last().unwrap().header_type, LAST_PAGE);
+    }
+
+    #[test]
+    fn vorbis_comments_round_trip() {
+        let pages = round_trip(vorbis_file(), "tags.ogg");
+        assert_eq!(pages.last().unwrap().header_type, LAST_PAGE);
+    }
+}